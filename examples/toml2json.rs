@@ -3,8 +3,7 @@ use std::fs::File;
 use std::io;
 use std::io::prelude::*;
 
-use serde_json::Value as Json;
-use toml::Value as Toml;
+use libkoompi::helpers::toml_to_json;
 
 fn main() {
     let mut args = env::args();
@@ -17,23 +16,9 @@ fn main() {
     }
     match input.parse() {
         Ok(toml) => {
-            let json = convert(toml);
+            let json = toml_to_json(toml);
             println!("{}", serde_json::to_string_pretty(&json).unwrap());
         }
         Err(error) => println!("failed to parse TOML {}", error),
     }
 }
-fn convert(toml: Toml) -> Json {
-    match toml {
-        Toml::String(s) => Json::String(s),
-        Toml::Integer(i) => Json::Number(i.into()),
-        Toml::Float(f) => {
-            let n = serde_json::Number::from_f64(f).expect("float infinite and nan not allowed");
-            Json::Number(n)
-        }
-        Toml::Boolean(b) => Json::Bool(b),
-        Toml::Array(arr) => Json::Array(arr.into_iter().map(convert).collect()),
-        Toml::Table(table) => Json::Object(table.into_iter().map(|(k, v)| (k, convert(v))).collect()),
-        Toml::Datetime(dt) => Json::String(dt.to_string()),
-    }
-}
@@ -0,0 +1,8 @@
+use libkoompi::system_settings::privileged::run_service;
+
+fn main() {
+   if let Err(err) = run_service() {
+      eprintln!("koompi-settings-helper: {}", err);
+      std::process::exit(1);
+   }
+}
@@ -7,6 +7,6 @@ pub mod errors;
 
 pub use desktop_item::DesktopItem;
 pub use background::WallpaperItem;
-pub use configs::PersistentData;
+pub use configs::{ConfigWatcher, PersistentData};
 pub use errors::DesktopError;
 pub use desktop_manager::DesktopManager;
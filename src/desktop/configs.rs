@@ -1,9 +1,11 @@
 pub mod background_conf;
 pub mod desktop_item_conf;
 pub mod wallpaper_conf;
+mod config_watcher;
 mod persistent_data;
 
-pub use persistent_data::PersistentData;
+pub use config_watcher::ConfigWatcher;
+pub use persistent_data::{ConfigSource, Migration, PersistentData};
 use background_conf::BackgroundConf;
 use desktop_item_conf::DesktopItemConf;
 use serde::{Deserialize, Serialize};
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, atomic::{AtomicBool, Ordering}, mpsc::{self, Receiver}};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use lazy_static::lazy_static;
+use crate::desktop::errors::DesktopError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long a changed file must stay unchanged before it is reloaded.
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+lazy_static! {
+    /// Hash of the content this process last saved to each config file.
+    static ref OWN_WRITES: Mutex<HashMap<PathBuf, u64>> = Mutex::new(HashMap::new());
+}
+
+/// Remember content saved by this process so watchers don't report it back.
+pub(super) fn record_own_write(path: &Path, contents: &str) {
+    OWN_WRITES.lock().unwrap().insert(path.to_path_buf(), content_hash(contents));
+}
+
+/// Handle of a config file watch, receiving the reloaded config whenever the file is changed by another process.
+/// Changes saved by this process are not reported. Watching stops when the handle is dropped.
+pub struct ConfigWatcher<T> {
+    path: PathBuf,
+    receiver: Receiver<T>,
+    running: Arc<AtomicBool>,
+}
+
+impl<T: Send + 'static> ConfigWatcher<T> {
+    pub(super) fn new(path: PathBuf, parse: fn(&str) -> Result<T, DesktopError>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let running = Arc::new(AtomicBool::new(true));
+        let thread_running = running.clone();
+        let thread_path = path.clone();
+        let mut last_stamp = file_stamp(&path);
+        let mut last_hash = fs::read_to_string(&path).ok().map(|contents| content_hash(&contents));
+
+        thread::spawn(move || {
+            let path = thread_path;
+            while thread_running.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                let mut stamp = file_stamp(&path);
+                if stamp == last_stamp {
+                    continue;
+                }
+                // wait for writers to finish before reading
+                loop {
+                    thread::sleep(DEBOUNCE);
+                    let next = file_stamp(&path);
+                    if next == stamp || !thread_running.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    stamp = next;
+                }
+                last_stamp = stamp;
+
+                let contents = match fs::read_to_string(&path) {
+                    Ok(contents) => contents,
+                    Err(_) => continue,
+                };
+                let hash = content_hash(&contents);
+                let own_write = OWN_WRITES.lock().unwrap().get(&path) == Some(&hash);
+                if last_hash.replace(hash) == Some(hash) || own_write {
+                    continue;
+                }
+
+                match parse(&contents) {
+                    Ok(conf) => {
+                        if sender.send(conf).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => log::warn!("ignoring invalid config {}: {}", path.display(), err),
+                }
+            }
+        });
+
+        Self { path, receiver, running }
+    }
+}
+
+impl<T> ConfigWatcher<T> {
+    /// Return the watched config file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Block until the config changes and return the reloaded value.
+    pub fn recv(&self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+
+    /// Return the latest reloaded value if the config changed since the last call, without blocking.
+    pub fn try_recv(&self) -> Option<T> {
+        self.receiver.try_iter().last()
+    }
+
+    /// Wait up to `timeout` for the config to change.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl<T> Drop for ConfigWatcher<T> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Identity and change times of a file. The inode tells a replacing rename apart, even one that keeps the
+/// mtime of the new file, and the ctime also moves on renames and permission changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+    ino: u64,
+    ctime: (i64, i64),
+}
+
+fn file_stamp(path: &Path) -> Option<FileStamp> {
+    let meta = fs::metadata(path).ok()?;
+    Some(FileStamp { modified: meta.modified().ok()?, len: meta.len(), ino: meta.ino(), ctime: (meta.ctime(), meta.ctime_nsec()) })
+}
+
+fn content_hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{record_own_write, ConfigWatcher};
+    use std::collections::HashMap;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_config_watcher() {
+        let dir = std::env::temp_dir().join(format!("libkoompi-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("watched.toml");
+        fs::write(&path, "value = 1\n").unwrap();
+
+        let watcher: ConfigWatcher<HashMap<String, i64>> = ConfigWatcher::new(path.clone(), |contents| Ok(toml::from_str(contents)?));
+        // a different length is noticed even within one timestamp tick
+        fs::write(&path, "value = 20\n").unwrap();
+        assert_eq!(watcher.recv_timeout(Duration::from_secs(5)).and_then(|conf| conf.get("value").copied()), Some(20));
+
+        record_own_write(&path, "value = 3\n");
+        fs::write(&path, "value = 3\n").unwrap();
+        assert_eq!(watcher.recv_timeout(Duration::from_secs(2)), None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,12 +1,41 @@
 use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fs;
-use std::path::PathBuf;
-use crate::helpers::constants::LOCAL_CONF;
+use std::path::{Path, PathBuf};
+use toml::{value::Table, Value};
+use crate::helpers::{constants::{CONF_DIRS, DATA_DIRS, LOCAL_CONF}, write_content_atomic, Locked, Lockdown};
 use crate::desktop::errors::DesktopError;
+use super::config_watcher::{record_own_write, ConfigWatcher};
+
+const VERSION_KEY: &str = "version";
+const BACKUP_EXT: &str = "bak";
+/// Directory holding vendor defaults under `DATA_DIRS` and admin overrides under `CONF_DIRS`.
+const SYSTEM_CONF_DIR: &str = "koompi";
+
+/// Function upgrading a raw config table by one schema version.
+pub type Migration = fn(&mut Table);
+
+/// Variants of the layer a config value comes from, from lowest to highest precedence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    Vendor(PathBuf),
+    Admin(PathBuf),
+    User(PathBuf),
+}
 
 pub trait PersistentData: DeserializeOwned + Serialize + Default {
+    /// Schema version written to the `version` key. Bump it together with a new migration.
+    const VERSION: u32 = 1;
+
     fn relative_path() -> PathBuf;
 
+    /// Migrations as (version upgraded from, function), applied in order to older files on load.
+    fn migrations() -> Vec<(u32, Migration)> {
+        Vec::new()
+    }
+
     fn path() -> Result<PathBuf, DesktopError> {
         let path = LOCAL_CONF.join(Self::relative_path());
         if let Some(dir) = path.parent() {
@@ -16,27 +45,323 @@ pub trait PersistentData: DeserializeOwned + Serialize + Default {
         Ok(path)
     }
 
+    /// Vendor default and admin override files of this config that exist, from lowest to highest precedence.
+    fn system_paths() -> Vec<ConfigSource> {
+        let layer = |dirs: &Vec<PathBuf>| dirs.iter().rev().map(|dir| dir.join(SYSTEM_CONF_DIR).join(Self::relative_path())).filter(|path| path.is_file()).collect::<Vec<PathBuf>>();
+        layer(&DATA_DIRS).into_iter().map(ConfigSource::Vendor).chain(layer(&CONF_DIRS).into_iter().map(ConfigSource::Admin)).collect()
+    }
+
+    /// Load the config, merging user values over admin overrides, vendor defaults and the built-in default key by key.
+    /// User values of keys locked by the administrator are ignored. A user file that can't be parsed falls back to its backup, then to the system layers alone.
+    ///
+    /// A layer can only add or replace values, not remove them: a map entry or an optional value provided by a vendor or admin layer
+    /// comes back on load even after the user removed it. Configs needing that should store an explicit value, e.g. `enabled = false`.
     fn load() -> Result<Self, DesktopError> {
         let file = Self::path()?;
+        let system = system_table::<Self>().0;
 
-        if file.exists() { 
+        if file.exists() {
             if file.is_file() {
-                Ok(toml::from_str(&fs::read_to_string(file)?)?)
+                for path in vec![file.clone(), backup_path(&file)].into_iter().filter(|path| path.exists()) {
+                    match merge_config(system.clone(), &fs::read_to_string(&path)?) {
+                        Ok(conf) => return Ok(conf),
+                        Err(err) => log::warn!("invalid config {}: {}", path.display(), err),
+                    }
+                }
             } else {
-                Err(DesktopError::ConfigNotFound(file.display().to_string()))
+                return Err(DesktopError::ConfigNotFound(file.display().to_string()));
             }
-        } else {
-            let default = Self::default();
-            let toml = toml::to_string_pretty(&default)?;
-            fs::write(Self::path()?, toml)?;
-            Ok(default)
-        } 
+        }
+        Ok(Value::Table(system).try_into().unwrap_or_default())
     }
 
+    /// Return the layer each value comes from, keyed by dotted key path.
+    fn provenance() -> Result<HashMap<String, ConfigSource>, DesktopError> {
+        let file = Self::path()?;
+        let mut provenance = system_table::<Self>().1;
+        if let Some(mut table) = fs::read_to_string(&file).ok().and_then(|contents| read_table::<Self>(&contents).ok()) {
+            Lockdown::load().locked_keys(Self::relative_path()).iter().for_each(|key| remove_key(&mut table, key));
+            record_keys(&table, "", &ConfigSource::User(file), &mut provenance);
+        }
+        provenance.remove(VERSION_KEY);
+        Ok(provenance)
+    }
+
+    /// Save the values differing from the system layers atomically, keeping keys unknown to this build and a backup of the previous file.
+    /// Changing a key locked by the administrator fails with `DesktopError::Locked`.
     fn save(&self) -> Result<(), DesktopError> {
-        let contents = toml::to_string_pretty(&self)?;
-        fs::write(Self::path()?, contents)?;
+        let file = Self::path()?;
+        let mut table = match fs::read_to_string(&file).ok().and_then(|contents| read_table::<Self>(&contents).ok()) {
+            Some(table) => {
+                fs::copy(&file, backup_path(&file))?;
+                table
+            }
+            None => Table::new(),
+        };
+        let system = system_table::<Self>().0;
+        if let Value::Table(current) = Value::try_from(self)? {
+            let locked_keys = Lockdown::load().locked_keys(Self::relative_path()).to_vec();
+            if let Some(key) = locked_keys.iter().find(|key| get_key(&current, key) != get_key(&system, key)) {
+                return Err(Locked(format!("{}: {}", Self::relative_path().display(), key)).into());
+            }
+            table = carry_unknown_keys::<Self>(table, current);
+            locked_keys.iter().for_each(|key| remove_key(&mut table, key));
+        }
+        strip_table(&mut table, &system);
+        let version = table.get(VERSION_KEY).and_then(Value::as_integer).and_then(|version| u32::try_from(version).ok()).map_or(Self::VERSION, |version| version.max(Self::VERSION));
+        table.insert(VERSION_KEY.to_string(), Value::Integer(i64::from(version)));
+
+        let contents = toml::to_string_pretty(&Value::Table(table))?;
+        record_own_write(&file, &contents);
+        write_content_atomic(file, &contents)?;
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    /// Watch the config file, receiving a reloaded value whenever another process changes it.
+    fn watch() -> Result<ConfigWatcher<Self>, DesktopError> where Self: Send + 'static {
+        Ok(ConfigWatcher::new(Self::path()?, parse_config::<Self>))
+    }
+}
+
+fn backup_path(file: &Path) -> PathBuf {
+    let mut backup = file.as_os_str().to_owned();
+    backup.push(".");
+    backup.push(BACKUP_EXT);
+    PathBuf::from(backup)
+}
+
+/// Parse a user config file and merge it over the system layers.
+pub(super) fn parse_config<T: PersistentData>(contents: &str) -> Result<T, DesktopError> {
+    merge_config(system_table::<T>().0, contents)
+}
+
+fn merge_config<T: PersistentData>(mut system: Table, contents: &str) -> Result<T, DesktopError> {
+    let mut user = read_table::<T>(contents)?;
+    Lockdown::load().locked_keys(T::relative_path()).iter().for_each(|key| remove_key(&mut user, key));
+    merge_table(&mut system, user);
+    Ok(Value::Table(system).try_into()?)
+}
+
+/// Merge the built-in default, vendor and admin layers, recording the layer of each value.
+/// Layers that can't be parsed are skipped.
+fn system_table<T: PersistentData>() -> (Table, HashMap<String, ConfigSource>) {
+    let mut table = match Value::try_from(T::default()) {
+        Ok(Value::Table(table)) => table,
+        _ => Table::new(),
+    };
+    let mut provenance = HashMap::new();
+    record_keys(&table, "", &ConfigSource::Default, &mut provenance);
+
+    T::system_paths().into_iter().for_each(|source| {
+        let path = match &source {
+            ConfigSource::Vendor(path) | ConfigSource::Admin(path) | ConfigSource::User(path) => path,
+            ConfigSource::Default => return,
+        };
+        match fs::read_to_string(path).map_err(DesktopError::from).and_then(|contents| read_table::<T>(&contents)) {
+            Ok(layer) => {
+                record_keys(&layer, "", &source, &mut provenance);
+                merge_table(&mut table, layer);
+            }
+            Err(err) => log::warn!("skipping config layer {}: {}", path.display(), err),
+        }
+    });
+    (table, provenance)
+}
+
+/// Parse a config file into a table migrated to the current schema version.
+/// A version that isn't a number from 1 fails with `DesktopError::InvalidValue`.
+fn read_table<T: PersistentData>(contents: &str) -> Result<Table, DesktopError> {
+    let mut table: Table = toml::from_str(contents)?;
+    let mut version = match table.get(VERSION_KEY) {
+        Some(val) => val.as_integer().and_then(|version| u32::try_from(version).ok()).filter(|version| *version >= 1).ok_or_else(|| DesktopError::InvalidValue(format!("config version {}", val)))?,
+        // files written before versioning hold the first schema
+        None => 1,
+    };
+    let migrations = T::migrations();
+    while version < T::VERSION {
+        migrations.iter().filter(|(from, _)| *from == version).for_each(|(_, migration)| migration(&mut table));
+        version += 1;
+    }
+    table.insert(VERSION_KEY.to_string(), Value::Integer(i64::from(version)));
+    Ok(table)
+}
+
+/// Merge `current` into `base` key by key, keeping keys only present in `base`, so `current` can't remove them.
+fn merge_table(base: &mut Table, current: Table) {
+    current.into_iter().for_each(|(key, val)| match (base.get_mut(&key), val) {
+        (Some(Value::Table(base_table)), Value::Table(table)) => merge_table(base_table, table),
+        (_, val) => {
+            base.insert(key, val);
+        }
+    });
+}
+
+/// Return `current` with the top-level keys of the saved `file` that `T` doesn't know, e.g. ones written by a newer version.
+/// Known keys are replaced wholesale, so keys `current` leaves out, like a `None` field or a removed map entry, are dropped.
+/// A key is known if it survives a round trip through `T`.
+fn carry_unknown_keys<T: PersistentData>(file: Table, mut current: Table) -> Table {
+    file.into_iter().for_each(|(key, val)| {
+        if current.contains_key(&key) {
+            return;
+        }
+        let mut probe = current.clone();
+        probe.insert(key.clone(), val.clone());
+        let known = match Value::Table(probe).try_into::<T>() {
+            Ok(conf) => matches!(Value::try_from(conf), Ok(Value::Table(table)) if table.contains_key(&key)),
+            // a known key holding a value of an older schema
+            Err(_) => true,
+        };
+        if !known {
+            current.insert(key, val);
+        }
+    });
+    current
+}
+
+/// Remove values of `table` equal to the ones in `system`, dropping tables left empty.
+/// Keys only present in `system` are left for the system layer to provide again.
+fn strip_table(table: &mut Table, system: &Table) {
+    let keys: Vec<String> = table.keys().cloned().collect();
+    keys.into_iter().for_each(|key| {
+        let keep = match (table.get_mut(&key), system.get(&key)) {
+            (Some(Value::Table(sub_table)), Some(Value::Table(sub_system))) => {
+                strip_table(sub_table, sub_system);
+                !sub_table.is_empty()
+            }
+            (val, system_val) => val.as_deref() != system_val,
+        };
+        if !keep {
+            table.remove(&key);
+        }
+    });
+}
+
+fn get_key<'a>(table: &'a Table, key: &str) -> Option<&'a Value> {
+    let mut parts = key.split('.');
+    let first = table.get(parts.next()?);
+    parts.try_fold(first?, |val, part| val.get(part))
+}
+
+fn remove_key(table: &mut Table, key: &str) {
+    match key.split_once('.') {
+        Some((parent, rest)) => {
+            if let Some(Value::Table(sub_table)) = table.get_mut(parent) {
+                remove_key(sub_table, rest);
+            }
+        }
+        None => {
+            table.remove(key);
+        }
+    }
+}
+
+fn record_keys(table: &Table, prefix: &str, source: &ConfigSource, provenance: &mut HashMap<String, ConfigSource>) {
+    table.iter().for_each(|(key, val)| {
+        let key = if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
+        match val {
+            Value::Table(sub_table) => record_keys(sub_table, &key, source, provenance),
+            _ => {
+                provenance.insert(key, source.clone());
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{carry_unknown_keys, merge_config, merge_table, parse_config, strip_table, Migration, PersistentData};
+    use crate::desktop::errors::DesktopError;
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+    use toml::{value::Table, Value};
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct TestConf {
+        size: i64,
+    }
+
+    impl PersistentData for TestConf {
+        const VERSION: u32 = 2;
+
+        fn relative_path() -> PathBuf {
+            PathBuf::from("test.toml")
+        }
+
+        fn migrations() -> Vec<(u32, Migration)> {
+            vec![(1, |table| {
+                if let Some(size) = table.remove("icon_size") {
+                    table.insert(String::from("size"), size);
+                }
+            })]
+        }
+    }
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct OptionalConf {
+        name: Option<String>,
+        sizes: BTreeMap<String, i64>,
+    }
+
+    impl PersistentData for OptionalConf {
+        fn relative_path() -> PathBuf {
+            PathBuf::from("optional.toml")
+        }
+    }
+
+    /// Save `conf` over the `file` previously saved, as `save` does without the system layers.
+    fn save_over<T: PersistentData>(file: &str, conf: &T) -> String {
+        let current = match Value::try_from(conf).unwrap() {
+            Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+        toml::to_string(&Value::Table(carry_unknown_keys::<T>(toml::from_str(file).unwrap(), current))).unwrap()
+    }
+
+    #[test]
+    fn test_save_removed_keys() {
+        let mut conf = OptionalConf { name: Some(String::from("a")), sizes: vec![(String::from("icon"), 48)].into_iter().collect() };
+        let saved = save_over("newer = true\n", &conf);
+        conf.name = None;
+        conf.sizes.clear();
+        let saved = save_over(&saved, &conf);
+        assert_eq!(parse_config::<OptionalConf>(&saved).unwrap(), OptionalConf::default());
+        assert_eq!(toml::from_str::<Value>(&saved).unwrap(), toml::from_str::<Value>("newer = true\n[sizes]\n").unwrap());
+    }
+
+    #[test]
+    fn test_system_values_not_removable() {
+        let system: Table = toml::from_str("name = \"vendor\"\n[sizes]\nicon = 48\n").unwrap();
+        let mut user = match Value::try_from(OptionalConf { name: None, sizes: vec![(String::from("panel"), 32)].into_iter().collect() }).unwrap() {
+            Value::Table(table) => table,
+            _ => unreachable!(),
+        };
+        strip_table(&mut user, &system);
+        assert_eq!(Value::Table(user.clone()), toml::from_str::<Value>("[sizes]\npanel = 32\n").unwrap());
+
+        // the removed name and icon size come back from the vendor layer
+        let conf: OptionalConf = merge_config(system, &toml::to_string(&user).unwrap()).unwrap();
+        let sizes = vec![(String::from("icon"), 48), (String::from("panel"), 32)].into_iter().collect();
+        assert_eq!(conf, OptionalConf { name: Some(String::from("vendor")), sizes });
+    }
+
+    #[test]
+    fn test_migrate_merge_and_strip() {
+        assert_eq!(parse_config::<TestConf>("icon_size = 48\n").unwrap(), TestConf { size: 48 });
+        assert_eq!(parse_config::<TestConf>("version = 2\nsize = 32\n").unwrap(), TestConf { size: 32 });
+        assert!(parse_config::<TestConf>("size = \"large\"\n").is_err());
+        for version in ["0", "-1", "4294967296", "\"2\""].iter() {
+            assert!(matches!(parse_config::<TestConf>(&format!("version = {}\nsize = 32\n", version)), Err(DesktopError::InvalidValue(_))));
+        }
+
+        let mut base: Table = toml::from_str("size = 1\nnewer = true\n[grid]\ncolumns = 4\nrows = 3\n").unwrap();
+        let current: Table = toml::from_str("size = 2\n[grid]\ncolumns = 5\n").unwrap();
+        merge_table(&mut base, current);
+        assert_eq!(Value::Table(base.clone()), toml::from_str::<Value>("size = 2\nnewer = true\n[grid]\ncolumns = 5\nrows = 3\n").unwrap());
+
+        let system: Table = toml::from_str("size = 2\n[grid]\ncolumns = 5\nrows = 4\n").unwrap();
+        strip_table(&mut base, &system);
+        assert_eq!(Value::Table(base), toml::from_str::<Value>("newer = true\n[grid]\nrows = 3\n").unwrap());
+    }
+}
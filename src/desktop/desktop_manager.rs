@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use std::fs;
 use super::constants::{DESKTOP_ENTRY, ICON};
 use super::configs::{PersistentData, desktop_item_conf::Sorting};
-use crate::helpers::{Resources, constants::{DATA_DIRS, LOCAL_DATA}};
+use crate::helpers::{Resources, constants::{DATA_DIRS, LOCAL_DATA}, lockdown::{check_action, ACTION_ADD_WALLPAPER}};
 use super::desktop_item::{DesktopItem, DesktopItemType};
 use super::background::WallpaperItem;
 use super::configs::DesktopConf;
@@ -88,6 +88,7 @@ impl DesktopManager {
     }
 
     pub fn add_wallpaper<P: AsRef<Path>>(&mut self, path: P) -> Result<(DesktopConf, Vec<WallpaperItem>), DesktopError> {
+        check_action(ACTION_ADD_WALLPAPER)?;
         let mut res = false;
         if path.as_ref().exists() && path.as_ref().is_file() {
             if let Some(ext) = path.as_ref().extension() {
@@ -1,6 +1,7 @@
 use thiserror::Error;
 use super::desktop_item::DesktopItemError;
 use super::background::WallpaperError;
+use crate::helpers::Locked;
 
 #[derive(Error, Debug)]
 pub enum DesktopError {
@@ -8,6 +9,8 @@ pub enum DesktopError {
     ConfigNotFound(String),
     #[error("path is not exists or a file: {0}")]
     PathIsNotAFile(String),
+    #[error("invalid value: {0}")]
+    InvalidValue(String),
     #[error(transparent)]
     ParseConfigError(#[from] toml::de::Error),
     #[error(transparent)]
@@ -18,4 +21,6 @@ pub enum DesktopError {
     WallpaperError(#[from] WallpaperError),
     #[error(transparent)]
     IOError(#[from] std::io::Error),
+    #[error(transparent)]
+    Locked(#[from] Locked),
 }   
\ No newline at end of file
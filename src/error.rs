@@ -0,0 +1,79 @@
+use thiserror::Error;
+use crate::desktop::DesktopError;
+use crate::helpers::{constants::PKEXEC, Locked};
+
+/// Exit code of pkexec when the user dismissed the authentication dialog.
+const PKEXEC_CANCELLED: i32 = 126;
+/// Exit code of pkexec when the caller isn't authorized or authentication failed.
+const PKEXEC_DENIED: i32 = 127;
+
+/// Errors of the system settings managers
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("authorization denied")]
+    AuthorizationDenied,
+    #[error("authorization cancelled")]
+    AuthorizationCancelled,
+    #[error("required tool is not installed: {0}")]
+    MissingTool(String),
+    #[error("{cmd} failed with exit code {}: {stderr}", code.map_or_else(|| String::from("none"), |code| code.to_string()))]
+    CommandFailed {
+        cmd: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    #[error("{cmd} timed out after {timeout:?}")]
+    Timeout {
+        cmd: String,
+        timeout: std::time::Duration,
+    },
+    #[error("invalid value: {0}")]
+    Validation(String),
+    #[error("failed to parse {0}")]
+    Parse(String),
+    #[error(transparent)]
+    DBus(#[from] dbus::Error),
+    #[error(transparent)]
+    Locked(#[from] Locked),
+    #[error(transparent)]
+    Desktop(#[from] DesktopError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    /// Classify a command that exited unsuccessfully, telling pkexec authorization failures apart.
+    pub fn from_exit_status(cmd: &str, code: Option<i32>, stderr: &str) -> Self {
+        match (cmd, code) {
+            (PKEXEC, Some(PKEXEC_CANCELLED)) => Error::AuthorizationCancelled,
+            (PKEXEC, Some(PKEXEC_DENIED)) => Error::AuthorizationDenied,
+            _ => Error::CommandFailed {
+                cmd: cmd.to_string(),
+                code,
+                stderr: stderr.trim().to_string(),
+            },
+        }
+    }
+
+    /// Classify a command that failed to start, telling a missing program apart.
+    pub fn from_spawn(cmd: &str, err: std::io::Error) -> Self {
+        if err.kind() == std::io::ErrorKind::NotFound {
+            Error::MissingTool(cmd.to_string())
+        } else {
+            Error::Io(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+
+    #[test]
+    fn test_exit_status() {
+        assert!(matches!(Error::from_exit_status("pkexec", Some(126), ""), Error::AuthorizationCancelled));
+        assert!(matches!(Error::from_exit_status("pkexec", Some(127), ""), Error::AuthorizationDenied));
+        assert!(matches!(Error::from_exit_status("useradd", Some(9), "useradd: user 'koompi' already exists\n"), Error::CommandFailed { code: Some(9), ref stderr, .. } if stderr == "useradd: user 'koompi' already exists"));
+        assert!(matches!(Error::from_spawn("timedatectl", std::io::Error::from(std::io::ErrorKind::NotFound)), Error::MissingTool(_)));
+    }
+}
@@ -1,16 +1,29 @@
+mod async_runtime;
 mod cmd_output_helper;
+mod command_runner;
 pub mod constants;
 mod dbus_helper;
 mod device_file;
 mod file_helper;
 mod format_helper;
+pub mod lockdown;
+mod property_cache;
 mod resources;
+mod system_bus;
 mod config;
 
 pub use resources::Resources;
 pub use config::Config;
+pub use async_runtime::{block_on, unblock};
+pub(crate) use async_runtime::RUNTIME;
 pub use cmd_output_helper::*;
+pub use command_runner::*;
 pub use dbus_helper::*;
 pub use device_file::*;
 pub use file_helper::*;
 pub use format_helper::*;
+pub use lockdown::{Locked, Lockdown};
+pub use property_cache::{required_prop, FromProperties, PropertyCache};
+pub use system_bus::{SystemBus, INTERACTIVE_TIMEOUT};
+#[cfg(any(test, feature = "test-support"))]
+pub(crate) use system_bus::reset_shared;
@@ -0,0 +1,46 @@
+use std::future::Future;
+use tokio::runtime::{Builder, Runtime};
+
+lazy_static::lazy_static! {
+   /// Background runtime driving the shared bus connection and the timers of blocking calls.
+   pub(crate) static ref RUNTIME: Runtime = Builder::new_multi_thread()
+      .worker_threads(1)
+      .thread_name("libkoompi-bus")
+      .enable_all()
+      .build()
+      .expect("failed to start the libkoompi runtime");
+}
+
+/// This function is used to run a future of the async managers to completion from blocking code.
+/// It may be called from inside another runtime, but then blocks that runtime's thread.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+   let _guard = RUNTIME.enter();
+   futures::executor::block_on(fut)
+}
+
+/// This function is used to run blocking work (commands, file I/O) off the async executor.
+pub async fn unblock<F, R>(f: F) -> R
+where
+   F: FnOnce() -> R + Send + 'static,
+   R: Send + 'static,
+{
+   match RUNTIME.spawn_blocking(f).await {
+      Ok(res) => res,
+      Err(err) => std::panic::resume_unwind(err.into_panic()),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{block_on, unblock};
+   use std::time::Duration;
+
+   #[test]
+   fn test_block_on() {
+      let res = block_on(async {
+         tokio::time::sleep(Duration::from_millis(10)).await;
+         unblock(|| 6 * 7).await
+      });
+      assert_eq!(res, 42);
+   }
+}
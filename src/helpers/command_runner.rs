@@ -0,0 +1,317 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Display, Formatter};
+use std::io::{self, ErrorKind, Read, Write};
+use std::ops::Deref;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::error::Error;
+use super::constants::PKEXEC;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Commands run through pkexec wait for the user to authenticate.
+const DEFAULT_AUTH_TIMEOUT: Duration = Duration::from_secs(300);
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Structure of a command to run, with optional stdin, environment and timeout
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandLine {
+   program: String,
+   args: Vec<String>,
+   stdin: Option<String>,
+   env: Vec<(String, String)>,
+   inherit_locale: bool,
+   timeout: Option<Duration>,
+}
+
+impl CommandLine {
+   pub fn new<S: AsRef<str>>(program: &str, args: &[S]) -> Self {
+      Self {
+         program: program.to_string(),
+         args: args.iter().map(|arg| arg.as_ref().to_string()).collect(),
+         ..Self::default()
+      }
+   }
+
+   /// Write `stdin` to the command's standard input.
+   pub fn stdin<T: Into<String>>(mut self, stdin: T) -> Self {
+      self.stdin = Some(stdin.into());
+      self
+   }
+
+   /// Set an environment variable for the command.
+   pub fn env<K: Into<String>, V: Into<String>>(mut self, key: K, val: V) -> Self {
+      self.env.push((key.into(), val.into()));
+      self
+   }
+
+   /// Keep the caller's locale instead of forcing `LC_ALL=C`, for commands reporting locale settings.
+   pub fn inherit_locale(mut self) -> Self {
+      self.inherit_locale = true;
+      self
+   }
+
+   /// Override the runner's timeout for this command.
+   pub fn timeout(mut self, timeout: Duration) -> Self {
+      self.timeout = Some(timeout);
+      self
+   }
+
+   /// Run the command as root through pkexec.
+   pub fn pkexec(mut self) -> Self {
+      self.args.insert(0, std::mem::replace(&mut self.program, PKEXEC.to_string()));
+      self
+   }
+
+   pub fn program(&self) -> &str {
+      &self.program
+   }
+
+   pub fn args(&self) -> &[String] {
+      &self.args
+   }
+}
+
+impl Display for CommandLine {
+   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      write!(f, "{}", std::iter::once(&self.program).chain(self.args.iter()).map(String::as_str).collect::<Vec<&str>>().join(" "))
+   }
+}
+
+/// Structure of a finished command's exit code and output
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+   pub code: Option<i32>,
+   pub stdout: String,
+   pub stderr: String,
+}
+
+impl CommandOutput {
+   /// Output of a command exiting successfully.
+   pub fn ok<T: Into<String>>(stdout: T) -> Self {
+      Self { code: Some(0), stdout: stdout.into(), stderr: String::new() }
+   }
+
+   /// Output of a command exiting with `code`.
+   pub fn failed<T: Into<String>>(code: i32, stderr: T) -> Self {
+      Self { code: Some(code), stdout: String::new(), stderr: stderr.into() }
+   }
+
+   pub fn success(&self) -> bool {
+      self.code == Some(0)
+   }
+}
+
+/// Backend running external commands for the managers
+pub trait CommandRunner: Send + Sync {
+   /// Run a command to completion, whatever its exit code.
+   fn run(&self, cmd: &CommandLine) -> Result<CommandOutput, Error>;
+
+   /// Run a command and return its trimmed stdout, failing if it exits unsuccessfully.
+   fn exec(&self, cmd: &CommandLine) -> Result<String, Error> {
+      let output = self.run(cmd)?;
+      if output.success() {
+         Ok(output.stdout.trim().to_string())
+      } else {
+         Err(Error::from_exit_status(cmd.program(), output.code, &output.stderr))
+      }
+   }
+}
+
+/// Runner of real processes. Commands run with `LC_ALL=C` so their output parses the same on every system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemRunner {
+   timeout: Duration,
+   auth_timeout: Duration,
+}
+
+impl Default for SystemRunner {
+   fn default() -> Self {
+      Self::new(DEFAULT_TIMEOUT, DEFAULT_AUTH_TIMEOUT)
+   }
+}
+
+impl SystemRunner {
+   /// Create a runner killing commands after `timeout`, or after `auth_timeout` for commands run through pkexec.
+   pub fn new(timeout: Duration, auth_timeout: Duration) -> Self {
+      Self { timeout, auth_timeout }
+   }
+}
+
+impl CommandRunner for SystemRunner {
+   fn run(&self, cmd: &CommandLine) -> Result<CommandOutput, Error> {
+      let mut command = Command::new(&cmd.program);
+      command.args(&cmd.args).stdout(Stdio::piped()).stderr(Stdio::piped());
+      command.stdin(if cmd.stdin.is_some() { Stdio::piped() } else { Stdio::null() });
+      if !cmd.inherit_locale {
+         command.env("LC_ALL", "C");
+      }
+      command.envs(cmd.env.iter().map(|(key, val)| (key, val)));
+      let mut child = command.spawn().map_err(|err| Error::from_spawn(&cmd.program, err))?;
+
+      // the pipes are served on their own threads, so a command filling stdout before reading stdin can't block the timeout
+      let stdout = read_pipe(child.stdout.take());
+      let stderr = read_pipe(child.stderr.take());
+      let stdin = write_pipe(child.stdin.take(), cmd.stdin.clone());
+
+      let timeout = cmd.timeout.unwrap_or(if cmd.program == PKEXEC { self.auth_timeout } else { self.timeout });
+      let status = match wait_timeout(&mut child, timeout) {
+         Ok(Some(status)) => status,
+         result => {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(match result {
+               Err(err) => err.into(),
+               _ => Error::Timeout { cmd: cmd.program.clone(), timeout },
+            });
+         }
+      };
+
+      // a command exiting without reading all its input closes the pipe, and its exit code tells what happened
+      match stdin.join() {
+         Ok(Err(err)) if err.kind() != ErrorKind::BrokenPipe => return Err(err.into()),
+         _ => {}
+      }
+      Ok(CommandOutput {
+         code: status.code(),
+         stdout: stdout.join().unwrap_or_default(),
+         stderr: stderr.join().unwrap_or_default(),
+      })
+   }
+}
+
+/// Wait for a child to exit, returning `None` once `timeout` has passed.
+fn wait_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+   let start = Instant::now();
+   loop {
+      if let Some(status) = child.try_wait()? {
+         return Ok(Some(status));
+      }
+      if start.elapsed() >= timeout {
+         return Ok(None);
+      }
+      thread::sleep(POLL_INTERVAL);
+   }
+}
+
+/// Write the input of a command on its own thread, closing the pipe once done.
+fn write_pipe<W: Write + Send + 'static>(pipe: Option<W>, input: Option<String>) -> thread::JoinHandle<io::Result<()>> {
+   thread::spawn(move || match (pipe, input) {
+      (Some(mut pipe), Some(input)) => pipe.write_all(input.as_bytes()),
+      _ => Ok(()),
+   })
+}
+
+/// Read a pipe on its own thread so a full stderr can't block a command writing to stdout.
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+   thread::spawn(move || {
+      let mut buf = Vec::new();
+      if let Some(mut pipe) = pipe {
+         let _ = pipe.read_to_end(&mut buf);
+      }
+      String::from_utf8_lossy(&buf).into_owned()
+   })
+}
+
+/// Scripted runner for tests. Responses are matched against the full command line; the last response of a command repeats.
+/// Clones share responses and recorded calls.
+#[derive(Debug, Clone, Default)]
+pub struct FakeRunner {
+   responses: Arc<Mutex<HashMap<String, VecDeque<CommandOutput>>>>,
+   calls: Arc<Mutex<Vec<CommandLine>>>,
+}
+
+impl FakeRunner {
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   /// Respond to a command line such as `"timedatectl show"` with `output`.
+   pub fn expect<T: Into<String>>(self, cmd_line: T, output: CommandOutput) -> Self {
+      self.responses.lock().unwrap().entry(cmd_line.into()).or_default().push_back(output);
+      self
+   }
+
+   /// Return the commands run so far, in order.
+   pub fn calls(&self) -> Vec<CommandLine> {
+      self.calls.lock().unwrap().clone()
+   }
+}
+
+impl CommandRunner for FakeRunner {
+   fn run(&self, cmd: &CommandLine) -> Result<CommandOutput, Error> {
+      self.calls.lock().unwrap().push(cmd.clone());
+      let mut responses = self.responses.lock().unwrap();
+      match responses.get_mut(&cmd.to_string()) {
+         Some(queue) if queue.len() > 1 => Ok(queue.pop_front().unwrap()),
+         Some(queue) if !queue.is_empty() => Ok(queue[0].clone()),
+         _ => Err(Error::MissingTool(cmd.to_string())),
+      }
+   }
+}
+
+/// Shared handle of the runner a manager uses, defaulting to `SystemRunner`
+#[derive(Clone)]
+pub struct Runner(Arc<dyn CommandRunner>);
+
+impl Runner {
+   pub fn new<R: CommandRunner + 'static>(runner: R) -> Self {
+      Self(Arc::new(runner))
+   }
+}
+
+impl Default for Runner {
+   fn default() -> Self {
+      Self::new(SystemRunner::default())
+   }
+}
+
+impl Debug for Runner {
+   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      f.write_str("Runner")
+   }
+}
+
+impl Deref for Runner {
+   type Target = dyn CommandRunner;
+
+   fn deref(&self) -> &Self::Target {
+      self.0.as_ref()
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{CommandLine, CommandOutput, CommandRunner, FakeRunner, SystemRunner};
+   use crate::error::Error;
+   use std::time::Duration;
+
+   #[test]
+   fn test_system_runner() {
+      let runner = SystemRunner::default();
+      assert_eq!(runner.exec(&CommandLine::new("sh", &["-c", "echo $LC_ALL"])).unwrap(), "C");
+      assert_eq!(runner.exec(&CommandLine::new("cat", &[] as &[&str]).stdin("hello\n")).unwrap(), "hello");
+      assert!(matches!(runner.exec(&CommandLine::new("sh", &["-c", "echo oops >&2; exit 3"])), Err(Error::CommandFailed { code: Some(3), ref stderr, .. }) if stderr == "oops"));
+      assert!(matches!(runner.run(&CommandLine::new("sleep", &["5"]).timeout(Duration::from_millis(100))), Err(Error::Timeout { .. })));
+
+      // the command fills its stdout before reading stdin
+      let input = "k".repeat(1 << 20);
+      let output = runner.run(&CommandLine::new("sh", &["-c", "head -c 1048576 /dev/zero; cat"]).stdin(input.as_str()).timeout(Duration::from_secs(10))).unwrap();
+      assert_eq!(output.stdout.len(), 2 << 20);
+      assert!(matches!(runner.run(&CommandLine::new("sleep", &["5"]).stdin(input).timeout(Duration::from_millis(100))), Err(Error::Timeout { .. })));
+      assert!(runner.exec(&CommandLine::new("true", &[] as &[&str]).stdin("k".repeat(1 << 20))).is_ok());
+      assert!(matches!(runner.run(&CommandLine::new("libkoompi-no-such-tool", &[] as &[&str])), Err(Error::MissingTool(_))));
+   }
+
+   #[test]
+   fn test_fake_runner() {
+      let runner = FakeRunner::new().expect("id -u", CommandOutput::ok("1000\n")).expect("pkexec groupadd test", CommandOutput::failed(127, "")).expect("pkexec groupadd test", CommandOutput::ok(""));
+      assert_eq!(runner.exec(&CommandLine::new("id", &["-u"])).unwrap(), "1000");
+      assert!(matches!(runner.exec(&CommandLine::new("pkexec", &["groupadd", "test"])), Err(Error::AuthorizationDenied)));
+      assert!(runner.exec(&CommandLine::new("pkexec", &["groupadd", "test"])).is_ok());
+      assert!(runner.exec(&CommandLine::new("pkexec", &["groupadd", "test"])).is_ok());
+      assert!(matches!(runner.run(&CommandLine::new("chsh", &["-l"])), Err(Error::MissingTool(_))));
+      assert_eq!(runner.calls().len(), 5);
+   }
+}
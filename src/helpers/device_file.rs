@@ -1,24 +1,63 @@
-const PATH: &str = "/sys/class";
-use std::path::Path;
-use udev::Device;
-pub fn set_device_path(path: &str) -> Result<udev::Device, std::io::Error> {
-    Device::from_syspath(Path::new(PATH).join(path).as_path())
+use crate::error::Error;
+use std::path::{Path, PathBuf};
+
+const SYSFS_ROOT: &str = "/sys";
+/// Environment variable pointing the device layer at another sysfs tree, e.g. a fixture.
+pub const SYSFS_ROOT_ENV: &str = "LIBKOOMPI_SYSFS_ROOT";
+
+/// Root of the sysfs tree the device layer reads and writes attributes in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sysfs {
+    root: PathBuf,
+}
+
+impl Default for Sysfs {
+    fn default() -> Self {
+        Self::new(std::env::var_os(SYSFS_ROOT_ENV).map_or_else(|| PathBuf::from(SYSFS_ROOT), PathBuf::from))
+    }
 }
 
-pub fn get_property(property: &str) -> u32 {
-    let mut max_val: u32 = 0;
-    match set_device_path("backlight/intel_backlight") {
-        Ok(dev) => match dev.attribute_value(property) {
-            Some(val) => match val.to_str() {
-                Some(val_str) => match val_str.parse::<u32>() {
-                    Ok(data) => max_val = data,
-                    Err(e) => eprintln!("Error: {:?}", e),
-                },
-                None => eprintln!("failed to convert str"),
-            },
-            None => println!("No value"),
-        },
-        Err(e) => eprintln!("Error Type: {:?}", e),
-    }
-    max_val
+impl Sysfs {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory of a device class, e.g. `<root>/class/backlight`.
+    pub fn class_dir(&self, class: &str) -> PathBuf {
+        self.root.join("class").join(class)
+    }
+
+    /// Directory of a device, e.g. `<root>/class/backlight/intel_backlight`.
+    pub fn device_dir(&self, class: &str, id: &str) -> PathBuf {
+        self.class_dir(class).join(id)
+    }
+
+    /// List the devices of a class, sorted by name. A missing class has no devices.
+    pub fn list_devices(&self, class: &str) -> Vec<String> {
+        let mut devices: Vec<String> = match std::fs::read_dir(self.class_dir(class)) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.file_name().to_string_lossy().into_owned()).collect(),
+            Err(_) => Vec::new(),
+        };
+        devices.sort();
+        devices
+    }
+
+    /// Read an attribute, trimmed of the trailing newline.
+    pub fn read_attribute(&self, class: &str, id: &str, attr: &str) -> Result<String, Error> {
+        Ok(std::fs::read_to_string(self.device_dir(class, id).join(attr))?.trim().to_string())
+    }
+
+    /// Read a numeric attribute. Fails with `Error::Parse` if it isn't a number.
+    pub fn read_u32(&self, class: &str, id: &str, attr: &str) -> Result<u32, Error> {
+        let val = self.read_attribute(class, id, attr)?;
+        val.parse().map_err(|_| Error::Parse(format!("{}/{}/{}: {}", class, id, attr, val)))
+    }
+
+    pub fn write_attribute<T: ToString>(&self, class: &str, id: &str, attr: &str, value: T) -> Result<(), Error> {
+        Ok(std::fs::write(self.device_dir(class, id).join(attr), value.to_string())?)
+    }
 }
@@ -1,5 +1,5 @@
 use std::fs::{self, File};
-use std::io::{prelude::*, BufRead, Result, Lines, BufReader};
+use std::io::{prelude::*, BufRead, Error, ErrorKind, Result, Lines, BufReader};
 use std::path::Path;
 
 /// This function is used to read file line by line and return resulf of lines.
@@ -33,4 +33,23 @@ where P: AsRef<Path> {
    let mut file = File::create(filename)?;
    file.write_all(content.as_bytes())?;
    Ok(())
-}
\ No newline at end of file
+}
+
+/// This function is used to replace file content atomically, so the file holds either the old or the new content even after a crash.
+pub fn write_content_atomic<P>(filename: P, content: &str) -> Result<()>
+where P: AsRef<Path> {
+   let path = filename.as_ref();
+   let name = path.file_name().ok_or_else(|| Error::new(ErrorKind::InvalidInput, "path has no file name"))?;
+   let tmp_path = path.with_file_name(format!(".{}.{}.tmp", name.to_string_lossy(), std::process::id()));
+
+   let mut file = File::create(&tmp_path)?;
+   if let Err(err) = file.write_all(content.as_bytes()).and_then(|_| file.sync_all()).and_then(|_| fs::rename(&tmp_path, path)) {
+      let _ = fs::remove_file(&tmp_path);
+      return Err(err);
+   }
+   // persist the rename itself
+   if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+      File::open(dir)?.sync_all()?;
+   }
+   Ok(())
+}
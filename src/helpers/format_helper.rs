@@ -1,4 +1,6 @@
+use serde_json::Value as Json;
 use titlecase::titlecase;
+use toml::Value as Toml;
 
 pub fn to_account_name<T: AsRef<str>>(formatted: T) -> String {
    formatted.as_ref().to_lowercase().replace(" ", "_")
@@ -6,4 +8,29 @@ pub fn to_account_name<T: AsRef<str>>(formatted: T) -> String {
 
 pub fn to_formatted_name<T: AsRef<str>>(name: T) -> String {
    titlecase(name.as_ref().replace("_", " ").as_str())
-}
\ No newline at end of file
+}
+
+/// This function is used to convert a TOML value to JSON. Datetimes become strings.
+pub fn toml_to_json(toml: Toml) -> Json {
+   match toml {
+      Toml::String(s) => Json::String(s),
+      Toml::Integer(i) => Json::Number(i.into()),
+      Toml::Float(f) => serde_json::Number::from_f64(f).map_or(Json::Null, Json::Number),
+      Toml::Boolean(b) => Json::Bool(b),
+      Toml::Array(arr) => Json::Array(arr.into_iter().map(toml_to_json).collect()),
+      Toml::Table(table) => Json::Object(table.into_iter().map(|(k, v)| (k, toml_to_json(v))).collect()),
+      Toml::Datetime(dt) => Json::String(dt.to_string()),
+   }
+}
+
+/// This function is used to convert a JSON value to TOML. Nulls, which TOML can't hold, are dropped.
+pub fn json_to_toml(json: Json) -> Option<Toml> {
+   match json {
+      Json::Null => None,
+      Json::Bool(b) => Some(Toml::Boolean(b)),
+      Json::Number(n) => n.as_i64().map(Toml::Integer).or_else(|| n.as_f64().map(Toml::Float)),
+      Json::String(s) => Some(Toml::String(s)),
+      Json::Array(arr) => Some(Toml::Array(arr.into_iter().filter_map(json_to_toml).collect())),
+      Json::Object(map) => Some(Toml::Table(map.into_iter().filter_map(|(k, v)| json_to_toml(v).map(|v| (k, v))).collect())),
+   }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use thiserror::Error;
+use super::constants::CONF_DIRS;
+
+const LOCKDOWN_CONF: &str = "lockdown.toml";
+const SYSTEM_CONF_DIR: &str = "koompi";
+
+/// Action id of `DesktopManager::add_wallpaper`.
+pub const ACTION_ADD_WALLPAPER: &str = "desktop.add-wallpaper";
+/// Action id of `UsersGroupsManager::create_user`.
+pub const ACTION_CREATE_USER: &str = "users.create-user";
+
+/// Error returned when a setting or action is locked by the administrator
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("locked by administrator: {0}")]
+pub struct Locked(pub String);
+
+/// Structure of admin lockdown policy, read from `koompi/lockdown.toml` in every `CONF_DIRS` entry:
+///
+/// ```toml
+/// disabled_actions = ["power.shutdown", "users.create-user"]
+///
+/// [locked_keys]
+/// "desktop/desktop.toml" = ["Background.Wallpaper_Config"]
+/// ```
+///
+/// A locked key also locks every key below it. Locked keys keep their vendor or admin value.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Lockdown {
+    /// Dotted key paths locked in each config, keyed by the config relative path.
+    locked_keys: HashMap<String, Vec<String>>,
+    disabled_actions: Vec<String>,
+}
+
+impl Lockdown {
+    /// Read and combine all lockdown files. Files that can't be parsed are skipped.
+    pub fn load() -> Self {
+        CONF_DIRS.iter().map(|dir| dir.join(SYSTEM_CONF_DIR).join(LOCKDOWN_CONF)).filter(|path| path.is_file()).fold(Self::default(), |mut lockdown, path| {
+            match fs::read_to_string(&path).map_err(|err| err.to_string()).and_then(|contents| toml::from_str::<Self>(&contents).map_err(|err| err.to_string())) {
+                Ok(file) => {
+                    file.locked_keys.into_iter().for_each(|(conf, keys)| lockdown.locked_keys.entry(conf).or_default().extend(keys));
+                    lockdown.disabled_actions.extend(file.disabled_actions);
+                }
+                Err(err) => log::warn!("skipping lockdown file {}: {}", path.display(), err),
+            }
+            lockdown
+        })
+    }
+
+    /// Return the locked keys of a config.
+    pub fn locked_keys<P: AsRef<Path>>(&self, relative_path: P) -> &[String] {
+        relative_path.as_ref().to_str().and_then(|path| self.locked_keys.get(path)).map_or(&[], Vec::as_slice)
+    }
+
+    /// Check if a dotted key of a config is locked, directly or through one of its parents.
+    pub fn is_key_locked<P: AsRef<Path>>(&self, relative_path: P, key: &str) -> bool {
+        self.locked_keys(relative_path).iter().any(|locked| key == locked || key.starts_with(&format!("{}.", locked)))
+    }
+
+    /// Check if an action is disabled.
+    pub fn is_action_disabled(&self, action: &str) -> bool {
+        self.disabled_actions.iter().any(|disabled| disabled == action)
+    }
+
+    /// Return a `Locked` error if an action is disabled.
+    pub fn check_action(&self, action: &str) -> Result<(), Locked> {
+        if self.is_action_disabled(action) {
+            Err(Locked(action.to_string()))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Return a `Locked` error if the current lockdown policy disables an action.
+pub fn check_action(action: &str) -> Result<(), Locked> {
+    Lockdown::load().check_action(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lockdown;
+
+    #[test]
+    fn test_lockdown() {
+        let lockdown: Lockdown = toml::from_str("disabled_actions = [\"power.shutdown\"]\n[locked_keys]\n\"desktop/desktop.toml\" = [\"Background\"]\n").unwrap();
+        assert!(lockdown.is_key_locked("desktop/desktop.toml", "Background.Wallpaper_Config.wallpaper_path"));
+        assert!(!lockdown.is_key_locked("desktop/desktop.toml", "Background_Extra"));
+        assert!(!lockdown.is_key_locked("fonts/fonts.toml", "Background"));
+        assert!(lockdown.check_action("power.shutdown").is_err());
+        assert!(lockdown.check_action("power.reboot").is_ok());
+    }
+}
@@ -0,0 +1,193 @@
+use std::sync::{Arc, Mutex};
+use dbus::arg::{prop_cast, PropMap, RefArg};
+use dbus::message::SignalArgs;
+use dbus::nonblock::stdintf::org_freedesktop_dbus::{Properties, PropertiesPropertiesChanged};
+use dbus::strings::{BusName, Path};
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
+use crate::error::Error;
+use super::async_runtime::RUNTIME;
+use super::system_bus::SystemBus;
+
+/// Typed view of the properties of one interface, rebuilt from the cached properties whenever they change
+pub trait FromProperties: Sized {
+   /// Build the view from all properties of the interface. Fails with `Error::Parse` if a required property is missing.
+   fn from_properties(props: &PropMap) -> Result<Self, Error>;
+}
+
+/// This function is used to read a required property of a `FromProperties` implementation.
+pub fn required_prop<T: Clone + 'static>(props: &PropMap, name: &str) -> Result<T, Error> {
+   prop_cast::<T>(props, name).cloned().ok_or_else(|| Error::Parse(format!("property {}", name)))
+}
+
+struct CacheState<T> {
+   props: PropMap,
+   value: T,
+   subscribers: Vec<mpsc::UnboundedSender<T>>,
+}
+
+/// Stops the update task once the last handle of a cache is dropped.
+struct StopGuard(Option<oneshot::Sender<()>>);
+
+impl Drop for StopGuard {
+   fn drop(&mut self) {
+      if let Some(stop) = self.0.take() {
+         let _ = stop.send(());
+      }
+   }
+}
+
+/// Local mirror of the properties of one interface of a bus object, kept current from `PropertiesChanged` signals.
+/// Clones share the mirror; the match rule is removed once every clone is dropped.
+pub struct PropertyCache<T> {
+   state: Arc<Mutex<CacheState<T>>>,
+   _stop: Arc<StopGuard>,
+}
+
+impl<T> Clone for PropertyCache<T> {
+   fn clone(&self) -> Self {
+      Self { state: self.state.clone(), _stop: self._stop.clone() }
+   }
+}
+
+impl<T> std::fmt::Debug for PropertyCache<T> {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      f.write_str("PropertyCache")
+   }
+}
+
+// Public API
+impl<T: FromProperties + Clone + Send + 'static> PropertyCache<T> {
+   /// This method is used to load all properties of `interface` and start following their changes.
+   pub async fn new(bus: &SystemBus, service: &str, path: &str, interface: &str) -> Result<Self, Error> {
+      let service = BusName::new(service.to_string()).map_err(Error::Validation)?;
+      let path = Path::new(path.to_string()).map_err(Error::Validation)?;
+      let interface = interface.to_string();
+
+      // Listen before loading, so no change is lost in between.
+      let conn = bus.connection();
+      let msg_match = conn.add_match(PropertiesPropertiesChanged::match_rule(Some(&service), Some(&path)).static_clone()).await?;
+      let (msg_match, mut signals) = msg_match.msg_stream();
+      let props = get_all(bus, &service, &path, &interface).await?;
+      let value = T::from_properties(&props)?;
+      let state = Arc::new(Mutex::new(CacheState { props, value, subscribers: Vec::new() }));
+
+      let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+      let task_state = state.clone();
+      let bus = bus.clone();
+      RUNTIME.spawn(async move {
+         loop {
+            let msg = tokio::select! {
+               _ = &mut stop_rx => break,
+               msg = signals.next() => match msg {
+                  Some(msg) => msg,
+                  None => break,
+               },
+            };
+            let changed = match PropertiesPropertiesChanged::from_message(&msg) {
+               Some(changed) if changed.interface_name == interface => changed,
+               _ => continue,
+            };
+            // Invalidated properties come without their value, so load them all again.
+            let reloaded = if changed.invalidated_properties.is_empty() {
+               None
+            } else {
+               match get_all(&bus, &service, &path, &interface).await {
+                  Ok(props) => Some(props),
+                  Err(err) => {
+                     log::warn!("failed to reload properties of {} {}: {}", path, interface, err);
+                     continue;
+                  }
+               }
+            };
+            let mut state = task_state.lock().unwrap();
+            match reloaded {
+               Some(props) => state.props = props,
+               None => apply_changes(&mut state.props, changed.changed_properties),
+            }
+            update_value(&mut state);
+         }
+         let _ = conn.remove_match(msg_match.token()).await;
+      });
+
+      Ok(Self { state, _stop: Arc::new(StopGuard(Some(stop_tx))) })
+   }
+
+   /// This method is used to return the current typed view.
+   pub fn get(&self) -> T {
+      self.state.lock().unwrap().value.clone()
+   }
+
+   /// This method is used to return a single property, whether or not the typed view exposes it.
+   pub fn property<R: RefArg + Clone + 'static>(&self, name: &str) -> Option<R> {
+      prop_cast::<R>(&self.state.lock().unwrap().props, name).cloned()
+   }
+
+   /// This method is used to receive the typed view each time the properties change.
+   pub fn subscribe(&self) -> mpsc::UnboundedReceiver<T> {
+      let (tx, rx) = mpsc::unbounded();
+      self.state.lock().unwrap().subscribers.push(tx);
+      rx
+   }
+}
+
+async fn get_all(bus: &SystemBus, service: &str, path: &str, interface: &str) -> Result<PropMap, Error> {
+   Ok(bus.proxy(service, path).get_all(interface).await?)
+}
+
+/// Merge the properties of a `PropertiesChanged` signal into the cached ones.
+fn apply_changes(props: &mut PropMap, changed: PropMap) {
+   props.extend(changed);
+}
+
+/// Rebuild the typed view and send it to the subscribers still listening.
+fn update_value<T: FromProperties + Clone>(state: &mut CacheState<T>) {
+   match T::from_properties(&state.props) {
+      Ok(value) => {
+         state.subscribers.retain(|tx| tx.unbounded_send(value.clone()).is_ok());
+         state.value = value;
+      }
+      Err(err) => log::warn!("ignoring property change: {}", err),
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{apply_changes, required_prop, update_value, CacheState, FromProperties};
+   use crate::error::Error;
+   use dbus::arg::{PropMap, Variant};
+   use futures::channel::mpsc;
+
+   #[derive(Debug, Clone, PartialEq)]
+   struct Battery {
+      percentage: f64,
+      state: u32,
+   }
+
+   impl FromProperties for Battery {
+      fn from_properties(props: &PropMap) -> Result<Self, Error> {
+         Ok(Self { percentage: required_prop(props, "Percentage")?, state: required_prop(props, "State")? })
+      }
+   }
+
+   #[test]
+   fn test_apply_changes() -> Result<(), Error> {
+      let mut props = PropMap::new();
+      props.insert("Percentage".into(), Variant(Box::new(80.0)));
+      props.insert("State".into(), Variant(Box::new(2u32)));
+      let value = Battery::from_properties(&props)?;
+      let (tx, mut rx) = mpsc::unbounded();
+      let mut state = CacheState { props, value, subscribers: vec![tx] };
+
+      let mut changed = PropMap::new();
+      changed.insert("Percentage".into(), Variant(Box::new(79.5)));
+      apply_changes(&mut state.props, changed);
+      update_value(&mut state);
+      assert_eq!(state.value, Battery { percentage: 79.5, state: 2 });
+      assert_eq!(rx.try_recv().unwrap(), state.value);
+
+      state.props.remove("State");
+      assert!(matches!(Battery::from_properties(&state.props), Err(Error::Parse(_))));
+      Ok(())
+   }
+}
@@ -0,0 +1,93 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use dbus::arg::{Append, AppendAll, Arg, Get, ReadAll};
+use dbus::nonblock::{stdintf::org_freedesktop_dbus::Properties, Proxy, SyncConnection};
+use crate::error::Error;
+use super::async_runtime::RUNTIME;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Calls which may show a polkit dialog wait for the user to authenticate.
+pub const INTERACTIVE_TIMEOUT: Duration = Duration::from_secs(300);
+
+lazy_static::lazy_static! {
+   static ref SHARED_CONN: Mutex<Option<Arc<SyncConnection>>> = Mutex::new(None);
+}
+
+/// Forget the shared connection, so the next `SystemBus::shared` connects to the bus in `DBUS_SYSTEM_BUS_ADDRESS`.
+#[cfg(any(test, feature = "test-support"))]
+pub(crate) fn reset_shared() {
+   SHARED_CONN.lock().unwrap().take();
+}
+
+/// Handle to the system bus connection shared by all async managers of the process.
+/// The connection is driven by a background runtime, so the handle works from any tokio runtime.
+#[derive(Clone)]
+pub struct SystemBus {
+   conn: Arc<SyncConnection>,
+}
+
+impl std::fmt::Debug for SystemBus {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      f.write_str("SystemBus")
+   }
+}
+
+// Public API
+impl SystemBus {
+   /// This method is used to get the shared connection, connecting on first use or after the connection was lost.
+   pub fn shared() -> Result<Self, Error> {
+      let mut shared = SHARED_CONN.lock().unwrap();
+      if let Some(conn) = shared.as_ref() {
+         return Ok(Self { conn: conn.clone() });
+      }
+
+      let _guard = RUNTIME.enter();
+      let (resource, conn) = dbus_tokio::connection::new_system_sync()?;
+      let lost_conn = conn.clone();
+      RUNTIME.spawn(async move {
+         let err = resource.await;
+         log::warn!("system bus connection lost: {}", err);
+         let mut shared = SHARED_CONN.lock().unwrap();
+         if shared.as_ref().is_some_and(|conn| Arc::ptr_eq(conn, &lost_conn)) {
+            *shared = None;
+         }
+      });
+      *shared = Some(conn.clone());
+      Ok(Self { conn })
+   }
+
+   /// This method is used to return the underlying connection, e.g. to add match rules.
+   pub fn connection(&self) -> Arc<SyncConnection> {
+      self.conn.clone()
+   }
+
+   /// This method is used to create a proxy with the default timeout.
+   pub fn proxy<'a>(&self, service: &'a str, path: &'a str) -> Proxy<'a, Arc<SyncConnection>> {
+      self.proxy_with_timeout(service, path, DEFAULT_TIMEOUT)
+   }
+
+   /// This method is used to create a proxy with a custom timeout.
+   pub fn proxy_with_timeout<'a>(&self, service: &'a str, path: &'a str, timeout: Duration) -> Proxy<'a, Arc<SyncConnection>> {
+      Proxy::new(service, path, timeout, self.conn.clone())
+   }
+
+   /// This method is used to call a method with the default timeout.
+   pub async fn call<A: AppendAll, R: ReadAll + 'static>(&self, service: &str, path: &str, interface: &str, method: &str, args: A) -> Result<R, Error> {
+      Ok(self.proxy(service, path).method_call(interface, method, args).await?)
+   }
+
+   /// This method is used to call a method which may ask the user to authenticate.
+   pub async fn call_interactive<A: AppendAll, R: ReadAll + 'static>(&self, service: &str, path: &str, interface: &str, method: &str, args: A) -> Result<R, Error> {
+      Ok(self.proxy_with_timeout(service, path, INTERACTIVE_TIMEOUT).method_call(interface, method, args).await?)
+   }
+
+   /// This method is used to get a property.
+   pub async fn get<T: for<'b> Get<'b> + 'static>(&self, service: &str, path: &str, interface: &str, prop: &str) -> Result<T, Error> {
+      Ok(self.proxy(service, path).get(interface, prop).await?)
+   }
+
+   /// This method is used to set a property.
+   pub async fn set<T: Arg + Append>(&self, service: &str, path: &str, interface: &str, prop: &str, value: T) -> Result<(), Error> {
+      Ok(self.proxy_with_timeout(service, path, INTERACTIVE_TIMEOUT).set(interface, prop, value).await?)
+   }
+}
@@ -2,3 +2,7 @@ pub mod helpers;
 pub mod session;
 pub mod system_settings;
 pub mod desktop;
+pub mod settings_bundle;
+pub mod error;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
@@ -1,4 +1,13 @@
+mod async_power;
+mod battery;
+mod events;
+mod inhibitor;
+mod policy;
 mod power;
-mod power_provider;
 
-pub use power::*;
\ No newline at end of file
+pub use async_power::*;
+pub use battery::*;
+pub use events::*;
+pub use inhibitor::*;
+pub use policy::*;
+pub use power::*;
@@ -0,0 +1,324 @@
+use dbus::arg::OwnedFd;
+use dbus::message::{MatchRule, MessageType};
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
+use crate::error::Error;
+use crate::helpers::{Lockdown, SystemBus, RUNTIME};
+use super::events::{PowerEvent, PowerEvents, PowerSignal};
+use super::inhibitor::{InhibitMode, InhibitWhat, Inhibitor, InhibitorInfo, InhibitorRecord};
+use super::power::Action;
+
+const LOGIN1_SERVICE: &str = "org.freedesktop.login1";
+const LOGIN1_PATH: &str = "/org/freedesktop/login1";
+const LOGIN1_MANAGER: &str = "org.freedesktop.login1.Manager";
+const LOGIN1_SESSION: &str = "org.freedesktop.login1.Session";
+const PREPARE_FOR_SLEEP: &str = "PrepareForSleep";
+const PREPARE_FOR_SHUTDOWN: &str = "PrepareForShutdown";
+/// logind resolves this path to the session of the caller.
+const LOGIN1_SESSION_AUTO: &str = "/org/freedesktop/login1/session/auto";
+
+/// Structure of the async Power Manager, talking to logind over the shared system bus connection
+#[derive(Debug, Clone, Default)]
+pub struct AsyncPowerManager;
+
+// Public API
+impl AsyncPowerManager {
+   /// This method is used to initialize the power manager.
+   pub fn new() -> Self {
+      Self
+   }
+
+   /// This method is used to check if can perform a defined action, which is never the case if the administrator disabled it.
+   pub async fn can_action(&self, action: Action) -> Result<bool, Error> {
+      if Lockdown::load().is_action_disabled(action.lockdown_id()) {
+         return Ok(false);
+      }
+      let method = match action {
+         Action::PowerLogout => return Ok(!self.session_id().await?.is_empty()),
+         Action::PowerHibernate => "CanHibernate",
+         Action::PowerReboot => "CanReboot",
+         Action::PowerShutdown => "CanPowerOff",
+         Action::PowerSuspend => "CanSuspend",
+      };
+      let (res,): (String,) = SystemBus::shared()?.call(LOGIN1_SERVICE, LOGIN1_PATH, LOGIN1_MANAGER, method, ()).await?;
+      Ok(res == "yes" || res == "challenge")
+   }
+
+   /// This method is used to perform the action after checking success.
+   pub async fn do_action(&self, action: Action) -> Result<bool, Error> {
+      Lockdown::load().check_action(action.lockdown_id())?;
+      if !self.can_action(action).await? {
+         return Ok(false);
+      }
+      let bus = SystemBus::shared()?;
+      let method = match action {
+         Action::PowerLogout => {
+            let sid = self.session_id().await?;
+            bus.call_interactive::<_, ()>(LOGIN1_SERVICE, LOGIN1_PATH, LOGIN1_MANAGER, "TerminateSession", (sid,)).await?;
+            return Ok(true);
+         }
+         Action::PowerHibernate => "Hibernate",
+         Action::PowerReboot => "Reboot",
+         Action::PowerShutdown => "PowerOff",
+         Action::PowerSuspend => "Suspend",
+      };
+      bus.call_interactive::<_, ()>(LOGIN1_SERVICE, LOGIN1_PATH, LOGIN1_MANAGER, method, (true,)).await?;
+      Ok(true)
+   }
+
+   /// This method is used to check if can logout.
+   pub async fn can_logout(&self) -> Result<bool, Error> {
+      self.can_action(Action::PowerLogout).await
+   }
+
+   /// This method is used to check if can hibernate.
+   pub async fn can_hibernate(&self) -> Result<bool, Error> {
+      self.can_action(Action::PowerHibernate).await
+   }
+
+   /// This method is used to check if can reboot.
+   pub async fn can_reboot(&self) -> Result<bool, Error> {
+      self.can_action(Action::PowerReboot).await
+   }
+
+   /// This method is used to check if can shutdown.
+   pub async fn can_shutdown(&self) -> Result<bool, Error> {
+      self.can_action(Action::PowerShutdown).await
+   }
+
+   /// This method is used to check if can suspend.
+   pub async fn can_suspend(&self) -> Result<bool, Error> {
+      self.can_action(Action::PowerSuspend).await
+   }
+
+   /// This method is used to perform logout with checking.
+   pub async fn logout(&self) -> Result<bool, Error> {
+      self.do_action(Action::PowerLogout).await
+   }
+
+   /// This method is used to perform hibernate with checking.
+   pub async fn hibernate(&self) -> Result<bool, Error> {
+      self.do_action(Action::PowerHibernate).await
+   }
+
+   /// This method is used to perform reboot with checking.
+   pub async fn reboot(&self) -> Result<bool, Error> {
+      self.do_action(Action::PowerReboot).await
+   }
+
+   /// This method is used to perform shutdown with checking.
+   pub async fn shutdown(&self) -> Result<bool, Error> {
+      self.do_action(Action::PowerShutdown).await
+   }
+
+   /// This method is used to perform suspend with checking.
+   pub async fn suspend(&self) -> Result<bool, Error> {
+      self.do_action(Action::PowerSuspend).await
+   }
+
+   /// This method is used to hold off `what` until the returned guard is dropped. `who` names the application and `why` is shown to the user.
+   /// Only shutdown and sleep can be delayed.
+   pub async fn inhibit(&self, what: InhibitWhat, who: &str, why: &str, mode: InhibitMode) -> Result<Inhibitor, Error> {
+      if what.is_empty() {
+         return Err(Error::Validation(String::from("nothing to inhibit")));
+      }
+      if mode == InhibitMode::Delay && !(InhibitWhat::SHUTDOWN | InhibitWhat::SLEEP).contains(what) {
+         return Err(Error::Validation(format!("{} can't be delayed", what)));
+      }
+      let (fd,): (OwnedFd,) = SystemBus::shared()?.call(LOGIN1_SERVICE, LOGIN1_PATH, LOGIN1_MANAGER, "Inhibit", (what.to_string(), who, why, mode.as_str())).await?;
+      Ok(Inhibitor::new(what, why, mode, fd))
+   }
+
+   /// This method is used to list the inhibitor locks of every process, e.g. to show why shutdown is blocked.
+   pub async fn list_inhibitors(&self) -> Result<Vec<InhibitorInfo>, Error> {
+      let (inhibitors,): (Vec<InhibitorRecord>,) = SystemBus::shared()?.call(LOGIN1_SERVICE, LOGIN1_PATH, LOGIN1_MANAGER, "ListInhibitors", ()).await?;
+      Ok(inhibitors.into_iter().map(InhibitorInfo::from).collect())
+   }
+
+   /// This method is used to receive an event before the system sleeps or shuts down, and after it resumes or shutdown is cancelled.
+   /// The system waits for each sleep or shutdown event to be done with, e.g. to lock the screen or save the brightness first.
+   /// `who` and `why` describe the delay inhibitor, which is taken again after each resume.
+   pub async fn subscribe(&self, who: &str, why: &str) -> Result<PowerEvents, Error> {
+      let what = InhibitWhat::SLEEP | InhibitWhat::SHUTDOWN;
+      // listen before taking the lock, so no signal is missed without a lock
+      let conn = SystemBus::shared()?.connection();
+      let rule = MatchRule::new().with_type(MessageType::Signal).with_interface(LOGIN1_MANAGER).with_sender(LOGIN1_SERVICE).with_path(LOGIN1_PATH);
+      let (msg_match, mut signals) = conn.add_match(rule).await?.msg_stream();
+      let mut delay = Some(self.inhibit(what, who, why, InhibitMode::Delay).await?);
+
+      let (tx, rx) = mpsc::unbounded();
+      let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+      let (power_mn, who, why) = (self.clone(), who.to_string(), why.to_string());
+      RUNTIME.spawn(async move {
+         loop {
+            // stop as soon as the events are dropped, instead of holding the lock until the next signal
+            let msg = tokio::select! {
+               _ = &mut stop_rx => break,
+               msg = signals.next() => match msg {
+                  Some(msg) => msg,
+                  None => break,
+               },
+            };
+            if msg.interface().as_deref() != Some(LOGIN1_MANAGER) {
+               continue;
+            }
+            let (start, member) = match (msg.read1::<bool>(), msg.member()) {
+               (Ok(start), Some(member)) => (start, member),
+               _ => continue,
+            };
+            let signal = match (&*member, start) {
+               (PREPARE_FOR_SLEEP, true) => PowerSignal::Sleep,
+               (PREPARE_FOR_SLEEP, false) => PowerSignal::Resume,
+               (PREPARE_FOR_SHUTDOWN, true) => PowerSignal::Shutdown,
+               (PREPARE_FOR_SHUTDOWN, false) => PowerSignal::ShutdownCancelled,
+               _ => continue,
+            };
+            let event = if start {
+               PowerEvent::new(signal, delay.take())
+            } else {
+               if delay.is_none() {
+                  match power_mn.inhibit(what, &who, &why, InhibitMode::Delay).await {
+                     Ok(inhibitor) => delay = Some(inhibitor),
+                     Err(err) => log::warn!("failed to delay the next sleep: {}", err),
+                  }
+               }
+               PowerEvent::new(signal, None)
+            };
+            if tx.unbounded_send(event).is_err() {
+               break;
+            }
+         }
+         drop(delay);
+         let _ = conn.remove_match(msg_match.token()).await;
+      });
+      Ok(PowerEvents::new(rx, stop_tx))
+   }
+}
+
+// Private API
+impl AsyncPowerManager {
+   /// Return the logind session of this process, or an empty id if it doesn't run in one.
+   async fn session_id(&self) -> Result<String, Error> {
+      match std::env::var("XDG_SESSION_ID") {
+         Ok(sid) if sid != "0" => Ok(sid),
+         _ => match SystemBus::shared()?.get::<String>(LOGIN1_SERVICE, LOGIN1_SESSION_AUTO, LOGIN1_SESSION, "Id").await {
+            Ok(sid) => Ok(sid),
+            Err(Error::DBus(err)) if err.name() == Some("org.freedesktop.login1.NoSessionForPID") => Ok(String::new()),
+            Err(err) => Err(err),
+         },
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::AsyncPowerManager;
+   use crate::error::Error;
+   use crate::helpers::block_on;
+   use crate::session::power::{InhibitMode, InhibitWhat, PowerSignal};
+   use crate::test_support::{MockService, MockValue};
+   use dbus::arg::OwnedFd;
+   use futures::StreamExt;
+   use std::io::Read;
+   use std::os::unix::io::IntoRawFd;
+   use std::os::unix::net::UnixStream;
+   use std::sync::Mutex;
+   use std::time::Duration;
+
+   #[test]
+   fn test_power_actions() -> Result<(), Error> {
+      let login1 = match MockService::login1() {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      login1.reply("org.freedesktop.login1.Manager", "CanHibernate", vec!["na".into()]);
+      let power_mn = AsyncPowerManager::new();
+      assert!(block_on(power_mn.can_reboot())?);
+      assert!(!block_on(power_mn.can_hibernate())?);
+      assert!(!block_on(power_mn.hibernate())?);
+      assert!(block_on(power_mn.suspend())?);
+      assert!(login1.calls_to("Hibernate").is_empty());
+      assert_eq!(login1.calls_to("Suspend")[0].read1::<bool>().unwrap(), true);
+      Ok(())
+   }
+
+   #[test]
+   fn test_inhibit() -> Result<(), Error> {
+      let login1 = match MockService::login1() {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      // keep the other end, which reads end of file once every copy of the lock fd is closed
+      let (lock, mut peer) = UnixStream::pair()?;
+      let lock = Mutex::new(Some(lock));
+      login1.on_call("org.freedesktop.login1.Manager", "Inhibit", move |_| {
+         let lock = lock.lock().unwrap().take().expect("inhibited once");
+         Ok(vec![MockValue::Fd(unsafe { OwnedFd::new(lock.into_raw_fd()) })])
+      });
+      let power_mn = AsyncPowerManager::new();
+
+      let what = InhibitWhat::SHUTDOWN | InhibitWhat::SLEEP;
+      assert!(matches!(block_on(power_mn.inhibit(InhibitWhat::IDLE, "Videos", "Playing a movie", InhibitMode::Delay)), Err(Error::Validation(_))));
+      let inhibitor = block_on(power_mn.inhibit(what, "Updater", "Installing updates", InhibitMode::Block))?;
+      assert_eq!((inhibitor.what(), inhibitor.why(), inhibitor.mode()), (what, "Installing updates", InhibitMode::Block));
+      let (sent_what, who, _, mode): (String, String, String, String) = login1.calls_to("Inhibit")[0].read_all().unwrap();
+      assert_eq!((sent_what.as_str(), who.as_str(), mode.as_str()), ("shutdown:sleep", "Updater", "block"));
+
+      peer.set_read_timeout(Some(Duration::from_secs(5)))?;
+      inhibitor.release();
+      assert_eq!(peer.read(&mut [0u8; 1])?, 0);
+      Ok(())
+   }
+
+   #[test]
+   fn test_subscribe() -> Result<(), Error> {
+      let login1 = match MockService::login1() {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      let power_mn = AsyncPowerManager::new();
+      let signals = block_on(async {
+         let mut events = power_mn.subscribe("Session", "Locking the screen").await?;
+         let mut signals = Vec::new();
+         for (member, start) in &[("PrepareForSleep", true), ("PrepareForSleep", false), ("PrepareForShutdown", true)] {
+            login1.emit("/org/freedesktop/login1", "org.freedesktop.login1.Manager", member, vec![(*start).into()]);
+            let event = tokio::time::timeout(Duration::from_secs(5), events.next()).await.map_err(|_| Error::Validation(String::from("no event")))?.unwrap();
+            signals.push((event.signal(), event.is_delaying()));
+            event.done();
+         }
+         Ok::<_, Error>(signals)
+      })?;
+      assert_eq!(signals, vec![(PowerSignal::Sleep, true), (PowerSignal::Resume, false), (PowerSignal::Shutdown, true)]);
+
+      let inhibits = login1.calls_to("Inhibit");
+      assert_eq!(inhibits.len(), 2);
+      let (what, _, _, mode): (String, String, String, String) = inhibits[1].read_all().unwrap();
+      assert_eq!((what.as_str(), mode.as_str()), ("shutdown:sleep", "delay"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_unsubscribe() -> Result<(), Error> {
+      let login1 = match MockService::login1() {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      let (lock, mut peer) = UnixStream::pair()?;
+      let lock = Mutex::new(Some(lock));
+      login1.on_call("org.freedesktop.login1.Manager", "Inhibit", move |_| {
+         let lock = lock.lock().unwrap().take().expect("inhibited once");
+         Ok(vec![MockValue::Fd(unsafe { OwnedFd::new(lock.into_raw_fd()) })])
+      });
+
+      let events = block_on(AsyncPowerManager::new().subscribe("Session", "Locking the screen"))?;
+      peer.set_read_timeout(Some(Duration::from_secs(5)))?;
+      // no signal comes, yet dropping the events releases the lock
+      drop(events);
+      assert_eq!(peer.read(&mut [0u8; 1])?, 0);
+      Ok(())
+   }
+}
@@ -0,0 +1,338 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use dbus::arg::{prop_cast, PropMap};
+use dbus::message::{MatchRule, MessageType};
+use dbus::Path;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
+use crate::error::Error;
+use crate::helpers::{block_on, required_prop, FromProperties, PropertyCache, Sysfs, SystemBus, RUNTIME};
+use crate::system_settings::devices::{list_power_supplies, BatteryInfo, HotplugStream, PowerSource, PowerSupplyKind, Subsystem};
+
+const UPOWER_SERVICE: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const UPOWER_INTERFACE: &str = "org.freedesktop.UPower";
+const UPOWER_DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+
+/// Global properties of UPower
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct UPowerProperties {
+   on_battery: bool,
+}
+
+impl FromProperties for UPowerProperties {
+   fn from_properties(props: &PropMap) -> Result<Self, Error> {
+      Ok(Self { on_battery: required_prop(props, "OnBattery")? })
+   }
+}
+
+impl FromProperties for BatteryInfo {
+   fn from_properties(props: &PropMap) -> Result<Self, Error> {
+      let seconds = |name: &str| prop_cast::<i64>(props, name).filter(|secs| **secs > 0).map(|secs| Duration::from_secs(*secs as u64));
+      let float = |name: &str| prop_cast::<f64>(props, name).copied().unwrap_or_default();
+      Ok(Self {
+         id: prop_cast::<String>(props, "NativePath").cloned().unwrap_or_default(),
+         kind: required_prop::<u32>(props, "Type")?.into(),
+         present: prop_cast::<bool>(props, "IsPresent").copied().unwrap_or(true),
+         power_supply: prop_cast::<bool>(props, "PowerSupply").copied().unwrap_or(true),
+         percentage: required_prop(props, "Percentage")?,
+         state: required_prop::<u32>(props, "State")?.into(),
+         time_to_empty: seconds("TimeToEmpty"),
+         time_to_full: seconds("TimeToFull"),
+         energy_rate: float("EnergyRate"),
+         energy_full: float("EnergyFull"),
+         energy_full_design: float("EnergyFullDesign"),
+         // -1 if unknown
+         cycle_count: prop_cast::<i32>(props, "ChargeCycles").filter(|count| **count > 0).map(|count| *count as u32),
+      })
+   }
+}
+
+/// Power supplies by object path, in the order UPower reported them
+type Devices = Arc<RwLock<Vec<(String, PropertyCache<BatteryInfo>)>>>;
+type Subscribers = Arc<Mutex<Vec<mpsc::UnboundedSender<BatteryInfo>>>>;
+
+#[derive(Debug)]
+enum Backend {
+   UPower {
+      props: PropertyCache<UPowerProperties>,
+      devices: Devices,
+      subscribers: Subscribers,
+      /// Stops following added and removed devices once the manager is dropped
+      _stop: oneshot::Sender<()>,
+   },
+   Sysfs(Sysfs),
+}
+
+/// Structure of the async Battery Manager, following UPower, or reading sysfs if UPower isn't running
+#[derive(Debug, Clone)]
+pub struct AsyncBatteryManager {
+   backend: Arc<Backend>,
+}
+
+// Public API
+impl AsyncBatteryManager {
+   /// This method is used to load the power supplies known to UPower, falling back to sysfs if UPower isn't running.
+   /// Power supplies plugged in or removed later, e.g. a wireless mouse, are followed as UPower reports them.
+   pub async fn new() -> Self {
+      match Self::connect_upower().await {
+         Ok(backend) => Self { backend: Arc::new(backend) },
+         Err(err) => {
+            log::info!("UPower unavailable, reading power supplies from sysfs: {}", err);
+            Self::with_sysfs(Sysfs::default())
+         }
+      }
+   }
+
+   /// This method is used to read the power supplies of a sysfs tree only, e.g. a test fixture.
+   pub fn with_sysfs(sysfs: Sysfs) -> Self {
+      Self { backend: Arc::new(Backend::Sysfs(sysfs)) }
+   }
+
+   /// This method is used to check whether the values come live from UPower.
+   pub fn uses_upower(&self) -> bool {
+      matches!(*self.backend, Backend::UPower { .. })
+   }
+
+   /// This method is used to list every power supply, including AC adapters and batteries of peripherals.
+   pub fn devices(&self) -> Vec<BatteryInfo> {
+      match &*self.backend {
+         Backend::UPower { devices, .. } => devices.read().unwrap().iter().map(|(_, dev)| dev.get()).collect(),
+         Backend::Sysfs(sysfs) => list_power_supplies(sysfs),
+      }
+   }
+
+   /// This method is used to list the batteries powering the machine.
+   pub fn batteries(&self) -> Vec<BatteryInfo> {
+      self.devices().into_iter().filter(|dev| dev.kind == PowerSupplyKind::Battery && dev.power_supply && dev.present).collect()
+   }
+
+   /// This method is used to check whether the machine runs on AC power. Machines without a battery always do.
+   pub fn on_ac(&self) -> bool {
+      match &*self.backend {
+         Backend::UPower { props, .. } => !props.get().on_battery,
+         Backend::Sysfs(sysfs) => PowerSource::detect(sysfs) == PowerSource::Ac,
+      }
+   }
+
+   /// This method is used to receive a power supply each time it changes or is plugged in.
+   /// Without UPower, changes come from udev, which the kernel notifies on status changes and every few percent.
+   pub fn subscribe(&self) -> Result<mpsc::UnboundedReceiver<BatteryInfo>, Error> {
+      let (tx, rx) = mpsc::unbounded();
+      match &*self.backend {
+         Backend::UPower { subscribers, .. } => subscribers.lock().unwrap().push(tx),
+         Backend::Sysfs(sysfs) => {
+            let mut events = HotplugStream::new(&[Subsystem::PowerSupply])?;
+            let sysfs = sysfs.clone();
+            RUNTIME.spawn(async move {
+               while let Some(event) = events.next().await {
+                  let dev = match BatteryInfo::from_sysfs(&sysfs, &event.sysname) {
+                     Ok(dev) => dev,
+                     // removed
+                     Err(_) => continue,
+                  };
+                  if tx.unbounded_send(dev).is_err() {
+                     break;
+                  }
+               }
+            });
+         }
+      }
+      Ok(rx)
+   }
+}
+
+// Private API
+impl AsyncBatteryManager {
+   async fn connect_upower() -> Result<Backend, Error> {
+      let bus = SystemBus::shared()?;
+      let props = PropertyCache::new(&bus, UPOWER_SERVICE, UPOWER_PATH, UPOWER_INTERFACE).await?;
+      // listen before enumerating, so no device is missed in between
+      let conn = bus.connection();
+      let rule = MatchRule::new().with_type(MessageType::Signal).with_interface(UPOWER_INTERFACE).with_sender(UPOWER_SERVICE).with_path(UPOWER_PATH);
+      let (msg_match, mut signals) = conn.add_match(rule).await?.msg_stream();
+      let (paths,): (Vec<Path<'static>>,) = bus.call(UPOWER_SERVICE, UPOWER_PATH, UPOWER_INTERFACE, "EnumerateDevices", ()).await?;
+      let subscribers: Subscribers = Arc::new(Mutex::new(Vec::new()));
+      let mut devices = Vec::with_capacity(paths.len());
+      for path in paths {
+         // a device may vanish while enumerating, or expose broken properties, which shouldn't hide the others
+         match PropertyCache::new(&bus, UPOWER_SERVICE, &path, UPOWER_DEVICE_INTERFACE).await {
+            Ok(dev) => {
+               forward_changes(&dev, &subscribers);
+               devices.push((path.to_string(), dev));
+            }
+            Err(err) => log::warn!("failed to follow power supply {}: {}", path, err),
+         }
+      }
+      let devices: Devices = Arc::new(RwLock::new(devices));
+
+      let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+      let (task_devices, task_subscribers) = (devices.clone(), subscribers.clone());
+      RUNTIME.spawn(async move {
+         loop {
+            let msg = tokio::select! {
+               _ = &mut stop_rx => break,
+               msg = signals.next() => match msg {
+                  Some(msg) => msg,
+                  None => break,
+               },
+            };
+            if msg.interface().as_deref() != Some(UPOWER_INTERFACE) {
+               continue;
+            }
+            let path = match msg.read1::<Path>() {
+               Ok(path) => path.to_string(),
+               Err(_) => continue,
+            };
+            match msg.member().as_deref() {
+               Some("DeviceAdded") => match PropertyCache::<BatteryInfo>::new(&bus, UPOWER_SERVICE, &path, UPOWER_DEVICE_INTERFACE).await {
+                  Ok(dev) => {
+                     forward_changes(&dev, &task_subscribers);
+                     let info = dev.get();
+                     {
+                        let mut devices = task_devices.write().unwrap();
+                        devices.retain(|(known, _)| *known != path);
+                        devices.push((path, dev));
+                     }
+                     notify(&task_subscribers, info);
+                  }
+                  Err(err) => log::warn!("failed to follow power supply {}: {}", path, err),
+               },
+               // dropping the cache ends its forwarding
+               Some("DeviceRemoved") => task_devices.write().unwrap().retain(|(known, _)| *known != path),
+               _ => {}
+            }
+         }
+         let _ = conn.remove_match(msg_match.token()).await;
+      });
+      Ok(Backend::UPower { props, devices, subscribers, _stop: stop_tx })
+   }
+}
+
+/// Pass the changes of a power supply on to the subscribers, until it's removed.
+fn forward_changes(dev: &PropertyCache<BatteryInfo>, subscribers: &Subscribers) {
+   let (mut changes, subscribers) = (dev.subscribe(), subscribers.clone());
+   RUNTIME.spawn(async move {
+      while let Some(dev) = changes.next().await {
+         notify(&subscribers, dev);
+      }
+   });
+}
+
+fn notify(subscribers: &Subscribers, dev: BatteryInfo) {
+   subscribers.lock().unwrap().retain(|tx| tx.unbounded_send(dev.clone()).is_ok());
+}
+
+/// Structure of Battery Manager, a blocking facade of `AsyncBatteryManager`
+#[derive(Debug, Clone)]
+pub struct BatteryManager {
+   inner: AsyncBatteryManager,
+}
+
+impl Default for BatteryManager {
+   fn default() -> Self {
+      Self { inner: block_on(AsyncBatteryManager::new()) }
+   }
+}
+
+// Public API
+impl BatteryManager {
+   /// This method is used to load the power supplies, falling back to sysfs if UPower isn't running.
+   pub fn new() -> Self {
+      Self::default()
+   }
+
+   /// This method is used to list every power supply, including AC adapters and batteries of peripherals.
+   pub fn devices(&self) -> Vec<BatteryInfo> {
+      self.inner.devices()
+   }
+
+   /// This method is used to list the batteries powering the machine.
+   pub fn batteries(&self) -> Vec<BatteryInfo> {
+      self.inner.batteries()
+   }
+
+   /// This method is used to check whether the machine runs on AC power.
+   pub fn on_ac(&self) -> bool {
+      self.inner.on_ac()
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::AsyncBatteryManager;
+   use crate::error::Error;
+   use crate::helpers::block_on;
+   use crate::system_settings::devices::{BatteryState, PowerSupplyKind};
+   use crate::test_support::{MockService, MockValue, SysfsFixture};
+   use futures::StreamExt;
+   use std::time::Duration;
+
+   #[test]
+   fn test_battery_manager() -> Result<(), Error> {
+      let fixture = SysfsFixture::new()?;
+      fixture.ac("AC", true).battery("BAT0", 97, "Charging");
+      let battery_mn = AsyncBatteryManager::with_sysfs(fixture.sysfs());
+      assert!(!battery_mn.uses_upower());
+      assert!(battery_mn.on_ac());
+      assert_eq!(battery_mn.batteries()[0].percentage, 97.0);
+
+      let upower = match MockService::upower() {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      const BAT0: &str = "/org/freedesktop/UPower/devices/battery_BAT0";
+      const MOUSE: &str = "/org/freedesktop/UPower/devices/mouse_hidpp_battery_0";
+      // enumerated, but gone before its properties are read
+      const GONE: &str = "/org/freedesktop/UPower/devices/line_power_AC";
+      upower.add_object(BAT0, "org.freedesktop.UPower.Device", vec![
+         ("NativePath", "BAT0".into()),
+         ("Type", 2u32.into()),
+         ("PowerSupply", true.into()),
+         ("IsPresent", true.into()),
+         ("Percentage", 42.0.into()),
+         ("State", 2u32.into()),
+         ("TimeToEmpty", 3600i64.into()),
+         ("TimeToFull", 0i64.into()),
+         ("EnergyFull", 45.0.into()),
+         ("EnergyFullDesign", 50.0.into()),
+         ("ChargeCycles", (-1i32).into()),
+      ]);
+      upower.reply("org.freedesktop.UPower", "EnumerateDevices", vec![MockValue::paths(vec![GONE, BAT0])]);
+      upower.set_property("/org/freedesktop/UPower", "org.freedesktop.UPower", "OnBattery", true);
+
+      block_on(async {
+         let battery_mn = AsyncBatteryManager::new().await;
+         assert!(battery_mn.uses_upower());
+         assert!(!battery_mn.on_ac());
+         assert_eq!(battery_mn.devices().len(), 1);
+         let battery = &battery_mn.batteries()[0];
+         assert_eq!((battery.id.as_str(), battery.kind, battery.state), ("BAT0", PowerSupplyKind::Battery, BatteryState::Discharging));
+         assert_eq!((battery.time_to_empty, battery.time_to_full, battery.cycle_count), (Some(Duration::from_secs(3600)), None, None));
+         assert_eq!(battery.health(), Some(90.0));
+
+         let mut changes = battery_mn.subscribe()?;
+         upower.set_property(BAT0, "org.freedesktop.UPower.Device", "Percentage", 41.0);
+         let changed = tokio::time::timeout(Duration::from_secs(5), changes.next()).await.map_err(|_| Error::Validation(String::from("no change")))?;
+         assert_eq!(changed.map(|battery| battery.percentage), Some(41.0));
+
+         upower.add_object(MOUSE, "org.freedesktop.UPower.Device", vec![("NativePath", "hidpp_battery_0".into()), ("Type", 5u32.into()), ("PowerSupply", false.into()), ("Percentage", 80.0.into()), ("State", 2u32.into())]);
+         upower.emit("/org/freedesktop/UPower", "org.freedesktop.UPower", "DeviceAdded", vec![MockValue::path(MOUSE)]);
+         let added = tokio::time::timeout(Duration::from_secs(5), changes.next()).await.map_err(|_| Error::Validation(String::from("no device added")))?;
+         assert_eq!(added.map(|dev| dev.kind), Some(PowerSupplyKind::Mouse));
+         assert_eq!(battery_mn.devices().len(), 2);
+
+         upower.emit("/org/freedesktop/UPower", "org.freedesktop.UPower", "DeviceRemoved", vec![MockValue::path(BAT0)]);
+         for _ in 0..100 {
+            if battery_mn.batteries().is_empty() {
+               break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+         }
+         assert_eq!(battery_mn.devices().iter().map(|dev| dev.id.as_str()).collect::<Vec<_>>(), vec!["hidpp_battery_0"]);
+         Ok::<_, Error>(())
+      })?;
+      Ok(())
+   }
+}
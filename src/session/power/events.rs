@@ -0,0 +1,63 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use futures::channel::{mpsc, oneshot};
+use futures::{Stream, StreamExt};
+use super::inhibitor::Inhibitor;
+
+/// Power state change announced by logind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerSignal {
+   /// The system is about to suspend or hibernate
+   Sleep,
+   Resume,
+   /// The system is about to power off or reboot
+   Shutdown,
+   ShutdownCancelled,
+}
+
+/// Power state change, holding off sleep or shutdown until it's done with or dropped
+#[derive(Debug)]
+pub struct PowerEvent {
+   signal: PowerSignal,
+   delay: Option<Inhibitor>,
+}
+
+impl PowerEvent {
+   pub(super) fn new(signal: PowerSignal, delay: Option<Inhibitor>) -> Self {
+      Self { signal, delay }
+   }
+
+   pub fn signal(&self) -> PowerSignal {
+      self.signal
+   }
+
+   /// This method is used to check whether the system waits for this event to be done with.
+   /// logind stops waiting after `InhibitDelayMaxSec`, 5 seconds by default.
+   pub fn is_delaying(&self) -> bool {
+      self.delay.is_some()
+   }
+
+   /// This method is used to let the system go on sleeping or shutting down, e.g. once the screen is locked.
+   pub fn done(self) {}
+}
+
+/// Stream of power events. Dropping it releases the delay inhibitor right away, so logind no longer waits for this process.
+#[derive(Debug)]
+pub struct PowerEvents {
+   events: mpsc::UnboundedReceiver<PowerEvent>,
+   _stop: oneshot::Sender<()>,
+}
+
+impl PowerEvents {
+   pub(super) fn new(events: mpsc::UnboundedReceiver<PowerEvent>, stop: oneshot::Sender<()>) -> Self {
+      Self { events, _stop: stop }
+   }
+}
+
+impl Stream for PowerEvents {
+   type Item = PowerEvent;
+
+   fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+      self.events.poll_next_unpin(cx)
+   }
+}
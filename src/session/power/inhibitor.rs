@@ -0,0 +1,146 @@
+use std::fmt::{self, Display, Formatter};
+use std::os::unix::io::{AsRawFd, RawFd};
+use bitflags::bitflags;
+use dbus::arg::OwnedFd;
+
+bitflags! {
+   /// Operations an inhibitor lock holds off
+   pub struct InhibitWhat: u8 {
+      const SHUTDOWN = 0x01;
+      const SLEEP = 0x02;
+      /// Automatic suspend or screen off while idle
+      const IDLE = 0x04;
+      /// logind leaves the key to the lock holder, e.g. the session's power policy
+      const HANDLE_POWER_KEY = 0x08;
+      const HANDLE_SUSPEND_KEY = 0x10;
+      const HANDLE_HIBERNATE_KEY = 0x20;
+      const HANDLE_LID_SWITCH = 0x40;
+      const HANDLE_REBOOT_KEY = 0x80;
+   }
+}
+
+const WHAT_NAMES: [(InhibitWhat, &str); 8] = [
+   (InhibitWhat::SHUTDOWN, "shutdown"),
+   (InhibitWhat::SLEEP, "sleep"),
+   (InhibitWhat::IDLE, "idle"),
+   (InhibitWhat::HANDLE_POWER_KEY, "handle-power-key"),
+   (InhibitWhat::HANDLE_SUSPEND_KEY, "handle-suspend-key"),
+   (InhibitWhat::HANDLE_HIBERNATE_KEY, "handle-hibernate-key"),
+   (InhibitWhat::HANDLE_LID_SWITCH, "handle-lid-switch"),
+   (InhibitWhat::HANDLE_REBOOT_KEY, "handle-reboot-key"),
+];
+
+/// Colon separated names, as logind takes and lists them
+impl Display for InhibitWhat {
+   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      let names: Vec<&str> = WHAT_NAMES.iter().filter(|(what, _)| self.contains(*what)).map(|(_, name)| *name).collect();
+      write!(f, "{}", names.join(":"))
+   }
+}
+
+/// Parse colon separated names, ignoring the ones added by newer logind versions.
+impl From<&str> for InhibitWhat {
+   fn from(names: &str) -> Self {
+      names.split(':').filter_map(|name| WHAT_NAMES.iter().find(|(_, known)| *known == name)).fold(Self::empty(), |flags, (what, _)| flags | *what)
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InhibitMode {
+   /// Hold off the operations until the lock is released
+   Block,
+   /// Hold off shutdown or sleep for a few seconds only, e.g. to save state before suspending
+   Delay,
+}
+
+impl InhibitMode {
+   pub fn as_str(&self) -> &'static str {
+      match self {
+         Self::Block => "block",
+         Self::Delay => "delay",
+      }
+   }
+}
+
+impl From<&str> for InhibitMode {
+   fn from(mode: &str) -> Self {
+      match mode {
+         "delay" => Self::Delay,
+         // including `block-weak`, which only root can override
+         _ => Self::Block,
+      }
+   }
+}
+
+/// Entry of `ListInhibitors`: what, who, why, mode, uid and pid
+pub(super) type InhibitorRecord = (String, String, String, String, u32, u32);
+
+/// Inhibitor lock taken by any process, as listed by logind
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InhibitorInfo {
+   pub what: InhibitWhat,
+   /// Name of the application holding the lock, e.g. `Videos`
+   pub who: String,
+   /// Reason shown to the user, e.g. `Playing a movie`
+   pub why: String,
+   pub mode: InhibitMode,
+   pub uid: u32,
+   pub pid: u32,
+}
+
+impl From<InhibitorRecord> for InhibitorInfo {
+   fn from((what, who, why, mode, uid, pid): InhibitorRecord) -> Self {
+      Self { what: InhibitWhat::from(what.as_str()), who, why, mode: InhibitMode::from(mode.as_str()), uid, pid }
+   }
+}
+
+/// Inhibitor lock held by this process, released when dropped
+#[derive(Debug)]
+pub struct Inhibitor {
+   what: InhibitWhat,
+   why: String,
+   mode: InhibitMode,
+   fd: OwnedFd,
+}
+
+impl Inhibitor {
+   pub(super) fn new(what: InhibitWhat, why: &str, mode: InhibitMode, fd: OwnedFd) -> Self {
+      Self { what, why: why.to_string(), mode, fd }
+   }
+
+   pub fn what(&self) -> InhibitWhat {
+      self.what
+   }
+
+   pub fn why(&self) -> &str {
+      &self.why
+   }
+
+   pub fn mode(&self) -> InhibitMode {
+      self.mode
+   }
+
+   /// This method is used to release the lock before the guard goes out of scope.
+   pub fn release(self) {}
+}
+
+impl AsRawFd for Inhibitor {
+   fn as_raw_fd(&self) -> RawFd {
+      self.fd.as_raw_fd()
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{InhibitMode, InhibitWhat, InhibitorInfo};
+
+   #[test]
+   fn test_inhibit_what() {
+      let what = InhibitWhat::SLEEP | InhibitWhat::HANDLE_LID_SWITCH;
+      assert_eq!(what.to_string(), "sleep:handle-lid-switch");
+      assert_eq!(InhibitWhat::from("handle-lid-switch:sleep:unknown"), what);
+
+      let info = InhibitorInfo::from((String::from("shutdown:sleep"), String::from("Updater"), String::from("Installing updates"), String::from("block-weak"), 1000, 42));
+      assert_eq!((info.what, info.mode, info.pid), (InhibitWhat::SHUTDOWN | InhibitWhat::SLEEP, InhibitMode::Block, 42));
+   }
+}
@@ -0,0 +1,349 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use dbus::arg::PropMap;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use crate::desktop::{DesktopError, PersistentData};
+use crate::error::Error;
+use crate::helpers::{required_prop, FromProperties, PropertyCache, SystemBus};
+use crate::system_settings::devices::{BatteryState, PowerSource};
+use super::{AsyncBatteryManager, AsyncPowerManager, InhibitMode, InhibitWhat, Inhibitor};
+
+const POWER_CONF: &str = "power.toml";
+const LOGIN1_SERVICE: &str = "org.freedesktop.login1";
+const LOGIN1_PATH: &str = "/org/freedesktop/login1";
+const LOGIN1_MANAGER: &str = "org.freedesktop.login1.Manager";
+const UPOWER_SERVICE: &str = "org.freedesktop.UPower";
+const UPOWER_PATH: &str = "/org/freedesktop/UPower";
+const UPOWER_INTERFACE: &str = "org.freedesktop.UPower";
+const INHIBIT_WHO: &str = "koompi";
+const INHIBIT_WHY: &str = "The session applies the power policy";
+
+/// Action the power policy takes on an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PolicyAction {
+   #[default]
+   Nothing,
+   /// Dim the screen, only taken while idle
+   Dim,
+   ScreenOff,
+   Lock,
+   Suspend,
+   Hibernate,
+   Shutdown,
+}
+
+/// What closing the lid does, following the defaults of logind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LidPolicy {
+   pub on_ac: PolicyAction,
+   pub on_battery: PolicyAction,
+   /// With an external display or a dock connected
+   pub docked: PolicyAction,
+}
+
+impl Default for LidPolicy {
+   fn default() -> Self {
+      Self { on_ac: PolicyAction::Suspend, on_battery: PolicyAction::Suspend, docked: PolicyAction::Nothing }
+   }
+}
+
+/// Idle times in seconds after which the screen dims, turns off and the machine suspends. 0 never does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdleTimeouts {
+   pub dim_after: u64,
+   pub screen_off_after: u64,
+   pub suspend_after: u64,
+}
+
+impl IdleTimeouts {
+   /// Return the deepest action reached after being idle for `idle`.
+   pub fn action(&self, idle: Duration) -> PolicyAction {
+      let reached = |secs: u64| secs > 0 && idle >= Duration::from_secs(secs);
+      if reached(self.suspend_after) {
+         PolicyAction::Suspend
+      } else if reached(self.screen_off_after) {
+         PolicyAction::ScreenOff
+      } else if reached(self.dim_after) {
+         PolicyAction::Dim
+      } else {
+         PolicyAction::Nothing
+      }
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdlePolicy {
+   pub on_ac: IdleTimeouts,
+   pub on_battery: IdleTimeouts,
+}
+
+impl Default for IdlePolicy {
+   fn default() -> Self {
+      Self {
+         on_ac: IdleTimeouts { dim_after: 300, screen_off_after: 600, suspend_after: 0 },
+         on_battery: IdleTimeouts { dim_after: 120, screen_off_after: 300, suspend_after: 900 },
+      }
+   }
+}
+
+/// What to do once the batteries run low while discharging
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CriticalBatteryPolicy {
+   /// Charge in percent at or below which the action is taken
+   pub threshold: f64,
+   pub action: PolicyAction,
+}
+
+impl Default for CriticalBatteryPolicy {
+   fn default() -> Self {
+      Self { threshold: 5.0, action: PolicyAction::Hibernate }
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct PowerPolicy {
+   pub power_button: PolicyAction,
+   pub lid: LidPolicy,
+   pub idle: IdlePolicy,
+   pub critical_battery: CriticalBatteryPolicy,
+}
+
+impl PersistentData for PowerPolicy {
+   fn relative_path() -> PathBuf {
+      PathBuf::from("power").join(POWER_CONF)
+   }
+}
+
+/// Event the power policy reacts to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PolicyEvent {
+   LidClosed,
+   PowerButton,
+   /// The session has been idle for this long
+   Idle(Duration),
+   /// Charge in percent of the discharging batteries
+   BatteryLevel(f64),
+}
+
+impl PowerPolicy {
+   /// This method is used to decide the action for an event, given the power source and whether the machine is docked.
+   pub fn decide(&self, event: PolicyEvent, source: PowerSource, docked: bool) -> PolicyAction {
+      match event {
+         PolicyEvent::LidClosed if docked => self.lid.docked,
+         PolicyEvent::LidClosed => match source {
+            PowerSource::Ac => self.lid.on_ac,
+            PowerSource::Battery => self.lid.on_battery,
+         },
+         PolicyEvent::PowerButton => self.power_button,
+         PolicyEvent::Idle(idle) => match source {
+            PowerSource::Ac => self.idle.on_ac.action(idle),
+            PowerSource::Battery => self.idle.on_battery.action(idle),
+         },
+         PolicyEvent::BatteryLevel(percent) if source == PowerSource::Battery && percent <= self.critical_battery.threshold => self.critical_battery.action,
+         PolicyEvent::BatteryLevel(_) => PolicyAction::Nothing,
+      }
+   }
+}
+
+/// Lid state of UPower
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LidState {
+   present: bool,
+   closed: bool,
+}
+
+impl FromProperties for LidState {
+   fn from_properties(props: &PropMap) -> Result<Self, Error> {
+      Ok(Self { present: required_prop(props, "LidIsPresent")?, closed: required_prop(props, "LidIsClosed")? })
+   }
+}
+
+/// Structure of the Power Policy Engine, applying a `PowerPolicy` on behalf of logind.
+/// While it lives, logind leaves the lid switch and the power key to the session.
+#[derive(Debug)]
+pub struct PowerPolicyEngine {
+   policy: PowerPolicy,
+   power_mn: AsyncPowerManager,
+   battery_mn: AsyncBatteryManager,
+   /// logind handles the keys again once this is released
+   _inhibitor: Inhibitor,
+}
+
+// Public API
+impl PowerPolicyEngine {
+   /// This method is used to load the saved policy and take over the lid switch and the power key from logind.
+   pub async fn new() -> Result<Self, Error> {
+      let policy = PowerPolicy::load()?;
+      Self::with_battery_manager(policy, AsyncBatteryManager::new().await).await
+   }
+
+   /// This method is used to apply `policy`, reading the power source from `battery_mn`.
+   pub async fn with_battery_manager(policy: PowerPolicy, battery_mn: AsyncBatteryManager) -> Result<Self, Error> {
+      let power_mn = AsyncPowerManager::new();
+      let inhibitor = power_mn.inhibit(InhibitWhat::HANDLE_LID_SWITCH | InhibitWhat::HANDLE_POWER_KEY, INHIBIT_WHO, INHIBIT_WHY, InhibitMode::Block).await?;
+      Ok(Self { policy, power_mn, battery_mn, _inhibitor: inhibitor })
+   }
+
+   pub fn policy(&self) -> &PowerPolicy {
+      &self.policy
+   }
+
+   /// This method is used to change and save the policy.
+   pub fn set_policy(&mut self, policy: PowerPolicy) -> Result<(), DesktopError> {
+      policy.save()?;
+      self.policy = policy;
+      Ok(())
+   }
+
+   /// This method is used to react to an event, suspending, hibernating or shutting down as the policy says.
+   /// The decided action is returned, so the session can dim, turn off or lock the screen itself.
+   /// The session reports the power button and idle time, which it receives from the compositor.
+   pub async fn handle(&self, event: PolicyEvent) -> Result<PolicyAction, Error> {
+      let source = if self.battery_mn.on_ac() { PowerSource::Ac } else { PowerSource::Battery };
+      let docked = event == PolicyEvent::LidClosed && self.docked().await;
+      let action = self.policy.decide(event, source, docked);
+      let performed = match action {
+         PolicyAction::Suspend => self.power_mn.suspend().await?,
+         PolicyAction::Hibernate => self.power_mn.hibernate().await?,
+         PolicyAction::Shutdown => self.power_mn.shutdown().await?,
+         _ => true,
+      };
+      if !performed {
+         log::warn!("{:?} is not allowed on this machine", action);
+      }
+      Ok(action)
+   }
+
+   /// This method is used to handle closing the lid and running low on battery until the event sources end,
+   /// passing every decided action to `on_action`. The lid is followed through UPower, if it runs.
+   pub async fn run<F: FnMut(PolicyAction)>(&self, mut on_action: F) -> Result<(), Error> {
+      enum Change {
+         Lid(LidState),
+         Battery,
+      }
+      let lid = match PropertyCache::<LidState>::new(&SystemBus::shared()?, UPOWER_SERVICE, UPOWER_PATH, UPOWER_INTERFACE).await {
+         Ok(lid) => Some(lid),
+         Err(err) => {
+            log::info!("UPower unavailable, not following the lid: {}", err);
+            None
+         }
+      };
+      let lid_changes = match &lid {
+         Some(lid) => lid.subscribe().map(Change::Lid).boxed(),
+         None => futures::stream::empty().boxed(),
+      };
+      let mut changes = futures::stream::select(lid_changes, self.battery_mn.subscribe()?.map(|_| Change::Battery));
+      let mut lid_closed = lid.as_ref().is_some_and(|lid| lid.get().closed);
+      let mut critical = false;
+
+      while let Some(change) = changes.next().await {
+         let event = match change {
+            // UPower also notifies other properties, e.g. OnBattery
+            Change::Lid(state) if state.present && state.closed != lid_closed => {
+               lid_closed = state.closed;
+               if !lid_closed {
+                  continue;
+               }
+               PolicyEvent::LidClosed
+            }
+            Change::Lid(_) => continue,
+            Change::Battery => {
+               let batteries = self.battery_mn.batteries();
+               let discharging = batteries.iter().any(|battery| battery.state == BatteryState::Discharging);
+               let level = battery_level(&batteries.iter().map(|battery| battery.percentage).collect::<Vec<_>>());
+               let low = discharging && !self.battery_mn.on_ac() && level <= self.policy.critical_battery.threshold;
+               // act once per discharge
+               if !low || critical {
+                  critical = low;
+                  continue;
+               }
+               critical = true;
+               PolicyEvent::BatteryLevel(level)
+            }
+         };
+         match self.handle(event).await {
+            Ok(PolicyAction::Nothing) => {}
+            Ok(action) => on_action(action),
+            Err(err) => log::warn!("failed to handle {:?}: {}", event, err),
+         }
+      }
+      Ok(())
+   }
+}
+
+// Private API
+impl PowerPolicyEngine {
+   /// Whether an external display or a dock is connected. Unknown counts as undocked.
+   async fn docked(&self) -> bool {
+      match SystemBus::shared() {
+         Ok(bus) => bus.get::<bool>(LOGIN1_SERVICE, LOGIN1_PATH, LOGIN1_MANAGER, "Docked").await.unwrap_or(false),
+         Err(_) => false,
+      }
+   }
+}
+
+/// Charge of several batteries, which is their average as they drain one after another.
+fn battery_level(percentages: &[f64]) -> f64 {
+   if percentages.is_empty() {
+      100.0
+   } else {
+      percentages.iter().sum::<f64>() / percentages.len() as f64
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{PolicyAction, PolicyEvent, PowerPolicy, PowerPolicyEngine};
+   use crate::error::Error;
+   use crate::helpers::block_on;
+   use crate::session::power::AsyncBatteryManager;
+   use crate::system_settings::devices::PowerSource;
+   use crate::test_support::{MockService, SysfsFixture};
+   use std::time::Duration;
+
+   #[test]
+   fn test_decide() {
+      let mut policy = PowerPolicy::default();
+      assert_eq!(policy.decide(PolicyEvent::LidClosed, PowerSource::Battery, false), PolicyAction::Suspend);
+      assert_eq!(policy.decide(PolicyEvent::LidClosed, PowerSource::Ac, true), PolicyAction::Nothing);
+      assert_eq!(policy.decide(PolicyEvent::Idle(Duration::from_secs(200)), PowerSource::Ac, false), PolicyAction::Nothing);
+      assert_eq!(policy.decide(PolicyEvent::Idle(Duration::from_secs(200)), PowerSource::Battery, false), PolicyAction::Dim);
+      assert_eq!(policy.decide(PolicyEvent::Idle(Duration::from_secs(3600)), PowerSource::Ac, false), PolicyAction::ScreenOff);
+      assert_eq!(policy.decide(PolicyEvent::Idle(Duration::from_secs(3600)), PowerSource::Battery, false), PolicyAction::Suspend);
+      assert_eq!(policy.decide(PolicyEvent::BatteryLevel(4.0), PowerSource::Ac, false), PolicyAction::Nothing);
+      assert_eq!(policy.decide(PolicyEvent::BatteryLevel(4.0), PowerSource::Battery, false), PolicyAction::Hibernate);
+
+      policy.power_button = PolicyAction::Suspend;
+      policy.critical_battery.threshold = 10.0;
+      let saved: PowerPolicy = toml::from_str(&toml::to_string(&policy).unwrap()).unwrap();
+      assert_eq!(saved, policy);
+   }
+
+   #[test]
+   fn test_policy_engine() -> Result<(), Error> {
+      let login1 = match MockService::login1() {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      let fixture = SysfsFixture::new()?;
+      fixture.ac("AC", false).battery("BAT0", 50, "Discharging");
+      let mut policy = PowerPolicy::default();
+      policy.lid.on_battery = PolicyAction::Lock;
+
+      block_on(async {
+         let engine = PowerPolicyEngine::with_battery_manager(policy, AsyncBatteryManager::with_sysfs(fixture.sysfs())).await?;
+         assert_eq!(engine.handle(PolicyEvent::LidClosed).await?, PolicyAction::Lock);
+         assert_eq!(engine.handle(PolicyEvent::Idle(Duration::from_secs(1000))).await?, PolicyAction::Suspend);
+         login1.set_property("/org/freedesktop/login1", "org.freedesktop.login1.Manager", "Docked", true);
+         assert_eq!(engine.handle(PolicyEvent::LidClosed).await?, PolicyAction::Nothing);
+         Ok::<_, Error>(())
+      })?;
+
+      let (what, _, _, mode): (String, String, String, String) = login1.calls_to("Inhibit")[0].read_all().unwrap();
+      assert_eq!((what.as_str(), mode.as_str()), ("handle-power-key:handle-lid-switch", "block"));
+      assert_eq!(login1.calls_to("Suspend").len(), 1);
+      Ok(())
+   }
+}
@@ -1,6 +1,8 @@
-use dbus::Error;
-
-use super::power_provider::{InitSystemProvider, PowerProvider, SessionProvider, UPowerProvider};
+use crate::helpers::block_on;
+use crate::error::Error;
+use super::async_power::AsyncPowerManager;
+use super::events::PowerEvents;
+use super::inhibitor::{InhibitMode, InhibitWhat, Inhibitor, InhibitorInfo};
 
 /// Power can perform next actions:
 #[derive(Debug, Clone, Copy)]
@@ -13,45 +15,40 @@ pub enum Action {
    // PowerMonitorOff,
 }
 
-/// Structure of Power Manager
-pub struct PowerManager {
-   providers: Vec<Box<dyn PowerProvider>>,
+impl Action {
+   /// Return the id used to disable this action in the lockdown policy.
+   pub fn lockdown_id(&self) -> &'static str {
+      match self {
+         Action::PowerLogout => "power.logout",
+         Action::PowerHibernate => "power.hibernate",
+         Action::PowerReboot => "power.reboot",
+         Action::PowerShutdown => "power.shutdown",
+         Action::PowerSuspend => "power.suspend",
+      }
+   }
 }
 
-impl Default for PowerManager {
-   fn default() -> Self {
-      Self { providers: Vec::new() }
-   }
+/// Structure of Power Manager, a blocking facade of `AsyncPowerManager`
+#[derive(Debug, Clone, Default)]
+pub struct PowerManager {
+   inner: AsyncPowerManager,
 }
 
 // Public API
 impl PowerManager {
-   /// This method is used to initialize the power manager with a bunch of power providers.
+   /// This method is used to initialize the power manager.
    pub fn new() -> Self {
-      Self {
-         providers: vec![
-            // Box::new(Provider),
-            Box::new(InitSystemProvider),
-            Box::new(UPowerProvider),
-            Box::new(SessionProvider),
-         ],
-      }
+      Self::default()
    }
 
-   /// This method is used to check if can perform a defined action.
+   /// This method is used to check if can perform a defined action, which is never the case if the administrator disabled it.
    fn can_action(&self, action: Action) -> Result<bool, Error> {
-      self.providers.iter().fold(Ok(false), |_, prod| prod.can_action(action))
+      block_on(self.inner.can_action(action))
    }
 
    /// This method is used to perform the action after checking success.
    fn do_action(&self, action: Action) -> Result<bool, Error> {
-      self.providers.iter().fold(Ok(false), |_, prod| match prod.can_action(action) {
-         Ok(can_action) => match prod.do_action(action) {
-            Ok(do_action) => Ok(can_action && do_action),
-            Err(err) => Err(err),
-         },
-         Err(err) => Err(err),
-      })
+      block_on(self.inner.do_action(action))
    }
 
    /// This method is used to check if can logout.
@@ -107,4 +104,20 @@ impl PowerManager {
    }
 
    // pub fn monitor_off(&self) -> Result<bool, Error> { self.do_action(Action::PowerMonitorOff) }
+
+   /// This method is used to hold off `what` until the returned guard is dropped.
+   pub fn inhibit(&self, what: InhibitWhat, who: &str, why: &str, mode: InhibitMode) -> Result<Inhibitor, Error> {
+      block_on(self.inner.inhibit(what, who, why, mode))
+   }
+
+   /// This method is used to list the inhibitor locks of every process.
+   pub fn list_inhibitors(&self) -> Result<Vec<InhibitorInfo>, Error> {
+      block_on(self.inner.list_inhibitors())
+   }
+
+   /// This method is used to receive an event before the system sleeps or shuts down, and after it resumes or shutdown is cancelled.
+   /// The system waits for each sleep or shutdown event to be done with, until the events are dropped.
+   pub fn subscribe(&self, who: &str, why: &str) -> Result<PowerEvents, Error> {
+      block_on(self.inner.subscribe(who, why))
+   }
 }
@@ -0,0 +1,514 @@
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as Json};
+use thiserror::Error;
+use toml::Value as Toml;
+use crate::desktop::{DesktopError, PersistentData, configs::DesktopConf};
+use crate::helpers::{constants::{LOCAL_CONF, LOCAL_DATA}, json_to_toml, read_content, toml_to_json, write_content_atomic, Locked, Lockdown, lockdown::ACTION_ADD_WALLPAPER};
+use crate::system_settings::devices::Brightness;
+use crate::system_settings::fonts::{FontConf, FontManager};
+use crate::system_settings::sounds::{controllers::{DeviceControl, SinkController, SourceController}, sound_api::Handler};
+use crate::system_settings::theme::{AppearanceConf, CursorManager};
+
+const BUNDLE_FORMAT: &str = "libkoompi-settings";
+const BUNDLE_VERSION: u32 = 1;
+const HOME_PREFIX: &str = "~/";
+const WALLPAPERS_DIR: &str = "wallpapers";
+const LOCALE_CONF: &str = "locale.conf";
+const MIME_FILE: &str = "mimeapps.list";
+const DEFAULT_SINK: &str = "default_sink";
+const DEFAULT_SOURCE: &str = "default_source";
+const CURSOR_THEME: &str = "theme";
+const CURSOR_SIZE: &str = "size";
+
+/// Variants of settings captured in a bundle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BundleSection {
+    Desktop,
+    Wallpapers,
+    Appearance,
+    Fonts,
+    Cursor,
+    Locale,
+    MimeApps,
+    Sound,
+    Brightness,
+}
+
+impl BundleSection {
+    pub const ALL: [BundleSection; 9] = [
+        BundleSection::Desktop, BundleSection::Wallpapers, BundleSection::Appearance, BundleSection::Fonts, BundleSection::Cursor,
+        BundleSection::Locale, BundleSection::MimeApps, BundleSection::Sound, BundleSection::Brightness,
+    ];
+
+    /// Return the key of this section in the bundle document.
+    pub fn key(&self) -> &'static str {
+        match self {
+            BundleSection::Desktop => "desktop",
+            BundleSection::Wallpapers => "wallpapers",
+            BundleSection::Appearance => "appearance",
+            BundleSection::Fonts => "fonts",
+            BundleSection::Cursor => "cursor",
+            BundleSection::Locale => "locale",
+            BundleSection::MimeApps => "mimeapps",
+            BundleSection::Sound => "sound",
+            BundleSection::Brightness => "brightness",
+        }
+    }
+
+    fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|section| section.key() == key)
+    }
+
+    /// Return the lockdown action id guarding the import of this section, if any.
+    fn action(&self) -> Option<&'static str> {
+        match self {
+            BundleSection::Wallpapers => Some(ACTION_ADD_WALLPAPER),
+            _ => None,
+        }
+    }
+}
+
+impl Display for BundleSection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use BundleSection::*;
+        write!(f, "{}", match self {
+            Desktop => "Desktop",
+            Wallpapers => "Wallpapers",
+            Appearance => "Appearance",
+            Fonts => "Fonts",
+            Cursor => "Cursor",
+            Locale => "Language & Region",
+            MimeApps => "Default Applications",
+            Sound => "Sound",
+            Brightness => "Brightness",
+        })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BundleError {
+    #[error("not a settings bundle")]
+    NotABundle,
+    #[error("unsupported settings bundle version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("invalid {0} section: {1}")]
+    InvalidSection(BundleSection, String),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    DesktopError(#[from] DesktopError),
+    #[error(transparent)]
+    SettingsError(#[from] crate::error::Error),
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+}
+
+/// Structure of one setting an import changes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SettingChange {
+    pub section: BundleSection,
+    /// Dotted path of the setting, starting with the section key.
+    pub key: String,
+    pub current: Option<Json>,
+    pub imported: Json,
+}
+
+/// Structure of a portable snapshot of user settings, stored as one JSON document.
+///
+/// Paths inside the home directory are stored relative to it, so a bundle applies to a different user name.
+/// Importing adds and overwrites settings but never removes existing ones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SettingsBundle {
+    format: String,
+    version: u32,
+    sections: BTreeMap<String, Json>,
+}
+
+// Public API
+impl SettingsBundle {
+    /// Snapshot every section. Sections that can't be read on this machine are left out.
+    pub fn export() -> Self {
+        Self::export_sections(&BundleSection::ALL)
+    }
+
+    /// Snapshot the given sections. Sections that can't be read on this machine are left out.
+    pub fn export_sections(sections: &[BundleSection]) -> Self {
+        let sections = sections.iter().filter_map(|section| match export_section(*section) {
+            Ok(Some(value)) => Some((section.key().to_string(), portable_paths(value))),
+            Ok(None) => None,
+            Err(err) => {
+                log::warn!("skipping {} settings: {}", section, err);
+                None
+            }
+        }).collect();
+
+        Self {
+            format: BUNDLE_FORMAT.to_string(),
+            version: BUNDLE_VERSION,
+            sections,
+        }
+    }
+
+    /// Parse and validate a bundle document.
+    pub fn from_json(json: &str) -> Result<Self, BundleError> {
+        let bundle: Self = serde_json::from_str(json)?;
+        if bundle.format != BUNDLE_FORMAT {
+            Err(BundleError::NotABundle)
+        } else if bundle.version > BUNDLE_VERSION {
+            Err(BundleError::UnsupportedVersion(bundle.version))
+        } else {
+            Ok(bundle)
+        }
+    }
+
+    /// Return the bundle as a JSON document.
+    pub fn to_json(&self) -> Result<String, BundleError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read a bundle from a file.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, BundleError> {
+        Self::from_json(&read_content(path)?)
+    }
+
+    /// Write the bundle to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BundleError> {
+        Ok(write_content_atomic(path, &self.to_json()?)?)
+    }
+
+    /// Return the sections captured in this bundle.
+    pub fn sections(&self) -> Vec<BundleSection> {
+        self.sections.keys().filter_map(|key| BundleSection::from_key(key)).collect()
+    }
+
+    /// Return the snapshot of a section.
+    pub fn section(&self, section: BundleSection) -> Option<&Json> {
+        self.sections.get(section.key())
+    }
+
+    /// Compare this bundle against the current settings and return what importing it would change.
+    pub fn diff(&self) -> Vec<SettingChange> {
+        let current = Self::export_sections(&self.sections());
+        self.sections().into_iter().flat_map(|section| {
+            let mut changes = Vec::new();
+            diff_json(section, section.key().to_string(), current.section(section), self.section(section).unwrap(), &mut changes);
+            changes
+        }).collect()
+    }
+
+    /// Apply the sections that differ from the current settings and return the changes.
+    /// Sections whose action the administrator disabled are skipped. With `dry_run`, nothing is applied.
+    pub fn import(&self, dry_run: bool) -> Result<Vec<SettingChange>, BundleError> {
+        self.import_with(dry_run, &Lockdown::load())
+    }
+}
+
+// Private API
+impl SettingsBundle {
+    fn import_with(&self, dry_run: bool, lockdown: &Lockdown) -> Result<Vec<SettingChange>, BundleError> {
+        let locked: Vec<(BundleSection, Locked)> = self.sections().into_iter().filter_map(|section| {
+            section.action().and_then(|action| lockdown.check_action(action).err()).map(|err| (section, err))
+        }).collect();
+        locked.iter().for_each(|(section, err)| log::warn!("skipping {} settings: {}", section, err));
+
+        let mut changes = self.diff();
+        changes.retain(|change| locked.iter().all(|(section, _)| *section != change.section));
+        if !dry_run {
+            let mut changed: Vec<BundleSection> = changes.iter().map(|change| change.section).collect();
+            changed.dedup();
+            for section in changed {
+                import_section(section, expand_paths(self.section(section).unwrap().clone()))?;
+            }
+        }
+        Ok(changes)
+    }
+}
+
+fn export_section(section: BundleSection) -> Result<Option<Json>, BundleError> {
+    Ok(match section {
+        BundleSection::Desktop => Some(export_conf::<DesktopConf>()?),
+        BundleSection::Appearance => Some(export_conf::<AppearanceConf>()?),
+        BundleSection::Fonts => Some(export_conf::<FontConf>()?),
+        BundleSection::Wallpapers => {
+            let dir = LOCAL_DATA.join(WALLPAPERS_DIR);
+            if !dir.is_dir() {
+                return Ok(None);
+            }
+            let mut wallpapers = Map::new();
+            for entry in dir.read_dir()?.filter_map(|e| e.ok()).filter(|entry| entry.path().is_file()) {
+                if let Some(name) = entry.file_name().to_str() {
+                    wallpapers.insert(name.to_string(), Json::String(encode_base64(&fs::read(entry.path())?)));
+                }
+            }
+            Some(Json::Object(wallpapers))
+        }
+        BundleSection::Cursor => {
+            let current = CursorManager::new().current().clone();
+            Some(serde_json::json!({ CURSOR_THEME: current.theme, CURSOR_SIZE: current.size }))
+        }
+        BundleSection::Locale => match read_content(LOCAL_CONF.join(LOCALE_CONF)) {
+            Ok(content) => Some(Json::Object(parse_locale(&content).into_iter().map(|(key, val)| (key, Json::String(val.trim_matches('"').to_string()))).collect())),
+            Err(_) => None,
+        },
+        BundleSection::MimeApps => {
+            let path = LOCAL_CONF.join(MIME_FILE);
+            if !path.is_file() {
+                return Ok(None);
+            }
+            let mut ini = mimeapps_ini();
+            ini.load(&path).map_err(|err| BundleError::InvalidSection(section, err))?;
+            Some(Json::Object(ini.get_map_ref().iter().map(|(group, entries)| {
+                (group.clone(), Json::Object(entries.iter().filter_map(|(mime, apps)| apps.clone().map(|apps| (mime.clone(), Json::String(apps)))).collect()))
+            }).collect()))
+        }
+        BundleSection::Sound => {
+            let handler = Handler::connect("SettingsBundle").map_err(|err| BundleError::InvalidSection(section, format!("{:?}", err)))?;
+            let info = SinkController { handler }.get_server_info().map_err(|err| BundleError::InvalidSection(section, format!("{:?}", err)))?;
+            let mut sound = Map::new();
+            info.default_sink_name.map(|name| sound.insert(DEFAULT_SINK.to_string(), Json::String(name)));
+            info.default_source_name.map(|name| sound.insert(DEFAULT_SOURCE.to_string(), Json::String(name)));
+            Some(Json::Object(sound))
+        }
+        BundleSection::Brightness => {
+            let brightness = Brightness::new();
+            if brightness.get_max_percent() == 0 {
+                None
+            } else {
+                Some(Json::from(brightness.get_percent()))
+            }
+        }
+    })
+}
+
+fn import_section(section: BundleSection, value: Json) -> Result<(), BundleError> {
+    let invalid = |err: &dyn Display| BundleError::InvalidSection(section, err.to_string());
+    match section {
+        BundleSection::Desktop => import_conf::<DesktopConf>(section, value)?.save()?,
+        BundleSection::Appearance => import_conf::<AppearanceConf>(section, value)?.save()?,
+        BundleSection::Fonts => FontManager::new()?.set_config(import_conf::<FontConf>(section, value)?)?,
+        BundleSection::Wallpapers => {
+            let dir = LOCAL_DATA.join(WALLPAPERS_DIR);
+            fs::create_dir_all(&dir)?;
+            for (name, data) in value.as_object().ok_or_else(|| invalid(&"expected an object"))? {
+                // a bare file name, so the bundle can't write outside the wallpapers directory
+                if Path::new(name).file_name().and_then(|n| n.to_str()) != Some(name.as_str()) {
+                    return Err(invalid(&format!("invalid wallpaper name {}", name)));
+                }
+                let bytes = data.as_str().and_then(decode_base64).ok_or_else(|| invalid(&format!("invalid wallpaper data {}", name)))?;
+                fs::write(dir.join(name), bytes)?;
+            }
+        }
+        BundleSection::Cursor => {
+            let theme = value.get(CURSOR_THEME).and_then(Json::as_str).ok_or_else(|| invalid(&"missing theme"))?;
+            let size = value.get(CURSOR_SIZE).and_then(Json::as_u64).ok_or_else(|| invalid(&"missing size"))?;
+            let mut cursor_mn = CursorManager::new();
+            if cursor_mn.theme(theme).is_some() {
+                cursor_mn.set_cursor(theme, size as u32)?;
+            } else {
+                log::warn!("cursor theme {} is not installed, skipping", theme);
+            }
+        }
+        BundleSection::Locale => {
+            let path = LOCAL_CONF.join(LOCALE_CONF);
+            let imported = value.as_object().ok_or_else(|| invalid(&"expected an object"))?;
+            write_content_atomic(&path, &merge_locale(&read_content(&path).unwrap_or_default(), imported))?;
+        }
+        BundleSection::MimeApps => {
+            let path = LOCAL_CONF.join(MIME_FILE);
+            let mut ini = mimeapps_ini();
+            if path.is_file() {
+                ini.load(&path).map_err(|err| invalid(&err))?;
+            }
+            for (group, entries) in value.as_object().ok_or_else(|| invalid(&"expected an object"))? {
+                entries.as_object().into_iter().flatten().for_each(|(mime, apps)| {
+                    ini.set(group, mime, apps.as_str().map(ToString::to_string));
+                });
+            }
+            write_content_atomic(path, &ini.writes())?;
+        }
+        BundleSection::Sound => {
+            if let Some(name) = value.get(DEFAULT_SINK).and_then(Json::as_str) {
+                let handler = Handler::connect("SettingsBundle").map_err(|err| invalid(&format!("{:?}", err)))?;
+                set_default_device(SinkController { handler }, name);
+            }
+            if let Some(name) = value.get(DEFAULT_SOURCE).and_then(Json::as_str) {
+                let handler = Handler::connect("SettingsBundle").map_err(|err| invalid(&format!("{:?}", err)))?;
+                set_default_device(SourceController { handler }, name);
+            }
+        }
+        BundleSection::Brightness => {
+            let percent = value.as_u64().ok_or_else(|| invalid(&"expected a percentage"))?;
+            Brightness::new().set_percent(percent.min(100) as u32)?;
+        }
+    }
+    Ok(())
+}
+
+fn export_conf<T: PersistentData>() -> Result<Json, BundleError> {
+    Ok(toml_to_json(Toml::try_from(T::load()?).map_err(DesktopError::from)?))
+}
+
+/// Merge an imported config over the current one, so settings missing from older bundles keep their value.
+fn import_conf<T: PersistentData>(section: BundleSection, value: Json) -> Result<T, BundleError> {
+    let mut conf = export_conf::<T>()?;
+    merge_json(&mut conf, value);
+    json_to_toml(conf).and_then(|toml| toml.try_into().ok()).ok_or_else(|| BundleError::InvalidSection(section, String::from("doesn't match the settings format")))
+}
+
+/// Set a default audio device if it exists on this machine.
+fn set_default_device<C: DeviceControl<D>, D>(mut controller: C, name: &str) {
+    if controller.get_device_by_name(name).is_ok() {
+        let _ = controller.set_default_device(name);
+    } else {
+        log::warn!("audio device {} is not available, skipping", name);
+    }
+}
+
+/// Parse the variables of a `locale.conf`, keeping their values as written.
+fn parse_locale(content: &str) -> BTreeMap<String, String> {
+    content.lines().filter_map(|line| {
+        let mut kv = line.splitn(2, '=');
+        Some((kv.next()?.trim().to_string(), kv.next()?.trim().to_string()))
+    }).filter(|(key, _)| !key.is_empty() && !key.starts_with('#')).collect()
+}
+
+/// Write imported variables over a `locale.conf`, quoting the changed values. Unchanged values keep their quoting.
+fn merge_locale(content: &str, imported: &Map<String, Json>) -> String {
+    let mut locale = parse_locale(content);
+    imported.iter().filter_map(|(key, val)| val.as_str().map(|val| (key, val))).for_each(|(key, val)| {
+        if locale.get(key).map(|current| current.trim_matches('"')) != Some(val) {
+            locale.insert(key.clone(), format!("\"{}\"", val));
+        }
+    });
+    locale.iter().map(|(key, val)| format!("{}={}\n", key, val)).collect()
+}
+
+fn mimeapps_ini() -> configparser::ini::Ini {
+    let mut ini = configparser::ini::Ini::new_cs();
+    // application lists are separated by semicolons
+    ini.set_comment_symbols(&['#']);
+    ini
+}
+
+fn diff_json(section: BundleSection, key: String, current: Option<&Json>, imported: &Json, changes: &mut Vec<SettingChange>) {
+    match (current, imported) {
+        (Some(Json::Object(current)), Json::Object(imported)) => imported.iter().for_each(|(sub_key, val)| {
+            diff_json(section, format!("{}.{}", key, sub_key), current.get(sub_key), val, changes);
+        }),
+        (current, imported) if current != Some(imported) => changes.push(SettingChange {
+            section, key, current: current.cloned(), imported: imported.clone(),
+        }),
+        _ => {}
+    }
+}
+
+fn merge_json(base: &mut Json, value: Json) {
+    match (base, value) {
+        (Json::Object(base), Json::Object(map)) => map.into_iter().for_each(|(key, val)| match base.get_mut(&key) {
+            Some(base_val) => merge_json(base_val, val),
+            None => {
+                base.insert(key, val);
+            }
+        }),
+        (base, value) => *base = value,
+    }
+}
+
+/// Replace the home directory prefix of string values with `~/`.
+fn portable_paths(value: Json) -> Json {
+    let home = dirs_next::home_dir().unwrap_or_default();
+    map_strings(value, &|s| match Path::new(&s).strip_prefix(&home) {
+        Ok(rel) if home.has_root() => format!("{}{}", HOME_PREFIX, rel.display()),
+        _ => s,
+    })
+}
+
+fn expand_paths(value: Json) -> Json {
+    let home = dirs_next::home_dir().unwrap_or_default();
+    map_strings(value, &|s| match s.strip_prefix(HOME_PREFIX) {
+        Some(rel) => home.join(rel).display().to_string(),
+        None => s,
+    })
+}
+
+fn map_strings(value: Json, f: &dyn Fn(String) -> String) -> Json {
+    match value {
+        Json::String(s) => Json::String(f(s)),
+        Json::Array(arr) => Json::Array(arr.into_iter().map(|val| map_strings(val, f)).collect()),
+        Json::Object(map) => Json::Object(map.into_iter().map(|(key, val)| (key, map_strings(val, f))).collect()),
+        value => value,
+    }
+}
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(bytes: &[u8]) -> String {
+    bytes.chunks(3).flat_map(|chunk| {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (idx, &b)| n | u32::from(b) << (16 - 8 * idx));
+        (0..4).map(move |idx| if idx <= chunk.len() { BASE64_CHARS[(n >> (18 - 6 * idx) & 0x3f) as usize] as char } else { '=' })
+    }).collect()
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let digits = encoded.trim_end_matches('=').bytes().map(|c| BASE64_CHARS.iter().position(|&b| b == c).map(|pos| pos as u32)).collect::<Option<Vec<u32>>>()?;
+    if digits.len() % 4 == 1 {
+        return None;
+    }
+    Some(digits.chunks(4).flat_map(|chunk| {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (idx, &d)| n | d << (18 - 6 * idx));
+        (0..chunk.len() - 1).map(move |idx| (n >> (16 - 8 * idx)) as u8)
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_base64, diff_json, encode_base64, merge_locale, BundleSection, SettingsBundle, WALLPAPERS_DIR};
+    use serde_json::json;
+    use crate::helpers::{constants::LOCAL_DATA, Lockdown};
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(encode_base64(b"Koompi"), "S29vbXBp");
+        assert_eq!(encode_base64(b"Ko"), "S28=");
+        assert_eq!(decode_base64("S29vbXBp").unwrap(), b"Koompi");
+        assert_eq!(decode_base64("S28=").unwrap(), b"Ko");
+        assert_eq!(decode_base64("S$=="), None);
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let current = json!({ "LANG": "en_US.UTF-8", "LC_TIME": "km_KH.UTF-8" });
+        let imported = json!({ "LANG": "km_KH.UTF-8", "LC_TIME": "km_KH.UTF-8", "LC_NUMERIC": "km_KH.UTF-8" });
+        let mut changes = Vec::new();
+        diff_json(BundleSection::Locale, String::from("locale"), Some(&current), &imported, &mut changes);
+        let keys: Vec<&str> = changes.iter().map(|change| change.key.as_str()).collect();
+        assert_eq!(keys, vec!["locale.LANG", "locale.LC_NUMERIC"]);
+        assert_eq!(changes[1].current, None);
+    }
+
+    #[test]
+    fn test_merge_locale() {
+        let content = "LANG=\"en_US.UTF-8\"\nLC_TIME=km_KH.UTF-8\n";
+        let unchanged = json!({ "LANG": "en_US.UTF-8", "LC_TIME": "km_KH.UTF-8" });
+        assert_eq!(merge_locale(content, unchanged.as_object().unwrap()), content);
+
+        let imported = json!({ "LC_TIME": "en_GB.UTF-8", "LC_NUMERIC": "km_KH.UTF-8" });
+        assert_eq!(merge_locale(content, imported.as_object().unwrap()), "LANG=\"en_US.UTF-8\"\nLC_NUMERIC=\"km_KH.UTF-8\"\nLC_TIME=\"en_GB.UTF-8\"\n");
+    }
+
+    #[test]
+    fn test_import_locked() {
+        let name = "libkoompi-test-locked.png";
+        let json = json!({ "format": "libkoompi-settings", "version": 1, "sections": { "wallpapers": { name: encode_base64(b"png") } } });
+        let bundle = SettingsBundle::from_json(&json.to_string()).unwrap();
+        let lockdown: Lockdown = toml::from_str("disabled_actions = [\"desktop.add-wallpaper\"]\n").unwrap();
+
+        assert!(bundle.import_with(false, &lockdown).unwrap().is_empty());
+        assert!(!LOCAL_DATA.join(WALLPAPERS_DIR).join(name).exists());
+        assert_eq!(bundle.import_with(true, &Lockdown::default()).unwrap().len(), 1);
+    }
+}
@@ -1,8 +1,11 @@
 pub mod bluetooth;
 pub mod datetime;
 pub mod devices;
+pub mod fonts;
 pub mod locale;
 pub mod network;
+pub mod privileged;
 pub mod sounds;
+pub mod theme;
 pub mod users_groups;
 pub use bluetooth::bluez_api_export;pub use sounds::controllers::{AppControl, DeviceControl, SinkController, SoundCard, SourceController};
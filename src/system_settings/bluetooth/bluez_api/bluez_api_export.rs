@@ -674,3 +674,28 @@ fn offset_to_propmap(offset: usize) -> PropMap {
     }
     map
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{BluetoothError, BluetoothSession, MacAddress};
+    use crate::error::Error;
+    use crate::test_support::MockService;
+
+    #[tokio::test]
+    async fn test_get_adapters() -> Result<(), BluetoothError> {
+        let bluez = match MockService::bluez() {
+            Ok(mock) => mock,
+            Err(Error::MissingTool(_)) => return Ok(()),
+            Err(err) => panic!("{}", err),
+        };
+        let (_, session) = BluetoothSession::new().await?;
+        let adapters = session.get_adapters().await?;
+        assert_eq!(adapters.len(), 1);
+        assert_eq!(adapters[0].mac_address, MacAddress("00:11:22:33:44:55".to_string()));
+        assert!(adapters[0].powered);
+
+        session.start_discovery().await?;
+        assert_eq!(bluez.calls_to("StartDiscovery").len(), 1);
+        Ok(())
+    }
+}
@@ -1,10 +1,17 @@
-use std::io::Error;
-use crate::helpers::{get_bool_yesno, exec_cmd, constants::PKEXEC};
+use crate::error::Error;
+use crate::helpers::{get_bool_yesno, read_lines, CommandLine, Runner};
+use super::privileged::{Operation, PrivilegedClient};
 use getset::{Getters};
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use itertools::Itertools;
 
+mod async_datetime;
+pub use async_datetime::AsyncDateTimeManager;
+
 const TIMEDATE_CTL: &str = "timedatectl";
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+const ZONE_TABS: [&str; 2] = ["/usr/share/zoneinfo/zone1970.tab", "/usr/share/zoneinfo/zone.tab"];
 
 /// Structure of DateTimeManager
 #[derive(Debug, Clone, Getters)]
@@ -21,6 +28,7 @@ pub struct DateTimeManager {
    rtc_time_usec: String,
    #[getset(get = "pub")]
    list_timezones: HashMap<String, Vec<String>>,
+   runner: Runner,
 }
 
 impl Default for DateTimeManager {
@@ -34,6 +42,7 @@ impl Default for DateTimeManager {
          time_usec: String::default(),
          rtc_time_usec: String::default(),
          list_timezones: HashMap::new(),
+         runner: Runner::default(),
       }
    }
 }
@@ -42,9 +51,14 @@ impl Default for DateTimeManager {
 impl DateTimeManager {
    /// Initialize method
    pub fn new() -> Result<Self, Error> {
-      let mut datetime_mn = Self::default();
+      Self::with_runner(Runner::default())
+   }
+
+   /// This method is used to initialize the manager running timedatectl through the given runner.
+   pub fn with_runner(runner: Runner) -> Result<Self, Error> {
+      let mut datetime_mn = Self { runner, ..Self::default() };
       datetime_mn.load_info()?;
-      let stdout = exec_cmd(TIMEDATE_CTL, vec!["list-timezones"])?;
+      let stdout = datetime_mn.runner.exec(&CommandLine::new(TIMEDATE_CTL, &["list-timezones"]))?;
       let mut ls_timezones: Vec<String> = stdout.lines().map(|line| line.trim().to_string()).collect();
       ls_timezones.push(String::from("Asia/Phnom_Penh"));
       ls_timezones.sort();
@@ -55,7 +69,7 @@ impl DateTimeManager {
    /// This method is used to set datetime manually.
    pub fn set_datetime(&mut self, datetime: &str) -> Result<bool, Error> {
       if !self.ntp {
-         exec_cmd(TIMEDATE_CTL, vec!["set-time", datetime])?;
+         self.runner.exec(&CommandLine::new(TIMEDATE_CTL, &["set-time", datetime]))?;
          self.time_usec = datetime.to_owned();
          self.load_info()?;
          Ok(true)
@@ -65,9 +79,10 @@ impl DateTimeManager {
    }
 
    /// This method is used to set timezone manually.
+   /// Fails with `Error::Validation` if the timezone isn't in the tz database.
    pub fn set_timezone(&mut self, tz: &str) -> Result<bool, Error> {
       if !self.ntp {
-         exec_cmd(PKEXEC, vec!["ln", "-sf", format!("/usr/share/zoneinfo/{}", tz).as_str(), "/etc/localtime"])?;
+         PrivilegedClient::new(self.runner.clone()).call(&Operation::SetTimezone { timezone: tz.to_owned() })?;
          self.timezone = tz.to_owned();
          self.load_info()?;
          Ok(true)
@@ -78,7 +93,7 @@ impl DateTimeManager {
 
    /// This method is used to enable/disable Network Time Protocol.
    pub fn set_ntp(&mut self, ntp: bool) -> Result<bool, Error> {
-      exec_cmd(TIMEDATE_CTL, vec!["set-ntp", format!("{}", ntp).as_str()])?;
+      self.runner.exec(&CommandLine::new(TIMEDATE_CTL, &["set-ntp", format!("{}", ntp).as_str()]))?;
       self.ntp = ntp;
       // system clock synchronized
       // self.ntp_sync = ntp;
@@ -89,7 +104,7 @@ impl DateTimeManager {
    /// This method is used to set Local RealTime Clock.
    pub fn set_local_rtc(&mut self, local_rtc: bool) -> Result<bool, Error> {
       if !self.ntp {
-         exec_cmd(TIMEDATE_CTL, vec!["set-local-rtc", if local_rtc {"true"} else {"0"}])?;
+         self.runner.exec(&CommandLine::new(TIMEDATE_CTL, &["set-local-rtc", if local_rtc {"true"} else {"0"}]))?;
          self.local_rtc = local_rtc;
          Ok(true)
       } else {
@@ -97,9 +112,22 @@ impl DateTimeManager {
       }
    }
 
+   /// This method is used to return (latitude, longitude) of the current timezone's principal location from the tz database.
+   pub fn coordinates(&self) -> Option<(f64, f64)> {
+      ZONE_TABS.iter().find_map(|tab| {
+         read_lines(tab).ok()?.map_while(Result::ok).filter(|line| !line.starts_with('#')).find_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            match (fields.get(1), fields.get(2)) {
+               (Some(coords), Some(tz)) if *tz == self.timezone => parse_iso6709(coords),
+               _ => None,
+            }
+         })
+      })
+   }
+
    /// This method is to load all info about date time.
    fn load_info(&mut self) -> Result<(), Error> {
-      let stdout = exec_cmd(TIMEDATE_CTL, vec!["show"])?;
+      let stdout = self.runner.exec(&CommandLine::new(TIMEDATE_CTL, &["show"]))?;
       stdout.lines().for_each(|line| {
          if line.starts_with("Timezone=") {
             self.timezone = line.split_at(9).1.trim().to_owned();
@@ -129,9 +157,69 @@ impl DateTimeManager {
    }
 }
 
+/// Return the tz database file of a timezone, failing with `Error::Validation` if it isn't in the database.
+pub(crate) fn zoneinfo_path(tz: &str) -> Result<PathBuf, Error> {
+   let zoneinfo = Path::new(ZONEINFO_DIR).join(tz);
+   if tz.is_empty() || tz.split('/').any(|part| part.is_empty() || part == "." || part == "..") || !zoneinfo.is_file() {
+      Err(Error::Validation(format!("unknown timezone: {}", tz)))
+   } else {
+      Ok(zoneinfo)
+   }
+}
+
+/// Parse ISO 6709 coordinates as written in zone.tab, e.g. `+1133+10455` or `-0708+11243`, to decimal degrees.
+fn parse_iso6709(coords: &str) -> Option<(f64, f64)> {
+   let split = coords.get(1..)?.find(['+', '-'])? + 1;
+   let (lat, lon) = coords.split_at(split);
+   Some((iso6709_to_degrees(lat, 2)?, iso6709_to_degrees(lon, 3)?))
+}
+
+/// Convert one signed ±DDMM[SS] (or ±DDDMM[SS] for longitude) component to decimal degrees.
+fn iso6709_to_degrees(val: &str, deg_len: usize) -> Option<f64> {
+   let sign = if val.starts_with('-') { -1.0 } else { 1.0 };
+   let digits = val.get(1..)?;
+   let deg: f64 = digits.get(..deg_len)?.parse().ok()?;
+   let min: f64 = digits.get(deg_len..deg_len + 2)?.parse().ok()?;
+   let sec: f64 = match digits.get(deg_len + 2..) {
+      Some(sec) if !sec.is_empty() => sec.parse().ok()?,
+      _ => 0.0,
+   };
+   Some(sign * (deg + min / 60.0 + sec / 3600.0))
+}
+
 #[cfg(test)]
 mod tests {
-   use super::DateTimeManager;
+   use super::{DateTimeManager, parse_iso6709};
+   use crate::helpers::{CommandOutput, FakeRunner, Runner};
+
+   #[test]
+   fn test_parse_iso6709() {
+      let (lat, lon) = parse_iso6709("+1133+10455").unwrap();
+      assert!((lat - 11.55).abs() < 1e-9);
+      assert!((lon - 104.916_666).abs() < 1e-5);
+      let (lat, lon) = parse_iso6709("-0708+11243").unwrap();
+      assert!(lat < 0.0 && lon > 112.0);
+      let (lat, lon) = parse_iso6709("+223800-0551750").unwrap();
+      assert!((lat - 22.633_333).abs() < 1e-5);
+      assert!((lon + 55.297_222).abs() < 1e-5);
+      assert_eq!(parse_iso6709("bogus"), None);
+   }
+
+   #[test]
+   fn test_load_info() {
+      let show = "Timezone=Asia/Phnom_Penh\nLocalRTC=no\nCanNTP=yes\nNTP=no\nNTPSynchronized=no\nTimeUSec=Mon 2021-03-01 10:00:00 +07\n";
+      let runner = FakeRunner::new().expect("timedatectl show", CommandOutput::ok(show)).expect("timedatectl list-timezones", CommandOutput::ok("America/Argentina/Salta\nAsia/Bangkok\nEurope/Paris\n")).expect("timedatectl set-ntp true", CommandOutput::ok(""));
+      let mut dt_mn = DateTimeManager::with_runner(Runner::new(runner.clone())).unwrap();
+      assert_eq!(dt_mn.timezone(), "Asia/Phnom_Penh");
+      assert!(!dt_mn.ntp());
+      assert_eq!(dt_mn.time_usec(), "Mon 2021-03-01 10:00:00 +07");
+      assert_eq!(dt_mn.list_timezones().get("Asia"), Some(&vec![String::from("Bangkok"), String::from("Phnom_Penh")]));
+      assert_eq!(dt_mn.list_timezones().get("America"), Some(&vec![String::from("Salta")]));
+      assert!(dt_mn.set_timezone("../../etc/passwd").is_err());
+
+      dt_mn.set_ntp(true).unwrap();
+      assert_eq!(runner.calls().iter().map(ToString::to_string).filter(|call| call.contains("set-ntp")).collect::<Vec<String>>(), vec!["timedatectl set-ntp true"]);
+   }
 
    #[test]
    fn test_dt_manager() {
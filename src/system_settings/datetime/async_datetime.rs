@@ -0,0 +1,89 @@
+use crate::error::Error;
+use crate::helpers::SystemBus;
+use super::zoneinfo_path;
+
+const TIMEDATE1_SERVICE: &str = "org.freedesktop.timedate1";
+const TIMEDATE1_PATH: &str = "/org/freedesktop/timedate1";
+const TIMEDATE1_INTERFACE: &str = TIMEDATE1_SERVICE;
+
+/// Structure of the async DateTime Manager, talking to systemd-timedated over the shared system bus connection.
+/// Setters let timedated ask the user to authenticate through polkit.
+#[derive(Debug, Clone, Default)]
+pub struct AsyncDateTimeManager;
+
+// Public API
+impl AsyncDateTimeManager {
+   /// Initialize method
+   pub fn new() -> Self {
+      Self
+   }
+
+   /// This method is used to return the system timezone.
+   pub async fn timezone(&self) -> Result<String, Error> {
+      self.get("Timezone").await
+   }
+
+   /// This method is used to check if the hardware clock is in local time.
+   pub async fn local_rtc(&self) -> Result<bool, Error> {
+      self.get("LocalRTC").await
+   }
+
+   /// This method is used to check if a Network Time Protocol service is available.
+   pub async fn can_ntp(&self) -> Result<bool, Error> {
+      self.get("CanNTP").await
+   }
+
+   /// This method is used to check if Network Time Protocol is enabled.
+   pub async fn ntp(&self) -> Result<bool, Error> {
+      self.get("NTP").await
+   }
+
+   /// This method is used to check if the system clock is synchronized.
+   pub async fn ntp_synchronized(&self) -> Result<bool, Error> {
+      self.get("NTPSynchronized").await
+   }
+
+   /// This method is used to return the system time in microseconds since the epoch.
+   pub async fn time_usec(&self) -> Result<u64, Error> {
+      self.get("TimeUSec").await
+   }
+
+   /// This method is used to list all timezones known to timedated.
+   pub async fn list_timezones(&self) -> Result<Vec<String>, Error> {
+      let (timezones,): (Vec<String>,) = SystemBus::shared()?.call(TIMEDATE1_SERVICE, TIMEDATE1_PATH, TIMEDATE1_INTERFACE, "ListTimezones", ()).await?;
+      Ok(timezones)
+   }
+
+   /// This method is used to set timezone.
+   /// Fails with `Error::Validation` if the timezone isn't in the tz database.
+   pub async fn set_timezone(&self, tz: &str) -> Result<(), Error> {
+      zoneinfo_path(tz)?;
+      self.call("SetTimezone", (tz, true)).await
+   }
+
+   /// This method is used to enable/disable Network Time Protocol.
+   pub async fn set_ntp(&self, ntp: bool) -> Result<(), Error> {
+      self.call("SetNTP", (ntp, true)).await
+   }
+
+   /// This method is used to set whether the hardware clock is in local time.
+   pub async fn set_local_rtc(&self, local_rtc: bool) -> Result<(), Error> {
+      self.call("SetLocalRTC", (local_rtc, false, true)).await
+   }
+
+   /// This method is used to set the system time, in microseconds since the epoch. Fails while NTP is enabled.
+   pub async fn set_time(&self, usec_utc: i64) -> Result<(), Error> {
+      self.call("SetTime", (usec_utc, false, true)).await
+   }
+}
+
+// Private API
+impl AsyncDateTimeManager {
+   async fn get<T: for<'b> dbus::arg::Get<'b> + 'static>(&self, prop: &str) -> Result<T, Error> {
+      SystemBus::shared()?.get(TIMEDATE1_SERVICE, TIMEDATE1_PATH, TIMEDATE1_INTERFACE, prop).await
+   }
+
+   async fn call<A: dbus::arg::AppendAll>(&self, method: &str, args: A) -> Result<(), Error> {
+      SystemBus::shared()?.call_interactive(TIMEDATE1_SERVICE, TIMEDATE1_PATH, TIMEDATE1_INTERFACE, method, args).await
+   }
+}
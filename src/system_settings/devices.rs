@@ -1,4 +1,10 @@
 mod brightness;
 mod hardware;
+mod hotplug;
+mod leds;
+mod power_supply;
 pub use brightness::*;
 pub use hardware::*;
+pub use hotplug::*;
+pub use leds::*;
+pub use power_supply::*;
@@ -1,20 +1,194 @@
 #![allow(dead_code)]
-use crate::helpers::{get_property, set_device_path};
-use dbus::blocking::Connection;
-use std::fs::OpenOptions;
-use std::io::prelude::*;
+mod store;
+pub use store::{BrightnessStore, SavedLevel};
+use super::PowerSource;
+use crate::error::Error;
+use crate::helpers::{block_on, Sysfs, SystemBus};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+const BACKLIGHT_CLASS: &str = "backlight";
+/// Interval between two steps of a fade, about one frame
+const FADE_STEP: Duration = Duration::from_millis(16);
+
+/// Kind of a backlight, ranked the way the kernel prefers them: firmware over platform over raw
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BacklightType {
+    /// Controlled through the firmware, e.g. `acpi_video0`
+    Firmware,
+    /// Controlled by a platform driver, e.g. a vendor laptop driver
+    Platform,
+    /// Direct register access of the graphics driver, e.g. `intel_backlight` or `amdgpu_bl0`
+    Raw,
+}
+
+impl std::str::FromStr for BacklightType {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "firmware" => Ok(Self::Firmware),
+            "platform" => Ok(Self::Platform),
+            "raw" => Ok(Self::Raw),
+            _ => Err(Error::Parse(format!("backlight type {}", s))),
+        }
+    }
+}
+
+/// Snapshot of a backlight under `/sys/class/backlight`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacklightInfo {
+    pub id: String,
+    pub kind: BacklightType,
+    pub max_brightness: u32,
+    pub brightness: u32,
+    /// Brightness reported by the hardware, which may lag behind or differ from the requested one
+    pub actual_brightness: u32,
+}
+
+impl BacklightInfo {
+    fn read(sysfs: &Sysfs, id: &str) -> Result<Self, Error> {
+        let brightness = sysfs.read_u32(BACKLIGHT_CLASS, id, "brightness")?;
+        Ok(Self {
+            id: id.to_string(),
+            kind: sysfs.read_attribute(BACKLIGHT_CLASS, id, "type")?.parse()?,
+            max_brightness: sysfs.read_u32(BACKLIGHT_CLASS, id, "max_brightness")?,
+            brightness,
+            actual_brightness: sysfs.read_u32(BACKLIGHT_CLASS, id, "actual_brightness").unwrap_or(brightness),
+        })
+    }
+}
+
+/// List the backlights of a sysfs tree, best ranked first. Unreadable devices are skipped.
+pub fn list_backlights(sysfs: &Sysfs) -> Vec<BacklightInfo> {
+    let mut devices: Vec<BacklightInfo> = sysfs
+        .list_devices(BACKLIGHT_CLASS)
+        .iter()
+        .filter_map(|id| match BacklightInfo::read(sysfs, id) {
+            Ok(info) => Some(info),
+            Err(e) => {
+                log::warn!("skipping backlight {}: {}", id, e);
+                None
+            }
+        })
+        .collect();
+    // list_devices is sorted by name, and the sort is stable
+    devices.sort_by_key(|info| info.kind);
+    devices
+}
+
+/// Mapping between brightness percentages and raw device values
+///
+/// A percentage `p` maps to `(p / 100) ^ exponent * max_brightness`, so an exponent above 1
+/// gives finer steps at the dark end, where the eye is most sensitive. The default exponent of 2 roughly
+/// follows perceived lightness, so each step looks about as large as the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BrightnessCurve {
+    pub exponent: f32,
+    /// Lowest raw value ever written, so the screen never goes fully dark. Use 0 to allow turning it off.
+    pub min_value: u32,
+}
+
+impl Default for BrightnessCurve {
+    fn default() -> Self {
+        Self { exponent: 2.0, min_value: 1 }
+    }
+}
+
+impl BrightnessCurve {
+    fn percent_to_value(&self, percent: f32, max: u32) -> u32 {
+        let value = (f32::powf(percent.clamp(0.0, 100.0) / 100.0, self.exponent) * max as f32).round() as u32;
+        value.max(self.min_value).min(max)
+    }
+    fn value_to_percent(&self, value: u32, max: u32) -> f32 {
+        if max == 0 {
+            0.0
+        } else {
+            f32::powf(value.min(max) as f32 / max as f32, 1.0 / self.exponent) * 100.0
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Brightness {
+    sysfs: Sysfs,
+    store_path: PathBuf,
+    curve: BrightnessCurve,
     device: BrightnessDevice,
+    /// Bumped whenever a fade starts or is cancelled, shared by all clones
+    fade_generation: Arc<AtomicU64>,
 }
 impl Brightness {
     pub fn new() -> Self {
+        Self::with_sysfs(Sysfs::default())
+    }
+    /// Control the best ranked backlight of another sysfs tree, e.g. a test fixture.
+    pub fn with_sysfs(sysfs: Sysfs) -> Self {
+        let id = list_backlights(&sysfs).into_iter().next().map_or_else(String::new, |info| info.id);
+        let curve = BrightnessCurve::default();
         Self {
-            device: BrightnessDevice::new(),
+            device: BrightnessDevice::new(sysfs.clone(), id, curve),
+            sysfs,
+            store_path: BrightnessStore::default_path(),
+            curve,
+            fade_generation: Arc::new(AtomicU64::new(0)),
         }
     }
-    pub fn set_percent(&mut self, percent: u32) {
-        self.device.set_bright(percent);
+    /// Save and restore levels in another store, e.g. in a test.
+    pub fn with_store_path<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.store_path = path.into();
+        self
+    }
+    pub fn curve(&self) -> BrightnessCurve {
+        self.curve
+    }
+    /// Change how percentages map to raw values. Fails with `Error::Validation` unless the exponent is positive.
+    pub fn set_curve(&mut self, curve: BrightnessCurve) -> Result<(), Error> {
+        if curve.exponent.is_finite() && curve.exponent > 0.0 {
+            self.curve = curve;
+            self.device.curve = curve;
+            Ok(())
+        } else {
+            Err(Error::Validation(format!("brightness exponent {} is not positive", curve.exponent)))
+        }
+    }
+    /// List every backlight, best ranked first.
+    pub fn devices(&self) -> Vec<BacklightInfo> {
+        list_backlights(&self.sysfs)
+    }
+    /// Id of the controlled backlight, empty if there is none.
+    pub fn device_id(&self) -> &str {
+        &self.device.id
+    }
+    /// Control another backlight. Fails with `Error::Validation` if there is no such device.
+    pub fn select(&mut self, id: &str) -> Result<(), Error> {
+        if self.devices().iter().any(|info| info.id == id) {
+            self.device = BrightnessDevice::new(self.sysfs.clone(), id.to_string(), self.curve);
+            Ok(())
+        } else {
+            Err(Error::Validation(format!("no backlight {}", id)))
+        }
+    }
+    /// Set every backlight to the same percentage, keeping the selected one current.
+    /// Every backlight is tried, and the first failure is returned.
+    pub fn set_percent_all(&mut self, percent: u32) -> Result<(), Error> {
+        let mut result = Ok(());
+        for info in self.devices() {
+            let res = if info.id == self.device.id {
+                self.device.set_bright(percent)
+            } else {
+                BrightnessDevice::new(self.sysfs.clone(), info.id, self.curve).set_bright(percent)
+            };
+            if result.is_ok() {
+                result = res;
+            }
+        }
+        result
+    }
+    /// Write a percentage to the backlight through sysfs. Fails if the session can't write the attribute.
+    pub fn set_percent(&mut self, percent: u32) -> Result<(), Error> {
+        self.device.set_bright(percent)
     }
     pub fn get_percent(&self) -> u32 {
         self.device.get_current_level()
@@ -22,158 +196,182 @@ impl Brightness {
     pub fn get_max_percent(&self) -> u32 {
         self.device.get_max_bright()
     }
-    pub fn login1_set_brightness(&mut self, level: u32) -> Result<(), Box<dyn std::error::Error>> {
-        match self.device.set_dbus_bright(level) {
-            Ok(()) => {}
-            Err(e) => eprintln!("Not crash but lovable error: {:?}", e),
-        }
-        Ok(())
+    pub fn login1_set_brightness(&mut self, level: u32) -> Result<(), Error> {
+        self.device.set_dbus_bright(level)
     }
-    pub fn save_data(&mut self) {
-        self.device.save_device_data();
+    pub async fn login1_set_brightness_async(&mut self, level: u32) -> Result<(), Error> {
+        self.device.set_dbus_bright_async(level).await
     }
-    pub fn restore(&mut self) -> Result<bool, Box<dyn std::error::Error>> {
-        self.device.restore();
+    /// Fade from the current brightness to `percent` over `duration` through logind, one step per frame.
+    ///
+    /// Starting another fade or calling `cancel_fade` on any clone stops this one where it got to.
+    /// Returns `false` if the fade was cancelled.
+    pub async fn fade_to(&mut self, percent: u32, duration: Duration) -> Result<bool, Error> {
+        if percent > 100 {
+            return Err(Error::Validation(format!("brightness {} is not between 0 - 100", percent)));
+        }
+        let generation = self.fade_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        self.device.refresh();
+        let start = self.device.curve.value_to_percent(self.device.current_brightness, self.device.max_brightness);
+        let steps = (duration.as_millis() / FADE_STEP.as_millis()).max(1) as u32;
+        for step in 1..=steps {
+            if step > 1 {
+                tokio::time::sleep(FADE_STEP).await;
+            }
+            if self.fade_generation.load(Ordering::SeqCst) != generation {
+                return Ok(false);
+            }
+            let level = start + (percent as f32 - start) * step as f32 / steps as f32;
+            let value = self.device.curve.percent_to_value(level, self.device.max_brightness);
+            if value != self.device.current_brightness {
+                self.device.set_dbus_value_async(value).await?;
+            }
+        }
         Ok(true)
     }
+    /// Blocking version of `fade_to`.
+    pub fn fade_to_blocking(&mut self, percent: u32, duration: Duration) -> Result<bool, Error> {
+        block_on(self.fade_to(percent, duration))
+    }
+    /// Stop the running fade, if any.
+    pub fn cancel_fade(&self) {
+        self.fade_generation.fetch_add(1, Ordering::SeqCst);
+    }
+    /// Remember the current level of the backlight for the current power source.
+    pub fn save_data(&mut self) -> Result<(), Error> {
+        let mut store = BrightnessStore::open(&self.store_path)?;
+        self.device.save(&mut store, PowerSource::detect(&self.sysfs))?;
+        store.save()
+    }
+    /// Restore the level saved for the current power source, e.g. at session start.
+    /// Returns `false` if no level was saved for this backlight and power source.
+    pub fn restore(&mut self) -> Result<bool, Error> {
+        let store = BrightnessStore::open(&self.store_path)?;
+        self.device.restore(&store, PowerSource::detect(&self.sysfs))
+    }
     pub fn information(&self) -> Vec<u32> {
         self.device.info()
     }
 }
 #[derive(Debug, Clone)]
 struct BrightnessDevice {
-    id: &'static str,
+    sysfs: Sysfs,
+    id: String,
     class: &'static str,
+    curve: BrightnessCurve,
     max_brightness: u32,
     current_brightness: u32,
 }
 
 impl BrightnessDevice {
-    fn new() -> Self {
-        let max_bright = get_property("max_brightness");
-        let cur_bright = get_property("brightness");
+    fn new(sysfs: Sysfs, id: String, curve: BrightnessCurve) -> Self {
+        let class = BACKLIGHT_CLASS;
+        let read = |attr: &str| match sysfs.read_u32(class, &id, attr) {
+            Ok(val) => val,
+            Err(e) => {
+                log::warn!("failed to read backlight {} {}: {}", id, attr, e);
+                0
+            }
+        };
+        let max_bright = read("max_brightness");
+        let cur_bright = read("brightness");
         Self {
-            id: "intel_backlight",
-            class: "backlight",
+            sysfs,
+            id,
+            class,
+            curve,
             max_brightness: max_bright,
             current_brightness: cur_bright,
         }
     }
-    fn save_device_data(&mut self) {
-        let file = OpenOptions::new().write(true).open(self.get_path());
-        match file {
-            Ok(mut pfile) => match pfile.write(&[self.current_brightness as u8]) {
-                Ok(data) => {
-                    println!("write to file {:?} bytes", data);
-                }
-                Err(e) => {
-                    eprintln!("Error: {:?}", e);
-                }
-            },
-            Err(e) => eprintln!("failed to open the file: {:?}", e),
+    // pick up changes made by others, e.g. the brightness keys
+    fn refresh(&mut self) {
+        if let Ok(cur_bright) = self.sysfs.read_u32(self.class, &self.id, "brightness") {
+            self.update(cur_bright);
         }
     }
+    fn save(&mut self, store: &mut BrightnessStore, source: PowerSource) -> Result<(), Error> {
+        self.check_device()?;
+        self.refresh();
+        store.set(self.class, &self.id, source, SavedLevel { value: self.current_brightness, max: self.max_brightness });
+        Ok(())
+    }
 
-    fn restore(&mut self) {
-        let mut buffer: String = String::new();
-        let file = OpenOptions::new().read(true).open(self.get_path());
-        match file {
-            Ok(mut pfile) => {
-                pfile.read_to_string(&mut buffer).unwrap();
-            }
-            Err(e) => println!("read data: {:?}", e),
-        }
-        println!("data: {}", buffer);
-        match buffer.parse::<u32>() {
-            Ok(data) => {
-                println!("memory buffer: {:?}", data);
-                self.current_brightness = data;
-            }
-            Err(e) => println!("{:?}", e),
+    fn restore(&mut self, store: &BrightnessStore, source: PowerSource) -> Result<bool, Error> {
+        self.check_device()?;
+        let saved = match store.get(self.class, &self.id, source) {
+            Some(saved) => saved,
+            None => return Ok(false),
         };
-        self.update(self.current_brightness);
-        self.set_bright(self.val_to_percent(self.current_brightness, true));
+        let value = saved.scaled_to(self.max_brightness).max(self.curve.min_value).min(self.max_brightness);
+        // logind works without write access to sysfs, which sessions usually lack
+        if let Err(e) = block_on(self.set_dbus_value_async(value)) {
+            log::debug!("restoring brightness through sysfs: {}", e);
+            self.sysfs.write_attribute(self.class, &self.id, "brightness", value)?;
+            self.update(value);
+        }
+        Ok(true)
     }
 
-    fn get_path(&self) -> std::path::PathBuf {
-        let mut xdg_dir: String = String::new();
-        match std::env::var("XDG_RUNTIME_DIR") {
-            Ok(xdg_path) => xdg_dir = xdg_path,
-            Err(e) => println!("path to xdg : {:?}", e),
+    fn check_device(&self) -> Result<(), Error> {
+        if self.id.is_empty() {
+            Err(Error::Validation(String::from("no backlight")))
+        } else {
+            Ok(())
         }
-        std::path::Path::new(&xdg_dir)
-            .join("brightnessctl")
-            .join(self.class)
-            .join(self.id)
     }
+
     // set current brightness of the system
-    fn set_bright(&mut self, level: u32) {
-        match set_device_path(format!("{}/{}", self.class, self.id).as_str()) {
-            Ok(mut dev) => {
-                // give time for cpu to execute on other processes
-                std::thread::sleep(std::time::Duration::from_millis(10));
-                let value = self.percent_to_value(level);
-                match dev.set_attribute_value("brightness", value.to_string()) {
-                    Ok(()) => {
-                        self.update(value);
-                    }
-                    Err(e) => {
-                        println!("Error: {:?}", e);
-                    }
-                }
-            }
-            Err(e) => eprint!("error: {:?}", e),
-        }
+    fn set_bright(&mut self, level: u32) -> Result<(), Error> {
+        self.check_device()?;
+        let value = self.percent_to_value(level);
+        self.sysfs.write_attribute(self.class, &self.id, "brightness", value)?;
+        self.update(value);
+        Ok(())
     }
     // return roundf(powf(percent / 100, p.exponent) * d->max_brightness);
-    // calculate percent value range 0..=100 by by round_up(f32::powf(input_v / 100, 1) * max_value, -2) as u32
     fn percent_to_value(&self, val: u32) -> u32 {
-        math::round::ceil(
-            (f32::powf(val as f32 / 100.0, 1.0) * self.get_max_bright() as f32) as f64,
-            -2,
-        ) as u32
-    }
-    // if (val < 0)
-    // 		return 0;
-    // 	float ret = powf(val / d->max_brightness, 1.0f / p.exponent) * 100;
-    // 	return rnd ? roundf(ret) : ret;
+        self.curve.percent_to_value(val as f32, self.get_max_bright())
+    }
+    // float ret = powf(val / d->max_brightness, 1.0f / p.exponent) * 100;
+    // return rnd ? roundf(ret) : ret;
     fn val_to_percent(&self, val: u32, rnd: bool) -> u32 {
-        if val.le(&0) {
-            1
+        let ret = self.curve.value_to_percent(val, self.get_max_bright());
+        if rnd {
+            ret.round() as u32
         } else {
-            let ret = f32::powf(val as f32 / self.get_max_bright() as f32, 1.0) * 100.0;
-            if rnd {
-                math::round::ceil(ret as f64, 2) as u32
-            } else {
-                ret as u32
-            }
+            ret as u32
         }
     }
     fn get_max_bright(&self) -> u32 {
         self.max_brightness
     }
-    fn set_dbus_bright(&mut self, level: u32) -> Result<(), Box<dyn std::error::Error>> {
-        if level.gt(&100) || level.lt(&0) {
-            Err(Box::new(std::io::Error::new(
-                std::io::ErrorKind::InvalidData,
-                "input value between 0 - 100",
-            )))
+    fn set_dbus_bright(&mut self, level: u32) -> Result<(), Error> {
+        block_on(self.set_dbus_bright_async(level))
+    }
+    async fn set_dbus_bright_async(&mut self, level: u32) -> Result<(), Error> {
+        if level.gt(&100) {
+            Err(Error::Validation(format!("brightness {} is not between 0 - 100", level)))
         } else {
-            let sys_conn = Connection::new_system().unwrap();
-            let proxy = sys_conn.with_proxy(
+            self.set_dbus_value_async(self.percent_to_value(level)).await
+        }
+    }
+    async fn set_dbus_value_async(&mut self, value: u32) -> Result<(), Error> {
+        SystemBus::shared()?
+            .proxy_with_timeout(
                 "org.freedesktop.login1",
                 "/org/freedesktop/login1/session/auto",
-                std::time::Duration::from_millis(100),
-            );
-            proxy.method_call(
+                Duration::from_millis(100),
+            )
+            .method_call::<(), _, _, _>(
                 "org.freedesktop.login1.Session",
                 "SetBrightness",
-                (self.class, self.id, self.percent_to_value(level)),
-            )?;
-            self.update(self.percent_to_value(level));
+                (self.class, self.id.as_str(), value),
+            )
+            .await?;
+        self.update(value);
 
-            Ok(())
-        }
+        Ok(())
     }
     fn get_current_level(&self) -> u32 {
         self.val_to_percent(self.current_brightness, true)
@@ -188,24 +386,146 @@ impl BrightnessDevice {
 
 #[cfg(test)]
 mod tests {
-    use super::Brightness;
+    use super::{BacklightType, Brightness, BrightnessCurve, BrightnessStore, SavedLevel};
+    use crate::system_settings::devices::PowerSource;
+    use crate::error::Error;
+    use crate::helpers::block_on;
     use std::time::Duration;
+    use crate::test_support::{MockService, SysfsFixture};
     #[test]
-    fn test_bright() {
-        let mut bright = Brightness::new();
-        // bright.login1_set_brightness(101);
-        println!("current : {}", bright.get_percent());
-        println!("max_percent: {}", bright.get_max_percent());
-        for i in 1..=100 {
-            std::thread::sleep(Duration::from_millis(10));
-            match bright.login1_set_brightness(i) {
-                Ok(()) => {}
-                Err(e) => println!("Error: {:?}", e),
-            }
-        }
+    fn test_bright() -> Result<(), Error> {
+        let fixture = SysfsFixture::new()?;
+        fixture.backlight("intel_backlight", "raw", 1000, 500);
+        let mut bright = Brightness::with_sysfs(fixture.sysfs());
+        assert_eq!(71, bright.get_percent());
+        assert_eq!(1000, bright.get_max_percent());
 
-        println!("current : {}", bright.get_percent());
+        bright.set_percent(80)?;
+        assert_eq!("640", fixture.read("backlight", "intel_backlight", "brightness"));
+        assert_eq!(80, bright.get_percent());
+
+        let login1 = match MockService::login1() {
+            Ok(mock) => mock,
+            Err(Error::MissingTool(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        assert!(bright.login1_set_brightness(101).is_err());
+        bright.login1_set_brightness(100)?;
         assert_eq!(100, bright.get_percent());
-        assert_eq!(2 + 2, 3);
+        let calls = login1.calls_to("SetBrightness");
+        assert_eq!(1, calls.len());
+        assert_eq!(("backlight", "intel_backlight", 1000), calls[0].read3::<&str, &str, u32>().unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn test_backlight_selection() -> Result<(), Error> {
+        let fixture = SysfsFixture::new()?;
+        fixture
+            .backlight("amdgpu_bl0", "raw", 255, 255)
+            .backlight("acpi_video0", "firmware", 100, 40)
+            .backlight("dell_backlight", "platform", 15, 15);
+        fixture.device("backlight", "acpi_video0", &[("actual_brightness", "38")]);
+        let mut bright = Brightness::with_sysfs(fixture.sysfs());
+        let devices = bright.devices();
+        let ids: Vec<&str> = devices.iter().map(|info| info.id.as_str()).collect();
+        assert_eq!(ids, vec!["acpi_video0", "dell_backlight", "amdgpu_bl0"]);
+        assert_eq!(devices[0].kind, BacklightType::Firmware);
+        assert_eq!(devices[0].actual_brightness, 38);
+        assert_eq!(bright.device_id(), "acpi_video0");
+        assert_eq!(63, bright.get_percent());
+
+        assert!(matches!(bright.select("intel_backlight"), Err(Error::Validation(_))));
+        bright.select("amdgpu_bl0")?;
+        assert_eq!(100, bright.get_percent());
+
+        bright.set_percent_all(0)?;
+        assert_eq!("1", fixture.read("backlight", "acpi_video0", "brightness"));
+        assert_eq!("1", fixture.read("backlight", "amdgpu_bl0", "brightness"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_brightness_curve() -> Result<(), Error> {
+        let fixture = SysfsFixture::new()?;
+        fixture.backlight("acpi_video0", "firmware", 15, 15);
+        let mut bright = Brightness::with_sysfs(fixture.sysfs());
+        bright.set_percent(50)?;
+        assert_eq!("4", fixture.read("backlight", "acpi_video0", "brightness"));
+        assert_eq!(52, bright.get_percent());
+
+        assert!(bright.set_curve(BrightnessCurve { exponent: 0.0, min_value: 1 }).is_err());
+        bright.set_curve(BrightnessCurve { exponent: 1.0, min_value: 2 })?;
+        bright.set_percent(50)?;
+        assert_eq!("8", fixture.read("backlight", "acpi_video0", "brightness"));
+        assert_eq!(53, bright.get_percent());
+        bright.set_percent(1)?;
+        assert_eq!("2", fixture.read("backlight", "acpi_video0", "brightness"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fade() -> Result<(), Error> {
+        let fixture = SysfsFixture::new()?;
+        fixture.backlight("intel_backlight", "raw", 1000, 100);
+        let login1 = match MockService::login1() {
+            Ok(mock) => mock,
+            Err(Error::MissingTool(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let mut bright = Brightness::with_sysfs(fixture.sysfs());
+        assert!(bright.fade_to_blocking(100, Duration::from_millis(100))?);
+        let values: Vec<u32> = login1.calls_to("SetBrightness").iter().map(|msg| msg.read3::<&str, &str, u32>().unwrap().2).collect();
+        assert!(values.len() > 1);
+        assert!(values.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(Some(&1000), values.last());
+        assert_eq!(100, bright.get_percent());
+
+        let mut fading = bright.clone();
+        let cancelled = block_on(async {
+            let fade = fading.fade_to(0, Duration::from_secs(10));
+            let cancel = async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                bright.cancel_fade();
+            };
+            futures::join!(fade, cancel).0
+        })?;
+        assert!(!cancelled);
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_restore() -> Result<(), Error> {
+        let fixture = SysfsFixture::new()?;
+        fixture.backlight("intel_backlight", "raw", 19393, 12000).battery("BAT0", 80, "Discharging").ac("AC", true);
+        let login1 = match MockService::login1() {
+            Ok(mock) => mock,
+            Err(Error::MissingTool(_)) => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let store_path = fixture.root().join("store").join("brightness.toml");
+        let mut bright = Brightness::with_sysfs(fixture.sysfs()).with_store_path(&store_path);
+        assert!(!bright.restore()?);
+
+        // values above 255 survive the round trip
+        bright.save_data()?;
+        fixture.ac("AC", false).backlight("intel_backlight", "raw", 19393, 3000);
+        bright.save_data()?;
+        let store = BrightnessStore::open(&store_path)?;
+        assert_eq!(store.get("backlight", "intel_backlight", PowerSource::Ac), Some(SavedLevel { value: 12000, max: 19393 }));
+        assert_eq!(store.get("backlight", "intel_backlight", PowerSource::Battery), Some(SavedLevel { value: 3000, max: 19393 }));
+
+        fixture.ac("AC", true);
+        assert!(bright.restore()?);
+        let calls = login1.calls_to("SetBrightness");
+        assert_eq!(("backlight", "intel_backlight", 12000), calls[0].read3::<&str, &str, u32>().unwrap());
+
+        // without logind the level is written to sysfs, scaled to the new range
+        login1.fail("org.freedesktop.login1.Session", "SetBrightness", "org.freedesktop.DBus.Error.AccessDenied", "denied");
+        fixture.backlight("intel_backlight", "raw", 1000, 0);
+        let mut bright = Brightness::with_sysfs(fixture.sysfs()).with_store_path(&store_path);
+        assert!(bright.restore()?);
+        assert_eq!("618", fixture.read("backlight", "intel_backlight", "brightness"));
+        Ok(())
     }
 }
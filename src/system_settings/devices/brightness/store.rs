@@ -0,0 +1,97 @@
+use crate::error::Error;
+use crate::helpers::{constants::LOCAL_DATA, write_content_atomic};
+use super::super::PowerSource;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+const STORE_FILE: &str = "brightness.toml";
+
+/// Raw level of a device when it was saved, together with the range it was saved in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedLevel {
+    pub value: u32,
+    pub max: u32,
+}
+
+impl SavedLevel {
+    /// Scale the level to a device whose range changed since, e.g. after a driver switch.
+    pub fn scaled_to(&self, max: u32) -> u32 {
+        if self.max == max || self.max == 0 {
+            self.value.min(max)
+        } else {
+            (u64::from(self.value) * u64::from(max) / u64::from(self.max)) as u32
+        }
+    }
+}
+
+/// Levels of one device, per power source
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct DeviceLevels {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ac: Option<SavedLevel>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    battery: Option<SavedLevel>,
+}
+
+impl DeviceLevels {
+    fn level_mut(&mut self, source: PowerSource) -> &mut Option<SavedLevel> {
+        match source {
+            PowerSource::Ac => &mut self.ac,
+            PowerSource::Battery => &mut self.battery,
+        }
+    }
+}
+
+/// Saved brightness of backlights and LEDs, keyed by device class and id, and by power source.
+/// Stored as TOML in the user's data dir, so the levels survive reboots.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrightnessStore {
+    path: PathBuf,
+    devices: BTreeMap<String, BTreeMap<String, DeviceLevels>>,
+}
+
+impl BrightnessStore {
+    pub fn default_path() -> PathBuf {
+        LOCAL_DATA.join("koompi").join(STORE_FILE)
+    }
+
+    /// Load the store. A missing file is an empty store, and so is an invalid one, which is replaced on the next save.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let devices = match std::fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents).unwrap_or_else(|err| {
+                log::warn!("ignoring invalid brightness store {}: {}", path.display(), err);
+                BTreeMap::new()
+            }),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self { path, devices })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self, class: &str, id: &str, source: PowerSource) -> Option<SavedLevel> {
+        let levels = self.devices.get(class)?.get(id)?;
+        match source {
+            PowerSource::Ac => levels.ac,
+            PowerSource::Battery => levels.battery,
+        }
+    }
+
+    pub fn set(&mut self, class: &str, id: &str, source: PowerSource, level: SavedLevel) {
+        *self.devices.entry(class.to_string()).or_default().entry(id.to_string()).or_default().level_mut(source) = Some(level);
+    }
+
+    /// Write the store atomically, creating its directory if needed.
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let contents = toml::to_string_pretty(&self.devices).map_err(|err| Error::Parse(format!("brightness store: {}", err)))?;
+        Ok(write_content_atomic(&self.path, &contents)?)
+    }
+}
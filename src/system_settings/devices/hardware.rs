@@ -1,17 +1,419 @@
-#![allow(unused_variables)]
-#![allow(unused_imports)]
-use udev::{Devices, Enumerator};
+mod hwdata;
+pub use hwdata::HwIds;
+use crate::error::Error;
+use crate::helpers::Sysfs;
+use hwdata::{PCI_IDS, USB_IDS};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use udev::{Device, Enumerator};
+
+const CPUINFO: &str = "/proc/cpuinfo";
+const MEMINFO: &str = "/proc/meminfo";
+/// Block devices which are no real storage
+const VIRTUAL_DISKS: [&str; 3] = ["loop", "ram", "zram"];
+
+/// Processor of the machine
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CpuInfo {
+    pub model: String,
+    /// Physical cores
+    pub cores: u32,
+    /// Logical processors, i.e. cores times threads per core
+    pub threads: u32,
+    pub min_frequency_mhz: Option<u32>,
+    pub max_frequency_mhz: Option<u32>,
+    /// Average current frequency of all logical processors
+    pub current_frequency_mhz: Option<u32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryInfo {
+    pub total_bytes: u64,
+    pub available_bytes: u64,
+    pub swap_bytes: u64,
+}
+
+/// Disk, e.g. `sda` or `nvme0n1`, without its partitions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageDevice {
+    pub name: String,
+    pub devnode: Option<PathBuf>,
+    pub model: Option<String>,
+    /// Bus the disk is attached to, e.g. `ata`, `usb` or `nvme`
+    pub bus: Option<String>,
+    pub size_bytes: u64,
+    pub rotational: bool,
+    pub removable: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuInfo {
+    /// DRM card, e.g. `card0`
+    pub card: String,
+    pub vendor: Option<String>,
+    pub product: Option<String>,
+    pub driver: Option<String>,
+    /// Whether the firmware used this GPU to boot, i.e. it drives the internal display
+    pub boot_vga: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PciDevice {
+    /// Slot, e.g. `0000:00:02.0`
+    pub slot: String,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    /// Class code, e.g. `0x030000` for a VGA controller
+    pub class: u32,
+    pub vendor: Option<String>,
+    pub product: Option<String>,
+    pub driver: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbDevice {
+    /// Port path, e.g. `1-2.1`
+    pub port: String,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub vendor: Option<String>,
+    pub product: Option<String>,
+}
+
+/// DMI identification of the machine
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SystemInfo {
+    pub vendor: Option<String>,
+    pub product_name: Option<String>,
+    pub product_version: Option<String>,
+    pub board_vendor: Option<String>,
+    pub board_name: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FirmwareInfo {
+    pub vendor: Option<String>,
+    pub version: Option<String>,
+    pub date: Option<String>,
+}
+
+/// Everything `HardwareManager` knows about the machine, e.g. for an "About this computer" page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardwareInfo {
+    pub cpu: CpuInfo,
+    pub memory: MemoryInfo,
+    pub storage: Vec<StorageDevice>,
+    pub gpus: Vec<GpuInfo>,
+    pub pci_devices: Vec<PciDevice>,
+    pub usb_devices: Vec<UsbDevice>,
+    pub system: SystemInfo,
+    pub firmware: FirmwareInfo,
+}
+
+/// Structure of Hardware Manager, taking inventory of the hardware through udev, sysfs and procfs
+#[derive(Debug, Clone)]
+pub struct HardwareManager {
+    sysfs: Sysfs,
+}
+
+impl Default for HardwareManager {
+    fn default() -> Self {
+        Self::with_sysfs(Sysfs::default())
+    }
+}
+
+// Public API
+impl HardwareManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This method is used to read DMI and CPU frequencies from another sysfs tree, e.g. a test fixture.
+    /// Devices are always enumerated through udev, which only knows the real tree.
+    pub fn with_sysfs(sysfs: Sysfs) -> Self {
+        Self { sysfs }
+    }
+
+    /// This method is used to take the whole inventory.
+    pub fn info(&self) -> Result<HardwareInfo, Error> {
+        Ok(HardwareInfo {
+            cpu: self.cpu()?,
+            memory: self.memory()?,
+            storage: self.storage()?,
+            gpus: self.gpus()?,
+            pci_devices: self.pci_devices()?,
+            usb_devices: self.usb_devices()?,
+            system: self.system(),
+            firmware: self.firmware(),
+        })
+    }
+
+    pub fn cpu(&self) -> Result<CpuInfo, Error> {
+        let mut cpu = parse_cpuinfo(&std::fs::read_to_string(CPUINFO)?);
+        let (min, max, current) = self.cpu_frequencies();
+        cpu.min_frequency_mhz = min;
+        cpu.max_frequency_mhz = max.or(cpu.max_frequency_mhz);
+        cpu.current_frequency_mhz = current.or(cpu.current_frequency_mhz);
+        Ok(cpu)
+    }
+
+    pub fn memory(&self) -> Result<MemoryInfo, Error> {
+        Ok(parse_meminfo(&std::fs::read_to_string(MEMINFO)?))
+    }
+
+    /// This method is used to list the disks, skipping loop devices and RAM disks.
+    pub fn storage(&self) -> Result<Vec<StorageDevice>, Error> {
+        let mut enumerator = Enumerator::new()?;
+        enumerator.match_subsystem("block")?;
+        enumerator.match_property("DEVTYPE", "disk")?;
+        Ok(enumerator
+            .scan_devices()?
+            .filter(|dev| {
+                let name = dev.sysname().to_string_lossy();
+                !VIRTUAL_DISKS.iter().any(|prefix| name.starts_with(prefix))
+            })
+            .filter_map(|dev| {
+                // the size attribute counts 512 byte sectors, whatever the sector size of the disk
+                let size_bytes = attribute(&dev, "size")?.parse::<u64>().ok()? * 512;
+                if size_bytes == 0 {
+                    return None;
+                }
+                Some(StorageDevice {
+                    name: dev.sysname().to_string_lossy().into_owned(),
+                    devnode: dev.devnode().map(PathBuf::from),
+                    model: property(&dev, "ID_MODEL").map(|model| model.replace('_', " ")).or_else(|| attribute(&dev, "device/model")),
+                    bus: property(&dev, "ID_BUS").or_else(|| property(&dev, "ID_PATH").and_then(|path| path.split('-').next().map(String::from))),
+                    size_bytes,
+                    rotational: attribute(&dev, "queue/rotational").as_deref() == Some("1"),
+                    removable: attribute(&dev, "removable").as_deref() == Some("1"),
+                })
+            })
+            .collect())
+    }
+
+    pub fn gpus(&self) -> Result<Vec<GpuInfo>, Error> {
+        let mut enumerator = Enumerator::new()?;
+        enumerator.match_subsystem("drm")?;
+        enumerator.match_sysname("card[0-9]*")?;
+        Ok(enumerator
+            .scan_devices()?
+            // connectors, e.g. card0-eDP-1, are children of the card
+            .filter(|dev| !dev.sysname().to_string_lossy().contains('-'))
+            .map(|dev| {
+                let parent = dev.parent();
+                let pci = parent.as_ref().and_then(pci_device);
+                GpuInfo {
+                    card: dev.sysname().to_string_lossy().into_owned(),
+                    vendor: pci.as_ref().and_then(|pci| pci.vendor.clone()),
+                    product: pci.as_ref().and_then(|pci| pci.product.clone()),
+                    driver: parent.as_ref().and_then(|parent| parent.driver()).map(lossy),
+                    boot_vga: parent.as_ref().and_then(|parent| attribute(parent, "boot_vga")).as_deref() == Some("1"),
+                }
+            })
+            .collect())
+    }
+
+    pub fn pci_devices(&self) -> Result<Vec<PciDevice>, Error> {
+        let mut enumerator = Enumerator::new()?;
+        enumerator.match_subsystem("pci")?;
+        Ok(enumerator.scan_devices()?.filter_map(|dev| pci_device(&dev)).collect())
+    }
+
+    pub fn usb_devices(&self) -> Result<Vec<UsbDevice>, Error> {
+        let mut enumerator = Enumerator::new()?;
+        enumerator.match_subsystem("usb")?;
+        enumerator.match_property("DEVTYPE", "usb_device")?;
+        Ok(enumerator
+            .scan_devices()?
+            .filter_map(|dev| {
+                let vendor_id = parse_hex(&attribute(&dev, "idVendor")?)? as u16;
+                let product_id = parse_hex(&attribute(&dev, "idProduct")?)? as u16;
+                Some(UsbDevice {
+                    port: dev.sysname().to_string_lossy().into_owned(),
+                    vendor_id,
+                    product_id,
+                    vendor: property(&dev, "ID_VENDOR_FROM_DATABASE").or_else(|| attribute(&dev, "manufacturer")).or_else(|| USB_IDS.vendor(vendor_id).map(String::from)),
+                    product: property(&dev, "ID_MODEL_FROM_DATABASE").or_else(|| attribute(&dev, "product")).or_else(|| USB_IDS.product(vendor_id, product_id).map(String::from)),
+                })
+            })
+            .collect())
+    }
+
+    /// This method is used to identify the machine. Virtual machines and some boards leave fields empty.
+    pub fn system(&self) -> SystemInfo {
+        SystemInfo {
+            vendor: self.dmi("sys_vendor"),
+            product_name: self.dmi("product_name"),
+            product_version: self.dmi("product_version"),
+            board_vendor: self.dmi("board_vendor"),
+            board_name: self.dmi("board_name"),
+        }
+    }
+
+    pub fn firmware(&self) -> FirmwareInfo {
+        FirmwareInfo {
+            vendor: self.dmi("bios_vendor"),
+            version: self.dmi("bios_version"),
+            date: self.dmi("bios_date"),
+        }
+    }
+}
+
+// Private API
+impl HardwareManager {
+    /// Read a DMI field, treating placeholders like `To Be Filled By O.E.M.` as missing.
+    fn dmi(&self, field: &str) -> Option<String> {
+        self.sysfs.read_attribute("dmi", "id", field).ok().filter(|val| !val.is_empty() && !is_placeholder(val))
+    }
+
+    /// Lowest minimum, highest maximum and average current frequency of all logical processors, in MHz.
+    fn cpu_frequencies(&self) -> (Option<u32>, Option<u32>, Option<u32>) {
+        let cpu_dir = self.sysfs.root().join("devices").join("system").join("cpu");
+        let read = |cpu: &str, attr: &str| std::fs::read_to_string(cpu_dir.join(cpu).join("cpufreq").join(attr)).ok().and_then(|val| val.trim().parse::<u32>().ok()).map(|khz| khz / 1000);
+        let cpus: Vec<String> = match std::fs::read_dir(&cpu_dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| name.strip_prefix("cpu").is_some_and(|num| !num.is_empty() && num.chars().all(|c| c.is_ascii_digit())))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        let current: Vec<u32> = cpus.iter().filter_map(|cpu| read(cpu, "scaling_cur_freq")).collect();
+        (
+            cpus.iter().filter_map(|cpu| read(cpu, "cpuinfo_min_freq")).min(),
+            cpus.iter().filter_map(|cpu| read(cpu, "cpuinfo_max_freq")).max(),
+            if current.is_empty() { None } else { Some(current.iter().sum::<u32>() / current.len() as u32) },
+        )
+    }
+}
+
+fn lossy(val: &OsStr) -> String {
+    val.to_string_lossy().trim().to_string()
+}
+
+fn property(dev: &Device, name: &str) -> Option<String> {
+    dev.property_value(name).map(lossy).filter(|val| !val.is_empty())
+}
+
+fn attribute(dev: &Device, name: &str) -> Option<String> {
+    dev.attribute_value(name).map(lossy).filter(|val| !val.is_empty())
+}
+
+fn parse_hex(val: &str) -> Option<u32> {
+    u32::from_str_radix(val.trim_start_matches("0x"), 16).ok()
+}
+
+fn is_placeholder(val: &str) -> bool {
+    let val = val.to_lowercase();
+    ["to be filled by o.e.m.", "default string", "system product name", "not applicable"].contains(&val.as_str())
+}
+
+fn pci_device(dev: &Device) -> Option<PciDevice> {
+    if dev.subsystem() != Some(OsStr::new("pci")) {
+        return None;
+    }
+    let vendor_id = parse_hex(&attribute(dev, "vendor")?)? as u16;
+    let device_id = parse_hex(&attribute(dev, "device")?)? as u16;
+    Some(PciDevice {
+        slot: dev.sysname().to_string_lossy().into_owned(),
+        vendor_id,
+        device_id,
+        class: attribute(dev, "class").and_then(|class| parse_hex(&class)).unwrap_or_default(),
+        vendor: property(dev, "ID_VENDOR_FROM_DATABASE").or_else(|| PCI_IDS.vendor(vendor_id).map(String::from)),
+        product: property(dev, "ID_MODEL_FROM_DATABASE").or_else(|| PCI_IDS.product(vendor_id, device_id).map(String::from)),
+        driver: dev.driver().map(lossy),
+    })
+}
+
+/// Parse `/proc/cpuinfo`. Frequencies are left to sysfs, except the current one of the first processor as a fallback.
+fn parse_cpuinfo(contents: &str) -> CpuInfo {
+    let mut cpu = CpuInfo::default();
+    let mut cores = HashSet::new();
+    let mut physical_id = String::new();
+    for line in contents.lines() {
+        let (key, val) = match line.split_once(':') {
+            Some((key, val)) => (key.trim(), val.trim()),
+            None => continue,
+        };
+        match key {
+            "processor" => cpu.threads += 1,
+            // x86 and most others, then ARM
+            "model name" | "Hardware" | "Model" if cpu.model.is_empty() => cpu.model = val.to_string(),
+            "physical id" => physical_id = val.to_string(),
+            "core id" => {
+                cores.insert((physical_id.clone(), val.to_string()));
+            }
+            "cpu MHz" if cpu.current_frequency_mhz.is_none() => cpu.current_frequency_mhz = val.parse::<f64>().ok().map(|mhz| mhz.round() as u32),
+            _ => {}
+        }
+    }
+    cpu.cores = if cores.is_empty() { cpu.threads } else { cores.len() as u32 };
+    cpu
+}
+
+fn parse_meminfo(contents: &str) -> MemoryInfo {
+    let mut memory = MemoryInfo::default();
+    for line in contents.lines() {
+        let mut fields = line.split_whitespace();
+        let (key, kib) = match (fields.next(), fields.next().and_then(|val| val.parse::<u64>().ok())) {
+            (Some(key), Some(kib)) => (key, kib),
+            _ => continue,
+        };
+        match key {
+            "MemTotal:" => memory.total_bytes = kib * 1024,
+            "MemAvailable:" => memory.available_bytes = kib * 1024,
+            "SwapTotal:" => memory.swap_bytes = kib * 1024,
+            _ => {}
+        }
+    }
+    memory
+}
 
 #[cfg(test)]
 mod tests {
+    use super::{parse_cpuinfo, parse_meminfo, HardwareManager, HwIds};
+    use crate::test_support::SysfsFixture;
+
+    #[test]
+    fn test_parse_proc() {
+        let cpuinfo = "processor\t: 0\nmodel name\t: Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz\ncpu MHz\t\t: 1799.999\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 1\nmodel name\t: Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz\nphysical id\t: 0\ncore id\t\t: 0\n\n\
+                       processor\t: 2\nmodel name\t: Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz\nphysical id\t: 0\ncore id\t\t: 1\n";
+        let cpu = parse_cpuinfo(cpuinfo);
+        assert_eq!((cpu.model.as_str(), cpu.cores, cpu.threads, cpu.current_frequency_mhz), ("Intel(R) Core(TM) i5-8250U CPU @ 1.60GHz", 2, 3, Some(1800)));
+
+        let memory = parse_meminfo("MemTotal:        8000000 kB\nMemFree:          100000 kB\nMemAvailable:    4000000 kB\nSwapTotal:             0 kB\n");
+        assert_eq!((memory.total_bytes, memory.available_bytes, memory.swap_bytes), (8_192_000_000, 4_096_000_000, 0));
+    }
+
     #[test]
-    fn it_works() {
-        // let device = udev::Device::from_syspath(std::path::Path::new("/sys/class/bluetooth"));
-        // device.iter().enumerate().for_each(|(key, value)| {
-        //     println!("{:?} : {:?}", key, value.sysname());
-        // });
-        // let enum_dev = udev::Enumerator::new().unwrap();
-
-        assert_eq!(2 + 2, 3);
+    fn test_hwdata() {
+        let ids = HwIds::parse("# comment\n8086  Intel Corporation\n\t3ea0  UHD Graphics 620 (Whiskey Lake)\n\t\t1028 0869  Vostro 3481\n1002  Advanced Micro Devices, Inc. [AMD/ATI]\nC 00  Unclassified device\n\t00  Non-VGA unclassified device\n");
+        assert_eq!(ids.vendor(0x8086), Some("Intel Corporation"));
+        assert_eq!(ids.product(0x8086, 0x3ea0), Some("UHD Graphics 620 (Whiskey Lake)"));
+        assert_eq!(ids.product(0x1002, 0x0000), None);
+        assert_eq!(ids.vendor(0x0000), None);
+    }
+
+    #[test]
+    fn test_dmi_and_frequencies() -> std::io::Result<()> {
+        let fixture = SysfsFixture::new()?;
+        fixture.device("dmi", "id", &[("sys_vendor", "KOOMPI"), ("product_name", "KOOMPI E13"), ("board_name", "To Be Filled By O.E.M."), ("bios_version", "1.07")]);
+        let cpu_dir = fixture.root().join("devices/system/cpu");
+        for (cpu, cur) in [("cpu0", "800000"), ("cpu1", "1600000")] {
+            std::fs::create_dir_all(cpu_dir.join(cpu).join("cpufreq"))?;
+            std::fs::write(cpu_dir.join(cpu).join("cpufreq/scaling_cur_freq"), cur)?;
+            std::fs::write(cpu_dir.join(cpu).join("cpufreq/cpuinfo_min_freq"), "400000")?;
+            std::fs::write(cpu_dir.join(cpu).join("cpufreq/cpuinfo_max_freq"), "3400000")?;
+        }
+        std::fs::create_dir_all(cpu_dir.join("cpufreq"))?;
+
+        let manager = HardwareManager::with_sysfs(fixture.sysfs());
+        let system = manager.system();
+        assert_eq!((system.vendor.as_deref(), system.product_name.as_deref(), system.board_name), (Some("KOOMPI"), Some("KOOMPI E13"), None));
+        assert_eq!(manager.firmware().version.as_deref(), Some("1.07"));
+        assert_eq!(manager.cpu_frequencies(), (Some(400), Some(3400), Some(1200)));
+        Ok(())
     }
 }
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// Directories the hwdata package installs the id databases into, depending on the distribution
+const HWDATA_DIRS: [&str; 2] = ["/usr/share/hwdata", "/usr/share/misc"];
+
+lazy_static::lazy_static! {
+    pub(super) static ref PCI_IDS: HwIds = HwIds::load("pci.ids");
+    pub(super) static ref USB_IDS: HwIds = HwIds::load("usb.ids");
+}
+
+/// Vendor and product names of a `pci.ids` or `usb.ids` database
+#[derive(Debug, Default)]
+pub struct HwIds {
+    vendors: HashMap<u16, (String, HashMap<u16, String>)>,
+}
+
+impl HwIds {
+    /// Load a database from the hwdata package. A missing database knows no names.
+    fn load(file: &str) -> Self {
+        HWDATA_DIRS.iter().map(|dir| PathBuf::from(dir).join(file)).find_map(|path| std::fs::read(path).ok()).map_or_else(Self::default, |contents| Self::parse(&String::from_utf8_lossy(&contents)))
+    }
+
+    /// Parse the vendor section of a database, where vendors start a line and their products are indented by one tab.
+    /// Subsystems and the class tables following the vendors are skipped.
+    pub fn parse(contents: &str) -> Self {
+        let mut vendors = HashMap::new();
+        let mut vendor: Option<u16> = None;
+        for line in contents.lines() {
+            if line.is_empty() || line.starts_with('#') || line.starts_with("\t\t") {
+                continue;
+            }
+            match line.strip_prefix('\t') {
+                Some(product) => {
+                    if let (Some(vendor), Some((id, name))) = (vendor, split_id(product)) {
+                        vendors.entry(vendor).or_insert_with(|| (String::new(), HashMap::new())).1.insert(id, name);
+                    }
+                }
+                None => match split_id(line) {
+                    Some((id, name)) => {
+                        vendors.insert(id, (name, HashMap::new()));
+                        vendor = Some(id);
+                    }
+                    // e.g. `C 00  Unclassified device`
+                    None => vendor = None,
+                },
+            }
+        }
+        Self { vendors }
+    }
+
+    pub fn vendor(&self, vendor: u16) -> Option<&str> {
+        self.vendors.get(&vendor).map(|(name, _)| name.as_str())
+    }
+
+    pub fn product(&self, vendor: u16, product: u16) -> Option<&str> {
+        self.vendors.get(&vendor)?.1.get(&product).map(String::as_str)
+    }
+}
+
+/// Split `8086  Intel Corporation` into its id and name.
+fn split_id(line: &str) -> Option<(u16, String)> {
+    let (id, name) = line.split_once("  ")?;
+    if id.len() != 4 {
+        return None;
+    }
+    Some((u16::from_str_radix(id, 16).ok()?, name.trim().to_string()))
+}
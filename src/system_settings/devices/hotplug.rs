@@ -0,0 +1,198 @@
+use crate::error::Error;
+use crate::helpers::RUNTIME;
+use futures::Stream;
+use std::collections::HashMap;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::unix::AsyncFd;
+use udev::{Device, EventType, MonitorBuilder};
+
+/// Device subsystems the managers react to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Subsystem {
+    Usb,
+    Input,
+    Block,
+    Drm,
+    PowerSupply,
+    Sound,
+    Backlight,
+    Other(String),
+}
+
+impl Subsystem {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Usb => "usb",
+            Self::Input => "input",
+            Self::Block => "block",
+            Self::Drm => "drm",
+            Self::PowerSupply => "power_supply",
+            Self::Sound => "sound",
+            Self::Backlight => "backlight",
+            Self::Other(name) => name,
+        }
+    }
+}
+
+impl From<&str> for Subsystem {
+    fn from(name: &str) -> Self {
+        match name {
+            "usb" => Self::Usb,
+            "input" => Self::Input,
+            "block" => Self::Block,
+            "drm" => Self::Drm,
+            "power_supply" => Self::PowerSupply,
+            "sound" => Self::Sound,
+            "backlight" => Self::Backlight,
+            _ => Self::Other(name.to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HotplugAction {
+    Add,
+    Remove,
+    /// Attributes changed, e.g. the charge of a battery or the media of a card reader
+    Change,
+    /// A driver was bound to the device
+    Bind,
+    /// The driver was unbound from the device
+    Unbind,
+    Unknown,
+}
+
+impl From<EventType> for HotplugAction {
+    fn from(event_type: EventType) -> Self {
+        match event_type {
+            EventType::Add => Self::Add,
+            EventType::Remove => Self::Remove,
+            EventType::Change => Self::Change,
+            EventType::Bind => Self::Bind,
+            EventType::Unbind => Self::Unbind,
+            EventType::Unknown => Self::Unknown,
+        }
+    }
+}
+
+/// Device event received from udev, after udev rules have run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HotplugEvent {
+    pub action: HotplugAction,
+    pub subsystem: Option<Subsystem>,
+    /// Kernel name, e.g. `sdb` or `card0-HDMI-A-1`
+    pub sysname: String,
+    pub syspath: PathBuf,
+    pub devnode: Option<PathBuf>,
+    pub devtype: Option<String>,
+    /// udev properties, e.g. `ID_MODEL` or `POWER_SUPPLY_CAPACITY`
+    pub properties: HashMap<String, String>,
+}
+
+impl HotplugEvent {
+    pub fn property(&self, name: &str) -> Option<&str> {
+        self.properties.get(name).map(String::as_str)
+    }
+
+    fn from_device(action: HotplugAction, dev: &Device) -> Self {
+        Self {
+            action,
+            subsystem: dev.subsystem().map(|subsystem| Subsystem::from(subsystem.to_string_lossy().as_ref())),
+            sysname: dev.sysname().to_string_lossy().into_owned(),
+            syspath: dev.syspath().to_path_buf(),
+            devnode: dev.devnode().map(PathBuf::from),
+            devtype: dev.devtype().map(|devtype| devtype.to_string_lossy().into_owned()),
+            properties: dev.properties().map(|entry| (entry.name().to_string_lossy().into_owned(), entry.value().to_string_lossy().into_owned())).collect(),
+        }
+    }
+}
+
+/// Monitor socket which may move to the thread polling it.
+/// libudev objects aren't thread safe, but only need to be used by one thread at a time.
+struct MonitorSocket(udev::MonitorSocket);
+
+unsafe impl Send for MonitorSocket {}
+
+impl AsRawFd for MonitorSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+/// Stream of udev device events, usable from any tokio runtime.
+/// Ends if the monitor socket fails.
+pub struct HotplugStream {
+    socket: AsyncFd<MonitorSocket>,
+}
+
+impl std::fmt::Debug for HotplugStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HotplugStream")
+    }
+}
+
+impl HotplugStream {
+    /// Listen for events of the given subsystems, or of every subsystem if none is given.
+    pub fn new(subsystems: &[Subsystem]) -> Result<Self, Error> {
+        let socket = subsystems.iter().try_fold(MonitorBuilder::new()?, |builder, subsystem| builder.match_subsystem(subsystem.as_str()))?.listen()?;
+        // register with the reactor of the background runtime, which keeps running whoever polls the stream
+        let _guard = RUNTIME.enter();
+        // the fd is owned by the socket moved into the AsyncFd, so it stays open until the stream is dropped.
+        // `AsyncFd::new` is deprecated from tokio 1.53.3 for the unsafe `AsyncFd::register`, which the older
+        // tokio versions this crate builds with lack; owning the fd is what `register` asks of the caller.
+        #[allow(deprecated)]
+        let socket = AsyncFd::new(MonitorSocket(socket))?;
+        Ok(Self { socket })
+    }
+}
+
+impl Stream for HotplugStream {
+    type Item = HotplugEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut guard = match self.socket.poll_read_ready_mut(cx) {
+                Poll::Ready(Ok(guard)) => guard,
+                Poll::Ready(Err(err)) => {
+                    log::warn!("udev monitor failed: {}", err);
+                    return Poll::Ready(None);
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            match guard.get_inner_mut().0.next() {
+                Some(event) => return Poll::Ready(Some(HotplugEvent::from_device(event.event_type().into(), &event))),
+                None => guard.clear_ready(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HotplugAction, HotplugStream, Subsystem};
+    use crate::helpers::block_on;
+    use futures::StreamExt;
+    use std::time::Duration;
+    use udev::EventType;
+
+    #[test]
+    fn test_hotplug_stream() {
+        for name in &["usb", "input", "block", "drm", "power_supply", "sound", "backlight", "hidraw"] {
+            assert_eq!(Subsystem::from(*name).as_str(), *name);
+        }
+        assert_eq!(Subsystem::from("hidraw"), Subsystem::Other(String::from("hidraw")));
+        assert_eq!(HotplugAction::from(EventType::Unbind), HotplugAction::Unbind);
+
+        // containers may not allow netlink sockets.
+        // No device has this subsystem, while batteries send change events every few percent.
+        let mut stream = match HotplugStream::new(&[Subsystem::Other(String::from("libkoompi-none"))]) {
+            Ok(stream) => stream,
+            Err(err) => return eprintln!("udev monitor unavailable, skipping: {}", err),
+        };
+        let event = block_on(async { tokio::time::timeout(Duration::from_millis(50), stream.next()).await });
+        assert!(event.is_err());
+    }
+}
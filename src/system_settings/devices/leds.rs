@@ -0,0 +1,200 @@
+use crate::error::Error;
+use crate::helpers::{block_on, Sysfs, SystemBus, RUNTIME};
+use dbus::message::MatchRule;
+use futures::channel::mpsc;
+use futures::StreamExt;
+use std::time::Duration;
+
+const LEDS_CLASS: &str = "leds";
+const UPOWER_SERVICE: &str = "org.freedesktop.UPower";
+const KBD_BACKLIGHT_PATH: &str = "/org/freedesktop/UPower/KbdBacklight";
+const KBD_BACKLIGHT_INTERFACE: &str = "org.freedesktop.UPower.KbdBacklight";
+const KBD_BACKLIGHT_CHANGED: &str = "BrightnessChangedWithSource";
+
+/// Kind of a LED, from the function part of its `devicename:color:function` name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LedKind {
+    KeyboardBacklight,
+    CapsLock,
+    NumLock,
+    ScrollLock,
+    Mute,
+    MicMute,
+    Other,
+}
+
+impl LedKind {
+    pub fn from_name(name: &str) -> Self {
+        match name.rsplit(':').next().unwrap_or_default() {
+            "kbd_backlight" => Self::KeyboardBacklight,
+            "capslock" => Self::CapsLock,
+            "numlock" => Self::NumLock,
+            "scrolllock" => Self::ScrollLock,
+            "mute" | "audio-mute" => Self::Mute,
+            "micmute" | "audio-micmute" => Self::MicMute,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Snapshot of a LED under `/sys/class/leds`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LedInfo {
+    pub id: String,
+    pub kind: LedKind,
+    pub brightness: u32,
+    pub max_brightness: u32,
+}
+
+/// Who changed the keyboard backlight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrightnessSource {
+    /// The hardware, e.g. a brightness key handled by the firmware
+    External,
+    /// A UPower client
+    Internal,
+}
+
+/// Change of the keyboard backlight reported by UPower
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KbdBacklightChange {
+    pub brightness: i32,
+    pub source: BrightnessSource,
+}
+
+/// Structure of LED Manager, controlling keyboard backlights and indicator LEDs
+#[derive(Debug, Clone)]
+pub struct LedManager {
+    sysfs: Sysfs,
+}
+
+impl Default for LedManager {
+    fn default() -> Self {
+        Self::with_sysfs(Sysfs::default())
+    }
+}
+
+// Public API
+impl LedManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// This method is used to control the LEDs of another sysfs tree, e.g. a test fixture.
+    pub fn with_sysfs(sysfs: Sysfs) -> Self {
+        Self { sysfs }
+    }
+
+    /// This method is used to list every LED, sorted by name. Unreadable LEDs are skipped.
+    pub fn leds(&self) -> Vec<LedInfo> {
+        self.sysfs.list_devices(LEDS_CLASS).iter().filter_map(|id| self.led(id).ok()).collect()
+    }
+
+    /// This method is used to read one LED.
+    pub fn led(&self, id: &str) -> Result<LedInfo, Error> {
+        Ok(LedInfo {
+            id: id.to_string(),
+            kind: LedKind::from_name(id),
+            brightness: self.sysfs.read_u32(LEDS_CLASS, id, "brightness")?,
+            max_brightness: self.sysfs.read_u32(LEDS_CLASS, id, "max_brightness")?,
+        })
+    }
+
+    /// This method is used to find the LEDs of one kind, e.g. the capslock LED of every keyboard.
+    pub fn leds_of_kind(&self, kind: LedKind) -> Vec<LedInfo> {
+        self.leds().into_iter().filter(|led| led.kind == kind).collect()
+    }
+
+    /// This method is used to find the keyboard backlight, if the machine has one.
+    pub fn keyboard_backlight(&self) -> Option<LedInfo> {
+        self.leds_of_kind(LedKind::KeyboardBacklight).into_iter().next()
+    }
+
+    /// This method is used to set the level of a LED through logind, which works without write access to sysfs.
+    /// Fails with `Error::Validation` if the level is above the LED's maximum.
+    pub async fn set_brightness_async(&self, id: &str, level: u32) -> Result<(), Error> {
+        let led = self.led(id)?;
+        if level > led.max_brightness {
+            return Err(Error::Validation(format!("brightness {} of {} is not between 0 - {}", level, id, led.max_brightness)));
+        }
+        SystemBus::shared()?
+            .proxy_with_timeout("org.freedesktop.login1", "/org/freedesktop/login1/session/auto", Duration::from_millis(100))
+            .method_call::<(), _, _, _>("org.freedesktop.login1.Session", "SetBrightness", (LEDS_CLASS, id, level))
+            .await?;
+        Ok(())
+    }
+
+    /// This method is used to set the level of a LED, blocking until logind replied.
+    pub fn set_brightness(&self, id: &str, level: u32) -> Result<(), Error> {
+        block_on(self.set_brightness_async(id, level))
+    }
+
+    /// This method is used to receive the keyboard backlight level whenever it changes, including through hardware keys.
+    /// The subscription ends at the first change after the receiver is dropped.
+    pub async fn watch_keyboard_backlight(&self) -> Result<mpsc::UnboundedReceiver<KbdBacklightChange>, Error> {
+        let conn = SystemBus::shared()?.connection();
+        let rule = MatchRule::new_signal(KBD_BACKLIGHT_INTERFACE, KBD_BACKLIGHT_CHANGED).with_sender(UPOWER_SERVICE).with_path(KBD_BACKLIGHT_PATH);
+        let (msg_match, mut signals) = conn.add_match(rule).await?.msg_stream();
+        let (tx, rx) = mpsc::unbounded();
+        RUNTIME.spawn(async move {
+            while let Some(msg) = signals.next().await {
+                if msg.interface().as_deref() != Some(KBD_BACKLIGHT_INTERFACE) || msg.member().as_deref() != Some(KBD_BACKLIGHT_CHANGED) {
+                    continue;
+                }
+                let change = match msg.read2::<i32, &str>() {
+                    Ok((brightness, "external")) => KbdBacklightChange { brightness, source: BrightnessSource::External },
+                    Ok((brightness, _)) => KbdBacklightChange { brightness, source: BrightnessSource::Internal },
+                    Err(err) => {
+                        log::warn!("ignoring keyboard backlight change: {}", err);
+                        continue;
+                    }
+                };
+                if tx.unbounded_send(change).is_err() {
+                    break;
+                }
+            }
+            let _ = conn.remove_match(msg_match.token()).await;
+        });
+        Ok(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BrightnessSource, KbdBacklightChange, LedKind, LedManager};
+    use crate::error::Error;
+    use crate::helpers::block_on;
+    use crate::test_support::{MockService, SysfsFixture};
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_leds() -> Result<(), Error> {
+        let fixture = SysfsFixture::new()?;
+        fixture.led("tpacpi::kbd_backlight", 2, 1).led("input3::capslock", 1, 0).led("platform::micmute", 1, 1).led("phy0-led", 1, 0);
+        let manager = LedManager::with_sysfs(fixture.sysfs());
+        assert_eq!(manager.leds().len(), 4);
+        assert_eq!(manager.led("phy0-led")?.kind, LedKind::Other);
+        assert_eq!(manager.leds_of_kind(LedKind::MicMute)[0].id, "platform::micmute");
+        let kbd = manager.keyboard_backlight().unwrap();
+        assert_eq!((kbd.id.as_str(), kbd.brightness, kbd.max_brightness), ("tpacpi::kbd_backlight", 1, 2));
+        assert!(matches!(manager.set_brightness("tpacpi::kbd_backlight", 3), Err(Error::Validation(_))));
+
+        let (login1, upower) = match (MockService::login1(), MockService::upower()) {
+            (Ok(login1), Ok(upower)) => (login1, upower),
+            (Err(Error::MissingTool(_)), _) | (_, Err(Error::MissingTool(_))) => return Ok(()),
+            (Err(e), _) | (_, Err(e)) => return Err(e),
+        };
+        manager.set_brightness("tpacpi::kbd_backlight", 2)?;
+        let calls = login1.calls_to("SetBrightness");
+        assert_eq!(("leds", "tpacpi::kbd_backlight", 2), calls[0].read3::<&str, &str, u32>().unwrap());
+
+        let change = block_on(async {
+            let mut changes = manager.watch_keyboard_backlight().await?;
+            upower.emit("/org/freedesktop/UPower/KbdBacklight", "org.freedesktop.UPower.KbdBacklight", "BrightnessChangedWithSource", vec![0i32.into(), "external".into()]);
+            tokio::time::timeout(Duration::from_secs(5), changes.next()).await.map_err(|_| Error::Validation(String::from("no change")))
+        })?;
+        assert_eq!(change, Some(KbdBacklightChange { brightness: 0, source: BrightnessSource::External }));
+        Ok(())
+    }
+}
@@ -0,0 +1,216 @@
+use crate::error::Error;
+use crate::helpers::Sysfs;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const POWER_SUPPLY_CLASS: &str = "power_supply";
+
+/// Power source the machine is running on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+impl PowerSource {
+    /// Detect the power source from `/sys/class/power_supply`.
+    /// Machines without a system battery, e.g. desktops, always run on AC.
+    pub fn detect(sysfs: &Sysfs) -> Self {
+        let read = |id: &str, attr: &str| sysfs.read_attribute(POWER_SUPPLY_CLASS, id, attr).ok();
+        let mut has_battery = false;
+        for id in sysfs.list_devices(POWER_SUPPLY_CLASS) {
+            // batteries of mice, keyboards and the like don't power the machine
+            if read(&id, "scope").as_deref() == Some("Device") {
+                continue;
+            }
+            match read(&id, "type").as_deref() {
+                Some("Battery") => has_battery |= read(&id, "present").as_deref() != Some("0"),
+                Some(_) if read(&id, "online").as_deref() == Some("1") => return Self::Ac,
+                _ => {}
+            }
+        }
+        if has_battery {
+            Self::Battery
+        } else {
+            Self::Ac
+        }
+    }
+}
+
+/// Kind of a power supply, following UPower's device types
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PowerSupplyKind {
+    Unknown,
+    LinePower,
+    Battery,
+    Ups,
+    Mouse,
+    Keyboard,
+    Phone,
+    Tablet,
+    /// Any other peripheral, e.g. a headset or a gaming controller
+    Other,
+}
+
+impl From<u32> for PowerSupplyKind {
+    fn from(kind: u32) -> Self {
+        match kind {
+            0 => Self::Unknown,
+            1 => Self::LinePower,
+            2 => Self::Battery,
+            3 => Self::Ups,
+            5 => Self::Mouse,
+            6 => Self::Keyboard,
+            8 => Self::Phone,
+            10 => Self::Tablet,
+            _ => Self::Other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BatteryState {
+    Unknown,
+    Charging,
+    Discharging,
+    Empty,
+    FullyCharged,
+    /// Plugged in, but not charging, e.g. because of a charge threshold
+    PendingCharge,
+    PendingDischarge,
+}
+
+impl From<u32> for BatteryState {
+    fn from(state: u32) -> Self {
+        match state {
+            1 => Self::Charging,
+            2 => Self::Discharging,
+            3 => Self::Empty,
+            4 => Self::FullyCharged,
+            5 => Self::PendingCharge,
+            6 => Self::PendingDischarge,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Snapshot of a battery or another power supply
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryInfo {
+    /// Kernel name, e.g. `BAT0` or `AC`
+    pub id: String,
+    pub kind: PowerSupplyKind,
+    pub present: bool,
+    /// Whether it powers the machine, unlike the battery of a mouse
+    pub power_supply: bool,
+    pub percentage: f64,
+    pub state: BatteryState,
+    pub time_to_empty: Option<Duration>,
+    pub time_to_full: Option<Duration>,
+    /// Rate of charge or discharge in W
+    pub energy_rate: f64,
+    /// Energy when full in Wh, which drops as the battery wears
+    pub energy_full: f64,
+    /// Energy when full as designed in Wh
+    pub energy_full_design: f64,
+    pub cycle_count: Option<u32>,
+}
+
+impl BatteryInfo {
+    /// Capacity left of the designed capacity in percent, if the battery reports both.
+    pub fn health(&self) -> Option<f64> {
+        if self.energy_full > 0.0 && self.energy_full_design > 0.0 {
+            Some(self.energy_full / self.energy_full_design * 100.0)
+        } else {
+            None
+        }
+    }
+
+    /// Read a power supply from sysfs, converting charge to energy for batteries which only report charge.
+    pub fn from_sysfs(sysfs: &Sysfs, id: &str) -> Result<Self, Error> {
+        let kind = match sysfs.read_attribute(POWER_SUPPLY_CLASS, id, "type")?.as_str() {
+            "Battery" => PowerSupplyKind::Battery,
+            "UPS" => PowerSupplyKind::Ups,
+            "Mains" | "USB" | "Wireless" => PowerSupplyKind::LinePower,
+            _ => PowerSupplyKind::Unknown,
+        };
+        let read = |attr: &str| sysfs.read_attribute(POWER_SUPPLY_CLASS, id, attr).ok();
+        // sysfs reports micro units, i.e. µWh, µAh, µW, µA and µV
+        let num = |attr: &str| read(attr).and_then(|val| val.parse::<f64>().ok()).map(|val| val / 1e6);
+        let voltage = num("voltage_min_design").or_else(|| num("voltage_now"));
+        let energy = |name: &str| num(&format!("energy_{}", name)).or_else(|| Some(num(&format!("charge_{}", name))? * voltage?));
+        let (energy_now, energy_full) = (energy("now"), energy("full").unwrap_or_default());
+        let energy_rate = num("power_now").or_else(|| Some(num("current_now")? * num("voltage_now")?)).unwrap_or_default().abs();
+        let state = match read("status").as_deref() {
+            Some("Charging") => BatteryState::Charging,
+            Some("Discharging") => BatteryState::Discharging,
+            Some("Full") => BatteryState::FullyCharged,
+            Some("Not charging") => BatteryState::PendingCharge,
+            _ => BatteryState::Unknown,
+        };
+        let hours = |energy: Option<f64>| energy.filter(|_| energy_rate > 0.0).map(|energy| Duration::from_secs((energy / energy_rate * 3600.0) as u64));
+
+        Ok(Self {
+            id: id.to_string(),
+            kind,
+            present: read("present").as_deref() != Some("0"),
+            power_supply: read("scope").as_deref() != Some("Device"),
+            percentage: read("capacity").and_then(|val| val.parse().ok()).or_else(|| energy_now.filter(|_| energy_full > 0.0).map(|now| now / energy_full * 100.0)).unwrap_or_default(),
+            state,
+            time_to_empty: if state == BatteryState::Discharging { hours(energy_now) } else { None },
+            time_to_full: if state == BatteryState::Charging { hours(energy_now.map(|now| energy_full - now)) } else { None },
+            energy_rate,
+            energy_full,
+            energy_full_design: energy("full_design").unwrap_or_default(),
+            cycle_count: read("cycle_count").and_then(|val| val.parse().ok()).filter(|count| *count > 0),
+        })
+    }
+}
+
+/// List the power supplies of a sysfs tree, sorted by name. Unreadable ones are skipped.
+pub fn list_power_supplies(sysfs: &Sysfs) -> Vec<BatteryInfo> {
+    sysfs.list_devices(POWER_SUPPLY_CLASS).iter().filter_map(|id| BatteryInfo::from_sysfs(sysfs, id).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list_power_supplies, BatteryState, PowerSource, PowerSupplyKind};
+    use crate::test_support::SysfsFixture;
+    use std::time::Duration;
+
+    #[test]
+    fn test_detect_power_source() -> std::io::Result<()> {
+        let fixture = SysfsFixture::new()?;
+        assert_eq!(PowerSource::detect(&fixture.sysfs()), PowerSource::Ac);
+        fixture.battery("BAT0", 50, "Discharging").ac("AC", false);
+        fixture.battery("hidpp_battery_0", 90, "Discharging").power_supply("hidpp_battery_0", &[("scope", "Device")]);
+        assert_eq!(PowerSource::detect(&fixture.sysfs()), PowerSource::Battery);
+        fixture.ac("AC", true);
+        assert_eq!(PowerSource::detect(&fixture.sysfs()), PowerSource::Ac);
+        Ok(())
+    }
+
+    #[test]
+    fn test_battery_from_sysfs() -> std::io::Result<()> {
+        let fixture = SysfsFixture::new()?;
+        fixture.ac("AC", false).battery("BAT0", 50, "Discharging").power_supply("BAT0", &[
+            ("charge_now", "2000000"),
+            ("charge_full", "4000000"),
+            ("charge_full_design", "5000000"),
+            ("voltage_min_design", "11000000"),
+            ("voltage_now", "12000000"),
+            ("current_now", "1000000"),
+            ("cycle_count", "0"),
+        ]);
+        let supplies = list_power_supplies(&fixture.sysfs());
+        assert_eq!(supplies[0].kind, PowerSupplyKind::LinePower);
+        let battery = &supplies[1];
+        assert_eq!((battery.kind, battery.state, battery.percentage), (PowerSupplyKind::Battery, BatteryState::Discharging, 50.0));
+        assert_eq!((battery.energy_full, battery.energy_full_design, battery.energy_rate), (44.0, 55.0, 12.0));
+        assert_eq!(battery.health(), Some(80.0));
+        assert_eq!(battery.time_to_empty, Some(Duration::from_secs(6600)));
+        assert_eq!((battery.time_to_full, battery.cycle_count), (None, None));
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+mod font_conf;
+mod font_family;
+
+pub use font_conf::*;
+pub use font_family::{FontFamily, FontStyle, FontResource};
+use std::fs;
+use std::path::PathBuf;
+use crate::desktop::{DesktopError, PersistentData};
+use crate::helpers::{constants::LOCAL_CONF, write_content_overwrite};
+use super::theme::set_gtk_settings;
+use font_family::list_font_families;
+
+const FONTCONFIG_FRAGMENT: &str = "50-koompi.conf";
+const GTK_FONT_NAME: &str = "gtk-font-name";
+
+/// Structure of Font Manager
+#[derive(Debug, Clone)]
+pub struct FontManager {
+   conf: FontConf,
+   families: Vec<FontFamily>,
+}
+
+// Public API
+impl FontManager {
+   /// This method is used to load font settings and scan installed font families.
+   pub fn new() -> Result<Self, DesktopError> {
+      Ok(Self {
+         conf: FontConf::load()?,
+         families: list_font_families(),
+      })
+   }
+
+   /// This method is used to rescan installed font families.
+   pub fn refresh(&mut self) {
+      self.families = list_font_families();
+   }
+
+   /// This method is used to return all installed font families sorted by name.
+   pub fn families(&self) -> &[FontFamily] {
+      self.families.as_slice()
+   }
+
+   /// This method is used to return an installed font family by name.
+   pub fn family<T: AsRef<str>>(&self, name: T) -> Option<&FontFamily> {
+      self.families.iter().find(|family| family.name.eq_ignore_ascii_case(name.as_ref()))
+   }
+
+   /// This method is used to return current font settings.
+   pub fn config(&self) -> &FontConf {
+      &self.conf
+   }
+
+   /// This method is used to return the default font of a role.
+   pub fn font(&self, role: FontRole) -> &FontSetting {
+      self.conf.font(role)
+   }
+
+   /// This method is used to replace all font settings at once, with sizes and DPI clamped to the allowed range.
+   pub fn set_config(&mut self, conf: FontConf) -> Result<(), DesktopError> {
+      self.conf = conf;
+      FontRole::ALL.iter().for_each(|role| {
+         let font = self.conf.font_mut(*role);
+         font.size = font.size.clamp(FontConf::MIN_SIZE, FontConf::MAX_SIZE);
+      });
+      self.conf.dpi = self.conf.dpi.clamp(FontConf::MIN_DPI, FontConf::MAX_DPI);
+      self.apply()
+   }
+
+   /// This method is used to set the default font of a role, with the size clamped to the allowed range.
+   pub fn set_font<T: AsRef<str>>(&mut self, role: FontRole, family: T, size: f32) -> Result<(), DesktopError> {
+      let font = self.conf.font_mut(role);
+      font.family = family.as_ref().to_string();
+      font.size = size.clamp(FontConf::MIN_SIZE, FontConf::MAX_SIZE);
+      self.apply()
+   }
+
+   /// This method is used to set antialiasing, hinting style and subpixel order.
+   pub fn set_rendering(&mut self, antialias: bool, hinting: Hinting, subpixel: SubpixelOrder) -> Result<(), DesktopError> {
+      self.conf.antialias = antialias;
+      self.conf.hinting = hinting;
+      self.conf.subpixel = subpixel;
+      self.apply()
+   }
+
+   /// This method is used to set the font DPI, clamped to the allowed range.
+   pub fn set_dpi(&mut self, dpi: f32) -> Result<(), DesktopError> {
+      self.conf.dpi = dpi.clamp(FontConf::MIN_DPI, FontConf::MAX_DPI);
+      self.apply()
+   }
+
+   /// This method is used to set the fallback order of fonts for Khmer script.
+   pub fn set_khmer_fallback(&mut self, ls_families: Vec<&str>) -> Result<(), DesktopError> {
+      self.conf.khmer_fallback = ls_families.into_iter().map(ToString::to_string).collect();
+      self.apply()
+   }
+
+   /// This method is used to return the user fontconfig fragment written by this manager.
+   pub fn fontconfig_path() -> PathBuf {
+      LOCAL_CONF.join("fontconfig").join("conf.d").join(FONTCONFIG_FRAGMENT)
+   }
+}
+
+// Private API
+impl FontManager {
+   /// Save settings, regenerate the user fontconfig fragment and pass the interface font on to GTK.
+   fn apply(&self) -> Result<(), DesktopError> {
+      self.conf.save()?;
+      let path = Self::fontconfig_path();
+      if let Some(dir) = path.parent() {
+         fs::create_dir_all(dir)?;
+      }
+      write_content_overwrite(path, &self.conf.to_fontconfig())?;
+      set_gtk_settings(&[(GTK_FONT_NAME, self.conf.gtk_font_name())])?;
+      Ok(())
+   }
+}
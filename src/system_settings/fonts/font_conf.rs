@@ -0,0 +1,265 @@
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::desktop::PersistentData;
+
+const FONTS_CONF: &str = "fonts.toml";
+
+/// Structure of a font choice with its size in points.
+/// The interface size goes to GTK, the other sizes are for applications reading these settings.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontSetting {
+   pub family: String,
+   pub size: f32,
+}
+
+impl FontSetting {
+   fn new(family: &str, size: f32) -> Self {
+      Self { family: family.to_string(), size }
+   }
+}
+
+/// Variants of default font roles
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontRole {
+   Sans,
+   Serif,
+   Monospace,
+   Interface,
+}
+
+impl FontRole {
+   pub const ALL: [FontRole; 4] = [
+      FontRole::Sans, FontRole::Serif, FontRole::Monospace, FontRole::Interface
+   ];
+
+   /// Return the fontconfig generic family this role configures.
+   pub fn generic_family(&self) -> &'static str {
+      match self {
+         FontRole::Sans => "sans-serif",
+         FontRole::Serif => "serif",
+         FontRole::Monospace => "monospace",
+         FontRole::Interface => "system-ui",
+      }
+   }
+}
+
+impl Display for FontRole {
+   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      use FontRole::*;
+      write!(f, "{}", match self {
+         Sans => "Sans Serif",
+         Serif => "Serif",
+         Monospace => "Monospace",
+         Interface => "Interface",
+      })
+   }
+}
+
+/// Variants of font hinting style
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Hinting {
+   None,
+   Slight,
+   Medium,
+   Full,
+}
+
+impl Hinting {
+   pub const ALL: [Hinting; 4] = [
+      Hinting::None, Hinting::Slight, Hinting::Medium, Hinting::Full
+   ];
+
+   fn fc_const(&self) -> &'static str {
+      match self {
+         Hinting::None => "hintnone",
+         Hinting::Slight => "hintslight",
+         Hinting::Medium => "hintmedium",
+         Hinting::Full => "hintfull",
+      }
+   }
+}
+
+impl Display for Hinting {
+   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      use Hinting::*;
+      write!(f, "{}", match self {
+         None => "None",
+         Slight => "Slight",
+         Medium => "Medium",
+         Full => "Full",
+      })
+   }
+}
+
+/// Variants of LCD subpixel order used for subpixel antialiasing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SubpixelOrder {
+   None,
+   Rgb,
+   Bgr,
+   Vrgb,
+   Vbgr,
+}
+
+impl SubpixelOrder {
+   pub const ALL: [SubpixelOrder; 5] = [
+      SubpixelOrder::None, SubpixelOrder::Rgb, SubpixelOrder::Bgr, SubpixelOrder::Vrgb, SubpixelOrder::Vbgr
+   ];
+
+   fn fc_const(&self) -> &'static str {
+      match self {
+         SubpixelOrder::None => "none",
+         SubpixelOrder::Rgb => "rgb",
+         SubpixelOrder::Bgr => "bgr",
+         SubpixelOrder::Vrgb => "vrgb",
+         SubpixelOrder::Vbgr => "vbgr",
+      }
+   }
+}
+
+impl Display for SubpixelOrder {
+   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+      use SubpixelOrder::*;
+      write!(f, "{}", match self {
+         None => "None",
+         Rgb => "RGB",
+         Bgr => "BGR",
+         Vrgb => "Vertical RGB",
+         Vbgr => "Vertical BGR",
+      })
+   }
+}
+
+/// Structure of font settings. Plain values come before the font tables so the TOML serializer accepts it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FontConf {
+   pub antialias: bool,
+   pub hinting: Hinting,
+   pub subpixel: SubpixelOrder,
+   pub dpi: f32,
+   /// Fonts tried in order for Khmer script, after the chosen family of each role.
+   pub khmer_fallback: Vec<String>,
+   pub sans: FontSetting,
+   pub serif: FontSetting,
+   pub monospace: FontSetting,
+   pub interface: FontSetting,
+}
+
+impl Default for FontConf {
+   fn default() -> Self {
+      Self {
+         antialias: true,
+         hinting: Hinting::Slight,
+         subpixel: SubpixelOrder::Rgb,
+         dpi: Self::DEF_DPI,
+         khmer_fallback: vec!["Noto Sans Khmer", "Khmer OS System", "Hanuman", "Battambang"].into_iter().map(ToString::to_string).collect(),
+         sans: FontSetting::new("Noto Sans", Self::DEF_SIZE),
+         serif: FontSetting::new("Noto Serif", Self::DEF_SIZE),
+         monospace: FontSetting::new("Noto Sans Mono", Self::DEF_SIZE),
+         interface: FontSetting::new("Noto Sans", Self::DEF_SIZE),
+      }
+   }
+}
+
+impl FontConf {
+   pub const MIN_SIZE: f32 = 6.0;
+   pub const DEF_SIZE: f32 = 10.0;
+   pub const MAX_SIZE: f32 = 72.0;
+   pub const MIN_DPI: f32 = 48.0;
+   pub const DEF_DPI: f32 = 96.0;
+   pub const MAX_DPI: f32 = 480.0;
+
+   /// Return the font setting of a role.
+   pub fn font(&self, role: FontRole) -> &FontSetting {
+      match role {
+         FontRole::Sans => &self.sans,
+         FontRole::Serif => &self.serif,
+         FontRole::Monospace => &self.monospace,
+         FontRole::Interface => &self.interface,
+      }
+   }
+
+   pub(super) fn font_mut(&mut self, role: FontRole) -> &mut FontSetting {
+      match role {
+         FontRole::Sans => &mut self.sans,
+         FontRole::Serif => &mut self.serif,
+         FontRole::Monospace => &mut self.monospace,
+         FontRole::Interface => &mut self.interface,
+      }
+   }
+
+   /// Return the interface font as a Pango font description, e.g. `Noto Sans 10`, as GTK expects in `gtk-font-name`.
+   pub fn gtk_font_name(&self) -> String {
+      format!("{} {}", self.interface.family, self.interface.size)
+   }
+
+   /// Render these settings as a fontconfig configuration fragment.
+   /// Fontconfig leaves sizes to the applications, so only families, rendering and DPI are part of it.
+   pub fn to_fontconfig(&self) -> String {
+      let bool_edit = |name: &str, val: bool| format!("    <edit name=\"{}\" mode=\"assign\"><bool>{}</bool></edit>\n", name, val);
+      let const_edit = |name: &str, val: &str| format!("    <edit name=\"{}\" mode=\"assign\"><const>{}</const></edit>\n", name, val);
+      let families = |ls: &[&str]| ls.iter().map(|family| format!("<family>{}</family>", escape_xml(family))).collect::<String>();
+      let khmer: Vec<&str> = self.khmer_fallback.iter().map(AsRef::as_ref).collect();
+
+      let mut conf = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE fontconfig SYSTEM \"fonts.dtd\">\n<!-- Generated by libkoompi; changes will be overwritten. -->\n<fontconfig>\n");
+      conf.push_str("  <match target=\"font\">\n");
+      conf.push_str(&bool_edit("antialias", self.antialias));
+      conf.push_str(&bool_edit("hinting", self.hinting != Hinting::None));
+      conf.push_str(&const_edit("hintstyle", self.hinting.fc_const()));
+      conf.push_str(&const_edit("rgba", self.subpixel.fc_const()));
+      conf.push_str(&const_edit("lcdfilter", if self.subpixel == SubpixelOrder::None { "lcdnone" } else { "lcddefault" }));
+      conf.push_str("  </match>\n");
+      conf.push_str(&format!("  <match target=\"pattern\">\n    <edit name=\"dpi\" mode=\"assign\"><double>{}</double></edit>\n  </match>\n", self.dpi));
+      if !khmer.is_empty() {
+         conf.push_str(&format!("  <match target=\"pattern\">\n    <test name=\"lang\" compare=\"contains\"><string>km</string></test>\n    <edit name=\"family\" mode=\"prepend\">{}</edit>\n  </match>\n", khmer.iter().map(|family| format!("<string>{}</string>", escape_xml(family))).collect::<String>()));
+      }
+      FontRole::ALL.iter().for_each(|role| {
+         let mut prefer = vec![self.font(*role).family.as_str()];
+         prefer.extend(khmer.iter());
+         conf.push_str(&format!("  <alias binding=\"strong\">\n    <family>{}</family>\n    <prefer>{}</prefer>\n  </alias>\n", role.generic_family(), families(&prefer)));
+      });
+      conf.push_str("</fontconfig>\n");
+      conf
+   }
+}
+
+impl PersistentData for FontConf {
+   fn relative_path() -> PathBuf {
+      PathBuf::from("fonts").join(FONTS_CONF)
+   }
+}
+
+fn escape_xml(val: &str) -> String {
+   val.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{FontConf, FontSetting, Hinting, SubpixelOrder};
+
+   #[test]
+   fn test_to_fontconfig() {
+      let mut conf = FontConf { hinting: Hinting::None, subpixel: SubpixelOrder::None, dpi: 120.0, khmer_fallback: vec![String::from("Khmer OS & Co")], ..FontConf::default() };
+      conf.monospace = FontSetting::new("Fira <Mono>", 11.5);
+      let fontconfig = conf.to_fontconfig();
+      assert!(fontconfig.contains("<edit name=\"hinting\" mode=\"assign\"><bool>false</bool></edit>"));
+      assert!(fontconfig.contains("<const>hintnone</const>"));
+      assert!(fontconfig.contains("<const>lcdnone</const>"));
+      assert!(fontconfig.contains("<double>120</double>"));
+      assert!(fontconfig.contains("<edit name=\"family\" mode=\"prepend\"><string>Khmer OS &amp; Co</string></edit>"));
+      assert!(fontconfig.contains("<family>monospace</family>\n    <prefer><family>Fira &lt;Mono&gt;</family><family>Khmer OS &amp; Co</family></prefer>"));
+      assert!(fontconfig.contains("<family>system-ui</family>\n    <prefer><family>Noto Sans</family>"));
+
+      conf.khmer_fallback.clear();
+      assert!(!conf.to_fontconfig().contains("<string>km</string>"));
+   }
+
+   #[test]
+   fn test_gtk_font_name() {
+      let mut conf = FontConf::default();
+      assert_eq!(conf.gtk_font_name(), "Noto Sans 10");
+      conf.interface = FontSetting::new("Hanuman", 11.5);
+      assert_eq!(conf.gtk_font_name(), "Hanuman 11.5");
+   }
+}
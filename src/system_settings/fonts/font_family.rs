@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{prelude::*, Error, SeekFrom};
+use std::path::{Path, PathBuf};
+use crate::helpers::Resources;
+
+const FONTS_DIR: &str = "fonts";
+const FONT_EXTS: [&str; 4] = ["ttf", "otf", "ttc", "otc"];
+const TTC_TAG: u32 = 0x7474_6366; // "ttcf"
+const NAME_TAG: u32 = 0x6E61_6D65; // "name"
+/// Upper bounds guarding against corrupt files.
+const MAX_FACES: u32 = 64;
+const MAX_NAME_TABLE: usize = 1 << 20;
+/// Name IDs of the font family/subfamily and their typographic variants.
+const NAME_FAMILY: u16 = 1;
+const NAME_SUBFAMILY: u16 = 2;
+const NAME_TYPO_FAMILY: u16 = 16;
+const NAME_TYPO_SUBFAMILY: u16 = 17;
+const LANG_EN_US: u16 = 0x409;
+
+/// Structure of an installed font family
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FontFamily {
+   pub name: String,
+   pub styles: Vec<FontStyle>,
+}
+
+/// Structure of a single style (face) of a font family
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FontStyle {
+   pub name: String,
+   pub path: PathBuf,
+}
+
+impl FontFamily {
+   /// Return the style names of this family.
+   pub fn style_names(&self) -> Vec<&str> {
+      self.styles.iter().map(|style| style.name.as_str()).collect()
+   }
+}
+
+pub struct FontResource;
+impl Resources for FontResource {
+   fn relative_path() -> PathBuf {
+      PathBuf::from(FONTS_DIR)
+   }
+
+   fn additional_paths() -> Option<Vec<PathBuf>> {
+      dirs_next::home_dir().map(|home| vec![home.join(".fonts")])
+   }
+}
+
+/// Walk all font directories and group every TrueType/OpenType face by family, sorted by family name.
+pub(super) fn list_font_families() -> Vec<FontFamily> {
+   let mut families: HashMap<String, Vec<FontStyle>> = HashMap::new();
+   FontResource.paths().into_iter().for_each(|dir| {
+      walkdir::WalkDir::new(dir).follow_links(true).into_iter().filter_map(|e| e.ok()).filter(|entry| is_font_file(entry.path())).for_each(|entry| {
+         match read_font_names(entry.path()) {
+            Ok(faces) => faces.into_iter().for_each(|(family, style)| {
+               let styles = families.entry(family).or_default();
+               if !styles.iter().any(|s| s.name == style) {
+                  styles.push(FontStyle { name: style, path: entry.path().to_path_buf() });
+               }
+            }),
+            Err(err) => log::debug!("skipping font {}: {}", entry.path().display(), err),
+         }
+      });
+   });
+
+   let mut families: Vec<FontFamily> = families.into_iter().map(|(name, mut styles)| {
+      styles.sort();
+      FontFamily { name, styles }
+   }).collect();
+   families.sort();
+   families
+}
+
+fn is_font_file(path: &Path) -> bool {
+   path.is_file() && path.extension().and_then(|ext| ext.to_str()).is_some_and(|ext| FONT_EXTS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Read (family, style) names of every face in a TrueType/OpenType font or font collection.
+fn read_font_names<P: AsRef<Path>>(path: P) -> Result<Vec<(String, String)>, Error> {
+   let mut file = File::open(path)?;
+   let offsets = if read_u32(&mut file, 0)? == TTC_TAG {
+      let num_fonts = read_u32(&mut file, 8)?.min(MAX_FACES);
+      (0..num_fonts).map(|idx| read_u32(&mut file, 12 + 4 * u64::from(idx))).collect::<Result<Vec<u32>, Error>>()?
+   } else {
+      vec![0]
+   };
+
+   Ok(offsets.into_iter().filter_map(|offset| read_face_names(&mut file, u64::from(offset)).ok().flatten()).collect())
+}
+
+/// Locate the `name` table of the face starting at `offset` and read its family and style.
+fn read_face_names(file: &mut File, offset: u64) -> Result<Option<(String, String)>, Error> {
+   let num_tables = read_u16(file, offset + 4)?;
+   for idx in 0..u64::from(num_tables) {
+      let record = offset + 12 + 16 * idx;
+      if read_u32(file, record)? == NAME_TAG {
+         let table_offset = read_u32(file, record + 8)?;
+         let length = read_u32(file, record + 12)? as usize;
+         let mut table = vec![0; length.min(MAX_NAME_TABLE)];
+         file.seek(SeekFrom::Start(u64::from(table_offset)))?;
+         file.read_exact(&mut table)?;
+         return Ok(parse_name_table(&table));
+      }
+   }
+   Ok(None)
+}
+
+/// Parse the family and style out of a raw `name` table, preferring typographic names and US English records.
+fn parse_name_table(table: &[u8]) -> Option<(String, String)> {
+   let be16 = |pos: usize| table.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+   let count = be16(2)? as usize;
+   let storage = be16(4)? as usize;
+   // name ID => (rank, value), lower rank wins
+   let mut names: HashMap<u16, (u8, String)> = HashMap::new();
+
+   for idx in 0..count {
+      let rec = 6 + 12 * idx;
+      let (platform, encoding, language, name_id) = (be16(rec)?, be16(rec + 2)?, be16(rec + 4)?, be16(rec + 6)?);
+      let (length, offset) = (be16(rec + 8)? as usize, be16(rec + 10)? as usize);
+      if ![NAME_FAMILY, NAME_SUBFAMILY, NAME_TYPO_FAMILY, NAME_TYPO_SUBFAMILY].contains(&name_id) {
+         continue;
+      }
+      let bytes = match table.get(storage + offset..storage + offset + length) {
+         Some(bytes) => bytes,
+         None => continue,
+      };
+      let (rank, value) = match (platform, encoding) {
+         (3, 1) | (3, 10) | (0, _) => (if language == LANG_EN_US { 0 } else { 1 }, decode_utf16be(bytes)),
+         // Mac Roman, close enough to Latin-1 for font names
+         (1, 0) => (2, bytes.iter().map(|&b| b as char).collect()),
+         _ => continue,
+      };
+      if !value.trim().is_empty() && names.get(&name_id).is_none_or(|(best, _)| rank < *best) {
+         names.insert(name_id, (rank, value.trim().to_string()));
+      }
+   }
+
+   let family = names.get(&NAME_TYPO_FAMILY).or_else(|| names.get(&NAME_FAMILY))?.1.clone();
+   let style = names.get(&NAME_TYPO_SUBFAMILY).or_else(|| names.get(&NAME_SUBFAMILY)).map_or_else(|| String::from("Regular"), |(_, style)| style.clone());
+   Some((family, style))
+}
+
+fn decode_utf16be(bytes: &[u8]) -> String {
+   let units: Vec<u16> = bytes.chunks_exact(2).map(|b| u16::from_be_bytes([b[0], b[1]])).collect();
+   String::from_utf16_lossy(&units)
+}
+
+fn read_u32(file: &mut File, pos: u64) -> Result<u32, Error> {
+   let mut buf = [0; 4];
+   file.seek(SeekFrom::Start(pos))?;
+   file.read_exact(&mut buf)?;
+   Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u16(file: &mut File, pos: u64) -> Result<u16, Error> {
+   let mut buf = [0; 2];
+   file.seek(SeekFrom::Start(pos))?;
+   file.read_exact(&mut buf)?;
+   Ok(u16::from_be_bytes(buf))
+}
+
+#[cfg(test)]
+mod tests {
+   use super::parse_name_table;
+
+   fn name_record(platform: u16, encoding: u16, language: u16, name_id: u16, length: usize, offset: usize) -> Vec<u8> {
+      [platform, encoding, language, name_id, length as u16, offset as u16].iter().flat_map(|v| v.to_be_bytes().to_vec()).collect()
+   }
+
+   #[test]
+   fn test_parse_name_table() {
+      let utf16 = |s: &str| s.encode_utf16().flat_map(|u| u.to_be_bytes().to_vec()).collect::<Vec<u8>>();
+      let strings = vec![b"Hanuman".to_vec(), utf16("Noto Sans Khmer"), utf16("Bold"), utf16("Noto Sans Khmer UI")];
+      let records = vec![(1, 0, 0, 1), (3, 1, 0x409, 1), (3, 1, 0x409, 2), (3, 1, 0x40c, 16)];
+
+      let storage = 6 + 12 * records.len();
+      let mut table = [0u16, records.len() as u16, storage as u16].iter().flat_map(|v| v.to_be_bytes().to_vec()).collect::<Vec<u8>>();
+      let mut offset = 0;
+      for ((platform, encoding, language, name_id), string) in records.iter().zip(strings.iter()) {
+         table.extend(name_record(*platform, *encoding, *language, *name_id, string.len(), offset));
+         offset += string.len();
+      }
+      strings.iter().for_each(|s| table.extend(s));
+
+      // the typographic family wins even from a non-English record
+      assert_eq!(parse_name_table(&table), Some((String::from("Noto Sans Khmer UI"), String::from("Bold"))));
+      assert_eq!(parse_name_table(&table[..4]), None);
+   }
+}
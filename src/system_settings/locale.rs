@@ -1,5 +1,7 @@
+mod async_locale;
 mod locale_manager;
 mod locale_category;
 
+pub use async_locale::AsyncLocaleManager;
 pub use locale_manager::*;
 pub use locale_category::*;
\ No newline at end of file
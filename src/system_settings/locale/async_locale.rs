@@ -0,0 +1,43 @@
+use crate::error::Error;
+use crate::helpers::SystemBus;
+use super::super::privileged::check_locale_conf;
+
+const LOCALE1_SERVICE: &str = "org.freedesktop.locale1";
+const LOCALE1_PATH: &str = "/org/freedesktop/locale1";
+const LOCALE1_INTERFACE: &str = LOCALE1_SERVICE;
+
+/// Structure of the async Locale Manager, reading and writing the system locale through systemd-localed
+/// over the shared system bus connection.
+#[derive(Debug, Clone, Default)]
+pub struct AsyncLocaleManager;
+
+// Public API
+impl AsyncLocaleManager {
+   /// Initialize method
+   pub fn new() -> Self {
+      Self
+   }
+
+   /// This method is used to return the system locale as `KEY=value` assignments, e.g. `LANG=en_US.UTF-8`.
+   pub async fn system_locale(&self) -> Result<Vec<String>, Error> {
+      SystemBus::shared()?.get(LOCALE1_SERVICE, LOCALE1_PATH, LOCALE1_INTERFACE, "Locale").await
+   }
+
+   /// This method is used to return the value of one locale variable of the system locale.
+   pub async fn system_locale_var(&self, key: &str) -> Result<Option<String>, Error> {
+      Ok(self.system_locale().await?.into_iter().find_map(|assign| assign.strip_prefix(key)?.strip_prefix('=').map(ToString::to_string)))
+   }
+
+   /// This method is used to set the system locale, letting localed ask the user to authenticate.
+   /// Fails with `Error::Validation` if an assignment isn't a locale variable.
+   pub async fn set_system_locale<S: AsRef<str>>(&self, locale: &[S]) -> Result<(), Error> {
+      let locale: Vec<&str> = locale.iter().map(AsRef::as_ref).collect();
+      check_locale_conf(&locale.join("\n"))?;
+      SystemBus::shared()?.call_interactive(LOCALE1_SERVICE, LOCALE1_PATH, LOCALE1_INTERFACE, "SetLocale", (locale, true)).await
+   }
+
+   /// This method is used to return the X11 keyboard layout.
+   pub async fn x11_layout(&self) -> Result<String, Error> {
+      SystemBus::shared()?.get(LOCALE1_SERVICE, LOCALE1_PATH, LOCALE1_INTERFACE, "X11Layout").await
+   }
+}
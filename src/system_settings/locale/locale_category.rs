@@ -1,7 +1,7 @@
 use super::locale_manager::{LC_Keywords, LOCALE};
-use crate::helpers::{exec_cmd, get_list_by_sep, get_val_from_keyval};
+use crate::helpers::{get_list_by_sep, get_val_from_keyval, CommandLine, CommandRunner, SystemRunner};
 use serde::Deserialize;
-use std::io::Error;
+use crate::error::Error;
 
 /// Structure of LC_NUMERIC
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -14,7 +14,12 @@ pub struct LCNumeric {
 impl LCNumeric {
    /// Fetch current locale LC_NUMERIC
    pub fn new() -> Result<Self, Error> {
-      let stdout = exec_cmd(LOCALE, vec!["-k", format!("{}", LC_Keywords::LC_NUMERIC).as_str()])?;
+      Self::with_runner(&SystemRunner::default())
+   }
+
+   /// Fetch current locale LC_NUMERIC through the given runner
+   pub fn with_runner(runner: &dyn CommandRunner) -> Result<Self, Error> {
+      let stdout = runner.exec(&locale_keywords(LC_Keywords::LC_NUMERIC))?;
       let re = regex::Regex::new(r"[0-9]+;[0-9]+").unwrap();
       let stdout_formatted = stdout.replace("-", "_").lines().map(|line| re.replace(line, "0")).map(|m| m.to_string()).collect::<Vec<String>>().join("\n");
       Ok(toml::from_str(&stdout_formatted).unwrap_or_default())
@@ -44,7 +49,12 @@ pub struct LCTime {
 impl LCTime {
    /// Fetch current locale LC_TIME
    pub fn new() -> Result<Self, Error> {
-      let stdout = exec_cmd(LOCALE, vec!["-k", format!("{}", LC_Keywords::LC_TIME).as_str()])?;
+      Self::with_runner(&SystemRunner::default())
+   }
+
+   /// Fetch current locale LC_TIME through the given runner
+   pub fn with_runner(runner: &dyn CommandRunner) -> Result<Self, Error> {
+      let stdout = runner.exec(&locale_keywords(LC_Keywords::LC_TIME))?;
       let stdout_formatted = stdout.replace("-", "_").lines().filter(|line| !line.starts_with("time_era_entries=")).map(ToString::to_string).fold(Vec::new(), |mut formatted, line| {
          if get_val_from_keyval(line.as_str(), None).is_empty() && !line.contains("\"") {
             formatted.push(format!("{}{}", line, "\"\""))
@@ -107,7 +117,12 @@ pub struct LCMonetary {
 impl LCMonetary {
    /// Fetch current locale LC_MONETARY
    pub fn new() -> Result<Self, Error> {
-      let stdout = exec_cmd(LOCALE, vec!["-k", format!("{}", LC_Keywords::LC_MONETARY).as_str()])?;
+      Self::with_runner(&SystemRunner::default())
+   }
+
+   /// Fetch current locale LC_MONETARY through the given runner
+   pub fn with_runner(runner: &dyn CommandRunner) -> Result<Self, Error> {
+      let stdout = runner.exec(&locale_keywords(LC_Keywords::LC_MONETARY))?;
       let re = regex::Regex::new(r"[0-9]+;[0-9]+").unwrap();
       let stdout_formatted = stdout.replace("-", "_").lines().map(|line| re.replace(line, "0")).map(|m| m.to_string()).collect::<Vec<String>>().join("\n");
       Ok(toml::from_str(&stdout_formatted).unwrap_or_default()) 
@@ -123,7 +138,17 @@ pub struct LCMeasure {
 impl LCMeasure {
    /// Fetch current locale LC_MEASUREMENT
    pub fn new() -> Result<Self, Error> {
-      let stdout = exec_cmd(LOCALE, vec!["-k", format!("{}", LC_Keywords::LC_MEASUREMENT).as_str()])?;
+      Self::with_runner(&SystemRunner::default())
+   }
+
+   /// Fetch current locale LC_MEASUREMENT through the given runner
+   pub fn with_runner(runner: &dyn CommandRunner) -> Result<Self, Error> {
+      let stdout = runner.exec(&locale_keywords(LC_Keywords::LC_MEASUREMENT))?;
       Ok(toml::from_str(&stdout).unwrap_or_default())
    }
 }
+
+/// Command printing the keywords of a locale category in the caller's locale.
+fn locale_keywords(category: LC_Keywords) -> CommandLine {
+   CommandLine::new(LOCALE, &["-k", category.to_string().as_str()]).inherit_locale()
+}
@@ -1,8 +1,9 @@
-use std::io::Error;
 use std::fmt::{self, Display, Formatter};
 use std::collections::HashMap;
-use crate::helpers::{get_val_from_keyval, exec_cmd, read_content, write_content, write_content_overwrite};
+use crate::error::Error;
+use crate::helpers::{get_val_from_keyval, read_content, write_content, write_content_overwrite, CommandLine, Runner};
 use super::locale_category::*;
+use super::super::privileged::{Operation, PrivilegedClient};
 
 /// List of available measurement units
 pub const LS_MEASURE_UNITS: [(&str, &str); 3] = [("km_KH.UTF-8", "Metric"), ("en_US.UTF-8", "Imperial US"), ("en_GB.UTF-8", "Imperial UK")];
@@ -21,6 +22,7 @@ pub struct LocaleManager {
    list_locales: Vec<String>,
    list_langs: HashMap<String, String>,
    tmp_locale: LocaleConf,
+   runner: Runner,
 }
 
 // Public API
@@ -28,8 +30,12 @@ impl LocaleManager {
    /// Initialize method
    pub fn new() -> Result<Self, Error> {
       Self::clone_from_etc()?;
+      Self::with_runner(Runner::default())
+   }
 
-      let mut locale_mn = Self::default();
+   /// This method is used to initialize the manager running locale tools through the given runner.
+   pub fn with_runner(runner: Runner) -> Result<Self, Error> {
+      let mut locale_mn = Self { runner: runner.clone(), ..Self::default() };
       let Self {
          lang,
          language,
@@ -41,28 +47,28 @@ impl LocaleManager {
          ..
       } = &mut locale_mn;
 
-      let mut stdout = exec_cmd(LOCALE, Vec::new())?;
+      let mut stdout = runner.exec(&CommandLine::new(LOCALE, &[] as &[&str]).inherit_locale())?;
       stdout.lines().for_each(|line| {
          if line.starts_with(format!("{}", LC_Keywords::LANG).as_str()) {
             *lang = get_val_from_keyval(line, None);
          } else if line.starts_with(format!("{}", LC_Keywords::LC_NUMERIC).as_str()) {
             lc_numeric.0 = get_val_from_keyval(line, None);
-            lc_numeric.1 = LCNumeric::new().unwrap_or_default();
+            lc_numeric.1 = LCNumeric::with_runner(&*runner).unwrap_or_default();
          } else if line.starts_with(format!("{}", LC_Keywords::LC_TIME).as_str()) {
             lc_time.0 = get_val_from_keyval(line, None);
-            lc_time.1 = LCTime::new().unwrap_or_default();
+            lc_time.1 = LCTime::with_runner(&*runner).unwrap_or_default();
          } else if line.starts_with(format!("{}", LC_Keywords::LC_MONETARY).as_str()) {
             lc_monetary.0 = get_val_from_keyval(line, None);
-            lc_monetary.1 = LCMonetary::new().unwrap_or_default();
+            lc_monetary.1 = LCMonetary::with_runner(&*runner).unwrap_or_default();
          } else if line.starts_with(format!("{}", LC_Keywords::LC_MEASUREMENT).as_str()) {
             lc_measure.0 = get_val_from_keyval(line, None);
-            lc_measure.1 = LCMeasure::new().unwrap_or_default();
+            lc_measure.1 = LCMeasure::with_runner(&*runner).unwrap_or_default();
          } 
       });
 
       *language = std::env::var(format!("{}", LC_Keywords::LANGUAGE)).unwrap_or(String::new());
 
-      stdout = exec_cmd(LOCALE_DEF, vec!["--list-archive"])?;
+      stdout = runner.exec(&CommandLine::new(LOCALE_DEF, &["--list-archive"]))?;
       *list_locales = stdout.lines().filter(|line| line.contains(".utf8")).map(|line| {
          let line_break = line.split('.').collect::<Vec<&str>>().iter().map(|s| s.to_string()).collect::<Vec<String>>();
          let locale = line_break.first().unwrap();
@@ -168,7 +174,17 @@ impl LocaleManager {
       // }
    }
 
+   /// write the locale settings to /etc/locale.conf through the privileged helper, making them the system default
+   pub fn write_system_conf(&mut self) -> Result<(), Error> {
+      self.tmp_to_val();
+      PrivilegedClient::new(self.runner.clone()).call(&Operation::WriteLocaleConf { contents: self.to_locale_string() })
+   }
+
+   /// Fails with `Error::Validation` if the locale isn't generated on this system. LANGUAGE takes a priority list and isn't checked.
    pub fn set_locale(&mut self, key: LC_Keywords, locale: &str) -> Result<(), Error> {
+      if !matches!(key, LC_Keywords::LANGUAGE) && !["C", "POSIX"].contains(&locale) && !self.list_locales.iter().any(|ls_locale| ls_locale == locale) {
+         return Err(Error::Validation(format!("locale not available: {}", locale)));
+      }
       std::env::set_var(key.to_string(), locale);
       use LC_Keywords::*;
       match key {
@@ -176,19 +192,19 @@ impl LocaleManager {
          LANGUAGE => self.tmp_locale.language = locale.to_owned(),
          LC_NUMERIC => {
             self.tmp_locale.lc_numeric = locale.to_owned();
-            self.lc_numeric.1 = LCNumeric::new().unwrap_or_default();
+            self.lc_numeric.1 = LCNumeric::with_runner(&*self.runner).unwrap_or_default();
          },
          LC_TIME => {
             self.tmp_locale.lc_time = locale.to_owned();
-            self.lc_time.1 = LCTime::new().unwrap_or_default();
+            self.lc_time.1 = LCTime::with_runner(&*self.runner).unwrap_or_default();
          },
          LC_MONETARY => {
             self.tmp_locale.lc_monetary = locale.to_owned();
-            self.lc_monetary.1 = LCMonetary::new().unwrap_or_default();
+            self.lc_monetary.1 = LCMonetary::with_runner(&*self.runner).unwrap_or_default();
          },
          LC_MEASUREMENT => {
             self.tmp_locale.lc_measure = locale.to_owned();
-            self.lc_measure.1 = LCMeasure::new().unwrap_or_default();
+            self.lc_measure.1 = LCMeasure::with_runner(&*self.runner).unwrap_or_default();
          }
       }
       Ok(())
@@ -218,21 +234,15 @@ impl LocaleManager {
 
    /// write content from /etc to HOME if not exists
    fn clone_from_etc() -> Result<(), Error> {
-      match read_content("/etc/locale.conf") {
-         Ok(content) => match write_content(dirs_next::config_dir().unwrap().join("locale.conf"), &content) {
-            Ok(_) => Ok(()),
-            Err(err) => Err(err), 
-         },
-         Err(err) => Err(err)
-      }
+      let content = read_content("/etc/locale.conf")?;
+      Ok(write_content(dirs_next::config_dir().unwrap().join("locale.conf"), &content)?)
    }
 
    /// Fetch all the Language and region of enabled locales
    fn fetch_list_region_lang(&mut self) {
       let mut ls_langs = HashMap::new();
       self.list_locales.iter().for_each(|locale| {
-         std::env::set_var("LC_ADDRESS", locale);
-         match exec_cmd(LOCALE, vec!["lang_name", "country_name"]) {
+         match self.runner.exec(&CommandLine::new(LOCALE, &["lang_name", "country_name"]).inherit_locale().env("LC_ADDRESS", locale.as_str())) {
             Ok(stdout) => {
                if !stdout.trim().is_empty() {
                   let lang_reg = stdout.lines().map(|line| line.trim()).collect::<Vec<&str>>().join(" — ");
@@ -285,6 +295,24 @@ impl Display for LC_Keywords {
 #[cfg(test)]
 mod test {
    use super::{LocaleManager, LC_Keywords};
+   use crate::helpers::{CommandOutput, FakeRunner, Runner};
+
+   #[test]
+   fn test_parse_locale() {
+      let runner = FakeRunner::new()
+         .expect("locale", CommandOutput::ok("LANG=en_US.UTF-8\nLC_NUMERIC=\"km_KH.UTF-8\"\nLC_TIME=\"en_US.UTF-8\"\n"))
+         .expect("locale -k LC_NUMERIC", CommandOutput::ok("decimal_point=\",\"\nthousands_sep=\".\"\ngrouping=3;3\nnumeric-codeset=\"UTF-8\"\n"))
+         .expect("localedef --list-archive", CommandOutput::ok("C.utf8\nen_US.utf8\nkm_KH.utf8\nkm_KH\n"))
+         .expect("locale lang_name country_name", CommandOutput::ok("Khmer\nCambodia\n"));
+      let mut locale_mn = LocaleManager::with_runner(Runner::new(runner.clone())).unwrap();
+      assert_eq!(locale_mn.language().0, "en_US.UTF-8");
+      assert_eq!(locale_mn.list_locales(), vec!["C.UTF-8", "en_US.UTF-8", "km_KH.UTF-8"]);
+      assert_eq!(locale_mn.numeric_details().decimal_point, ",");
+      assert_eq!(locale_mn.numeric(), (&String::from("km_KH.UTF-8"), &String::from("Khmer — Cambodia (km_KH.UTF-8)")));
+      assert!(locale_mn.set_locale(LC_Keywords::LC_TIME, "fr_FR.UTF-8").is_err());
+      assert!(runner.calls().iter().filter(|call| call.program() == "locale").count() >= 2);
+   }
+
    #[test]
    fn test_locale_manager() {
       match LocaleManager::new() {
@@ -1,6 +1,8 @@
 pub mod accesspoint;
+mod async_network;
 pub mod auto_gen;
 pub mod wifi;
 pub use accesspoint::{get_accesspoints, AccessPoint};
+pub use async_network::{AsyncNetworkManager, ConnectivityState, NetworkProperties, NetworkState};
 pub use auto_gen::*;
 pub use wifi::Wifi;
@@ -1,11 +1,7 @@
-use dbus::arg::PropMap;
-use dbus::blocking::{Connection, Proxy};
-use dbus::Error;
-use std::collections::HashMap;
-use std::time::Duration;
-const SERVICE_NAME: &str = "org.freedesktop.NetworkManager";
-const SERVICE_INTERFACE: &str = "org.freedesktop.NetworkManager";
-const ACESSPOINT_INTERFACE: &str = "org.freedesktop.NetworkManager.AccessPoint";
+use crate::error::Error;
+use crate::helpers::block_on;
+use super::AsyncNetworkManager;
+
 #[derive(Default, Debug, PartialEq)]
 pub struct AccessPoint {
     pub ssid: String,
@@ -20,111 +16,51 @@ pub struct AccessPoint {
     pub wpa_flags: u32,
 }
 
+/// This function is used to scan all wifi devices and return the access points in range, sorted and deduplicated by SSID.
 pub fn get_accesspoints() -> Result<Vec<AccessPoint>, Error> {
-    let mut vec_accesspoint: Vec<AccessPoint> = Vec::new();
-    let conn = match Connection::new_system() {
-        Ok(conn) => conn,
-        Err(e) => {
-            println!("Error: {:?}", e);
-            dbus::blocking::Connection::new_system()?
-        }
-    };
-    let mut proxy = conn.with_proxy(SERVICE_NAME, "/org/freedesktop/NetworkManager", Duration::from_millis(2000));
-    // Now we make the method call. The ListNames method call take zero input paramters and one output parameter which is an array of strings.Duration
-    // Therefore the input is a zero tuple "()" , and the output is a single tuple "(names, "
-    let result: Result<Vec<dbus::Path<'static>>, dbus::Error> = proxy.method_call(SERVICE_INTERFACE, "GetDevices", ()).and_then(|r: (Vec<dbus::Path<'static>>,)| Ok(r.0));
-    let device_paths = match result {
-        Ok(res) => res,
-        Err(e) => {
-            println!("Error : {:?}", e);
-            Vec::new()
-        }
-    };
-    use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
-    for i in &device_paths {
-        proxy = conn.with_proxy(SERVICE_NAME, i, Duration::from_millis(500));
-        let dev_type: u32 = match proxy.get("org.freedesktop.NetworkManager.Device", "DeviceType") {
-            Ok(res) => res,
-            Err(e) => {
-                println!("Error: {:?}", e);
-                2
-            }
-        };
-        if dev_type == 2 {
-            let wifi_deice = Proxy::new(SERVICE_NAME, i, Duration::from_millis(500), &conn);
-            let dict: PropMap = HashMap::new();
-            let _device: Result<(), Error> = wifi_deice.method_call("org.freedesktop.NetworkManager.Device.Wireless", "RequestScan", (dict,));
-            std::thread::sleep(Duration::from_millis(1000));
-            let accessspoint: Result<Vec<dbus::Path<'static>>, dbus::Error> = wifi_deice.method_call("org.freedesktop.NetworkManager.Device.Wireless", "GetAccessPoints", ()).and_then(|r: (Vec<dbus::Path<'static>>,)| Ok(r.0));
-            match accessspoint {
-                Ok(data) => {
-                    data.into_iter().for_each(|access_path| {
-                        // println!("Path: {:?}", access_path);
-                        let p = conn.with_proxy(SERVICE_INTERFACE, access_path, Duration::from_millis(500));
-                        let data: Result<Vec<u8>, dbus::Error> = p.get(ACESSPOINT_INTERFACE, "Ssid");
-                        let address: Result<String, dbus::Error> = p.get(ACESSPOINT_INTERFACE, "HwAddress");
-                        let strength: Result<u8, dbus::Error> = p.get(ACESSPOINT_INTERFACE, "Strength");
-                        let result_strenght = u8::from_le_bytes(
-                            [match strength {
-                                Ok(d) => d,
-                                Err(e) => {
-                                    println!("Error: {:?}", e);
-                                    // default signal when error
-                                    50
-                                }
-                            }; 1],
-                        );
-                        let longdata = match data {
-                            Ok(ssid_res) => ssid_res,
-                            Err(e) => {
-                                println!("Error: {:?}", e);
-                                Vec::new()
-                            }
-                        };
-                        let acutal_data = std::str::from_utf8(&longdata);
-                        vec_accesspoint.push(AccessPoint {
-                            ssid: match acutal_data {
-                                Ok(a_data) => a_data.to_string(),
-                                Err(e) => {
-                                    println!("Error: {:?}", e);
-                                    String::from("Unknown")
-                                }
-                            },
-                            hwaddress: match address {
-                                Ok(addr) => addr.to_string(),
-                                Err(e) => {
-                                    println!("Error: {:?}", e);
-                                    String::from("192.168.1.1")
-                                }
-                            },
-                            strenght: result_strenght,
-                            ..Default::default()
-                        });
-                    });
-                }
-                Err(e) => {
-                    println!("Error: {:?}", e);
-                }
-            }
-        } else {
-            continue;
-        }
-    }
-    vec_accesspoint.sort_by(|a, b| a.ssid.cmp(&b.ssid));
-    vec_accesspoint.dedup_by(|a, b| a.ssid == b.ssid);
-    Ok(vec_accesspoint)
+    block_on(AsyncNetworkManager::new().access_points())
 }
 
 #[test]
-fn test_access() {
-    match get_accesspoints() {
-        Ok(res) => {
-            println!("Len of accesspiont: {}", res.len());
-            for i in res {
-                println!("Result: {:?}", i.ssid)
-            }
-        }
-        Err(e) => println!("Error: {:?}", e),
+fn test_access() -> Result<(), Error> {
+    use crate::test_support::{MockService, MockValue};
+
+    const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+    const WIRELESS_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+    const ACCESSPOINT_INTERFACE: &str = "org.freedesktop.NetworkManager.AccessPoint";
+    let nm = match MockService::network_manager() {
+        Ok(mock) => mock,
+        Err(Error::MissingTool(_)) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    // the last access point vanished between GetAccessPoints and reading its properties
+    let aps = [
+        "/org/freedesktop/NetworkManager/AccessPoint/1",
+        "/org/freedesktop/NetworkManager/AccessPoint/2",
+        "/org/freedesktop/NetworkManager/AccessPoint/3",
+        "/org/freedesktop/NetworkManager/AccessPoint/4",
+    ];
+    nm.add_object("/org/freedesktop/NetworkManager/Devices/1", "org.freedesktop.NetworkManager.Device", vec![("DeviceType", 2u32.into())]);
+    nm.reply(NM_INTERFACE, "GetDevices", vec![MockValue::paths(vec!["/org/freedesktop/NetworkManager/Devices/1"])])
+        .reply(WIRELESS_INTERFACE, "RequestScan", Vec::new())
+        .reply(WIRELESS_INTERFACE, "GetAccessPoints", vec![MockValue::paths(aps.to_vec())]);
+    for (path, ssid, strength) in [(aps[0], "Koompi OS", 80u8), (aps[1], "Cafe", 40), (aps[2], "Koompi OS", 60)].iter() {
+        nm.add_object(path, ACCESSPOINT_INTERFACE, vec![
+            ("Ssid", ssid.as_bytes().to_vec().into()),
+            ("Strength", (*strength).into()),
+            ("LastSeen", 100i32.into()),
+            ("HwAddress", "00:11:22:33:44:55".into()),
+            ("Flags", 1u32.into()),
+            ("Frequency", 2412u32.into()),
+            ("MaxBitrate", 54000u32.into()),
+            ("Mode", 2u32.into()),
+            ("RsnFlags", 0x188u32.into()),
+            ("WpaFlags", 0u32.into()),
+        ]);
     }
-    assert_eq!(1, 1)
+
+    let access_points = get_accesspoints()?;
+    assert_eq!(access_points.iter().map(|ap| (ap.ssid.as_str(), ap.strenght)).collect::<Vec<_>>(), vec![("Cafe", 40), ("Koompi OS", 80)]);
+    assert_eq!(nm.calls_to("RequestScan").len(), 1);
+    Ok(())
 }
@@ -0,0 +1,254 @@
+use super::accesspoint::AccessPoint;
+use super::wifi::{Connectivity, Wifi};
+use crate::error::Error;
+use crate::helpers::{required_prop, unblock, FromProperties, PropertyCache, Runner, SystemBus};
+use dbus::arg::PropMap;
+use dbus::Path;
+use std::time::Duration;
+
+const NM_SERVICE: &str = "org.freedesktop.NetworkManager";
+const NM_PATH: &str = "/org/freedesktop/NetworkManager";
+const NM_INTERFACE: &str = NM_SERVICE;
+const NM_DEVICE_INTERFACE: &str = "org.freedesktop.NetworkManager.Device";
+const NM_WIRELESS_INTERFACE: &str = "org.freedesktop.NetworkManager.Device.Wireless";
+const NM_ACCESSPOINT_INTERFACE: &str = "org.freedesktop.NetworkManager.AccessPoint";
+const NM_DEVICE_TYPE_WIFI: u32 = 2;
+/// Time given to the wireless devices to report the results of a scan.
+const SCAN_DELAY: Duration = Duration::from_secs(1);
+
+/// Overall networking state of NetworkManager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkState {
+    Unknown,
+    Asleep,
+    Disconnected,
+    Disconnecting,
+    Connecting,
+    ConnectedLocal,
+    ConnectedSite,
+    ConnectedGlobal,
+}
+
+impl From<u32> for NetworkState {
+    fn from(state: u32) -> Self {
+        match state {
+            10 => Self::Asleep,
+            20 => Self::Disconnected,
+            30 => Self::Disconnecting,
+            40 => Self::Connecting,
+            50 => Self::ConnectedLocal,
+            60 => Self::ConnectedSite,
+            70 => Self::ConnectedGlobal,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Internet connectivity as checked by NetworkManager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectivityState {
+    Unknown,
+    None,
+    Portal,
+    Limited,
+    Full,
+}
+
+impl From<u32> for ConnectivityState {
+    fn from(state: u32) -> Self {
+        match state {
+            1 => Self::None,
+            2 => Self::Portal,
+            3 => Self::Limited,
+            4 => Self::Full,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Global properties of NetworkManager
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkProperties {
+    pub networking_enabled: bool,
+    pub wireless_enabled: bool,
+    pub state: NetworkState,
+    pub connectivity: ConnectivityState,
+}
+
+impl FromProperties for NetworkProperties {
+    fn from_properties(props: &PropMap) -> Result<Self, Error> {
+        Ok(Self {
+            networking_enabled: required_prop(props, "NetworkingEnabled")?,
+            wireless_enabled: required_prop(props, "WirelessEnabled")?,
+            state: required_prop::<u32>(props, "State")?.into(),
+            connectivity: required_prop::<u32>(props, "Connectivity")?.into(),
+        })
+    }
+}
+
+/// Async network manager talking to NetworkManager over the shared system bus connection
+#[derive(Debug, Clone, Default)]
+pub struct AsyncNetworkManager {
+    wifi: Wifi,
+}
+
+impl AsyncNetworkManager {
+    /// Create a manager using the shared system bus connection.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a manager connecting to wifi networks through the given runner.
+    pub fn with_runner(runner: Runner) -> Self {
+        Self { wifi: Wifi::with_runner(runner) }
+    }
+
+    /// Follow the global properties, e.g. to update the UI when the wifi radio is switched or connectivity changes.
+    pub async fn properties(&self) -> Result<PropertyCache<NetworkProperties>, Error> {
+        PropertyCache::new(&SystemBus::shared()?, NM_SERVICE, NM_PATH, NM_INTERFACE).await
+    }
+
+    /// Check if the wifi radio is enabled.
+    pub async fn wireless_enabled(&self) -> Result<bool, Error> {
+        SystemBus::shared()?.get(NM_SERVICE, NM_PATH, NM_INTERFACE, "WirelessEnabled").await
+    }
+
+    /// Turn the wifi radio on or off.
+    pub async fn set_wireless_enabled(&self, enabled: bool) -> Result<(), Error> {
+        SystemBus::shared()?.set(NM_SERVICE, NM_PATH, NM_INTERFACE, "WirelessEnabled", enabled).await
+    }
+
+    /// Check if networking is enabled at all.
+    pub async fn networking_enabled(&self) -> Result<bool, Error> {
+        SystemBus::shared()?.get(NM_SERVICE, NM_PATH, NM_INTERFACE, "NetworkingEnabled").await
+    }
+
+    /// Return the overall networking state.
+    pub async fn state(&self) -> Result<NetworkState, Error> {
+        let state: u32 = SystemBus::shared()?.get(NM_SERVICE, NM_PATH, NM_INTERFACE, "State").await?;
+        Ok(state.into())
+    }
+
+    /// Return the last known connectivity, without checking again.
+    pub async fn connectivity(&self) -> Result<ConnectivityState, Error> {
+        let state: u32 = SystemBus::shared()?.get(NM_SERVICE, NM_PATH, NM_INTERFACE, "Connectivity").await?;
+        Ok(state.into())
+    }
+
+    /// Ask NetworkManager to check the connectivity now.
+    pub async fn check_connectivity(&self) -> Result<ConnectivityState, Error> {
+        let (state,): (u32,) = SystemBus::shared()?.call(NM_SERVICE, NM_PATH, NM_INTERFACE, "CheckConnectivity", ()).await?;
+        Ok(state.into())
+    }
+
+    /// Scan all wifi devices and return the access points in range, sorted and deduplicated by SSID.
+    pub async fn access_points(&self) -> Result<Vec<AccessPoint>, Error> {
+        let bus = SystemBus::shared()?;
+        let (devices,): (Vec<Path<'static>>,) = bus.call(NM_SERVICE, NM_PATH, NM_INTERFACE, "GetDevices", ()).await?;
+        let mut wifi_devices = Vec::new();
+        for device in devices {
+            let dev_type: u32 = bus.get(NM_SERVICE, &device, NM_DEVICE_INTERFACE, "DeviceType").await?;
+            if dev_type == NM_DEVICE_TYPE_WIFI {
+                if let Err(err) = bus.call::<_, ()>(NM_SERVICE, &device, NM_WIRELESS_INTERFACE, "RequestScan", (PropMap::new(),)).await {
+                    log::debug!("scan request of {} failed: {}", device, err);
+                }
+                wifi_devices.push(device);
+            }
+        }
+        if !wifi_devices.is_empty() {
+            tokio::time::sleep(SCAN_DELAY).await;
+        }
+
+        let mut ls_accesspoints = Vec::new();
+        for device in wifi_devices {
+            let (paths,): (Vec<Path<'static>>,) = bus.call(NM_SERVICE, &device, NM_WIRELESS_INTERFACE, "GetAccessPoints", ()).await?;
+            for path in paths {
+                // access points come and go while scanning, so one vanishing doesn't fail the whole scan
+                match read_access_point(&bus, &path).await {
+                    Ok(ap) => ls_accesspoints.push(ap),
+                    Err(err) => log::warn!("skipping access point {}: {}", path, err),
+                }
+            }
+        }
+        ls_accesspoints.sort_by(|a, b| a.ssid.cmp(&b.ssid));
+        ls_accesspoints.dedup_by(|a, b| a.ssid == b.ssid);
+        Ok(ls_accesspoints)
+    }
+
+    /// Connect to a wireless network, returning whether NetworkManager activated the connection.
+    /// Fails with `Error::Validation` if the wifi radio is turned off.
+    pub async fn connect_wifi(&self, ssid: &str, password: &str) -> Result<bool, Error> {
+        let (wifi, ssid, password) = (self.wifi.clone(), ssid.to_string(), password.to_string());
+        unblock(move || wifi.connect(&ssid, &password)).await
+    }
+
+    /// Disconnect from a wireless network, returning whether NetworkManager deactivated the connection.
+    pub async fn disconnect_wifi(&self, ssid: &str) -> Result<bool, Error> {
+        let (wifi, ssid) = (self.wifi.clone(), ssid.to_string());
+        unblock(move || wifi.disconnect(&ssid)).await
+    }
+}
+
+async fn read_access_point(bus: &SystemBus, path: &str) -> Result<AccessPoint, Error> {
+    let ssid: Vec<u8> = bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "Ssid").await?;
+    Ok(AccessPoint {
+        ssid: String::from_utf8_lossy(&ssid).into_owned(),
+        strenght: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "Strength").await?,
+        last_seen: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "LastSeen").await?,
+        hwaddress: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "HwAddress").await?,
+        flags: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "Flags").await?,
+        frequency: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "Frequency").await?,
+        max_bitrate: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "MaxBitrate").await?,
+        mode: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "Mode").await?,
+        rns_flags: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "RsnFlags").await?,
+        wpa_flags: bus.get(NM_SERVICE, path, NM_ACCESSPOINT_INTERFACE, "WpaFlags").await?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncNetworkManager, ConnectivityState, NetworkState};
+    use crate::error::Error;
+    use crate::helpers::{block_on, CommandOutput, FakeRunner, Runner};
+    use crate::test_support::MockService;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_network_properties() -> Result<(), Error> {
+        let nm = match MockService::network_manager() {
+            Ok(mock) => mock,
+            Err(Error::MissingTool(_)) => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let network_mn = AsyncNetworkManager::new();
+        assert_eq!(block_on(network_mn.state())?, NetworkState::ConnectedGlobal);
+        assert_eq!(block_on(network_mn.check_connectivity())?, ConnectivityState::Full);
+        assert!(block_on(network_mn.access_points())?.is_empty());
+
+        let props = block_on(network_mn.properties())?;
+        let mut changes = props.subscribe();
+        block_on(network_mn.set_wireless_enabled(false))?;
+        let changed = block_on(async { tokio::time::timeout(Duration::from_secs(5), changes.next()).await }).ok().flatten();
+        assert!(!changed.expect("no PropertiesChanged received").wireless_enabled);
+        assert!(!props.get().wireless_enabled);
+
+        nm.set_property("/org/freedesktop/NetworkManager", "org.freedesktop.NetworkManager", "State", 20u32);
+        let changed = block_on(async { tokio::time::timeout(Duration::from_secs(5), changes.next()).await }).ok().flatten();
+        assert_eq!(changed.map(|props| props.state), Some(NetworkState::Disconnected));
+        Ok(())
+    }
+
+    #[test]
+    fn test_connect_wifi() -> Result<(), Error> {
+        let runner = FakeRunner::new()
+            .expect("nmcli radio wifi", CommandOutput::ok("enabled\n"))
+            .expect("nmcli d wifi connect Koompi OS password Hi@Koompi", CommandOutput::ok("Device 'wlan0' successfully activated with 'b5d6b5c5'.\n"))
+            .expect("nmcli connection down Koompi OS", CommandOutput::ok("Error: 'Koompi OS' is not an active connection.\n"));
+        let network_mn = AsyncNetworkManager::with_runner(Runner::new(runner.clone()));
+        assert!(block_on(network_mn.connect_wifi("Koompi OS", "Hi@Koompi"))?);
+        assert!(!block_on(network_mn.disconnect_wifi("Koompi OS"))?);
+        assert_eq!(runner.calls().len(), 3);
+        Ok(())
+    }
+}
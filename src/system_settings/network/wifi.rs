@@ -1,109 +1,97 @@
-use std::process::Command;
-use std::{fmt, io};
+use std::fmt;
+use crate::error::Error;
+use crate::helpers::{CommandLine, Runner};
+
+const NMCLI: &str = "nmcli";
+
 pub trait Connectivity: fmt::Debug {
-    fn connect(ssid: String, password: String) -> Result<bool, WifiConnectionError>;
-    fn disconnect(ssid: String) -> Result<bool, WifiConnectionError>;
+    /// Connect to a wireless network, returning whether NetworkManager activated the connection.
+    fn connect(&self, ssid: &str, password: &str) -> Result<bool, Error>;
+    /// Disconnect from a wireless network, returning whether NetworkManager deactivated the connection.
+    fn disconnect(&self, ssid: &str) -> Result<bool, Error>;
 }
 
 pub trait WifiInterface: fmt::Debug {
     /// Check if the wifi interface on host machine is enabled.
-    fn is_wifi_enabled() -> Result<bool, WifiError> {
-        unimplemented!();
-    }
+    fn is_wifi_enabled(&self) -> Result<bool, Error>;
 
     /// Turn on the wifi interface of host machine.
-    fn turn_on() -> Result<(), WifiError> {
-        unimplemented!();
-    }
+    fn turn_on(&self) -> Result<(), Error>;
 
     /// Turn off the wifi interface of host machine.
-    fn turn_off() -> Result<(), WifiError> {
-        unimplemented!();
-    }
+    fn turn_off(&self) -> Result<(), Error>;
 }
 
-#[derive(Debug)]
-pub enum WifiConnectionError {
-    /// Adding the newtork profile failed.
-    #[cfg(target_os = "windows")]
-    AddNetworkProfileFailed,
-    /// Failed to connect to wireless network.
-    FailedToConnect(String),
-    /// Failed to disconnect from wireless network. Try turning the wireless interface down.
-    FailedToDisconnect(String),
-    /// A wireless error occurred.
-    Other { kind: WifiError },
-    // SsidNotFound,
+/// Structure of Wifi, managed through nmcli
+#[derive(Debug, Clone, Default)]
+pub struct Wifi {
+    runner: Runner,
 }
 
-impl From<io::Error> for WifiConnectionError {
-    fn from(error: io::Error) -> Self {
-        WifiConnectionError::Other { kind: WifiError::IoError(error) }
+impl Wifi {
+    pub fn new() -> Self {
+        Self::default()
     }
-}
 
-#[derive(Debug)]
-pub enum WifiError {
-    // The specified wifi  is currently disabled. Try switching it on.
-    WifiDisabled,
-    /// The wifi interface interface failed to switch on.
-    #[cfg(target_os = "windows")]
-    InterfaceFailedToOn,
-    /// IO Error occurred.
-    IoError(io::Error),
+    /// This method is used to create a Wifi running nmcli through the given runner.
+    pub fn with_runner(runner: Runner) -> Self {
+        Self { runner }
+    }
 }
-#[derive(Debug, Clone, Default)]
-pub struct Wifi;
-impl WifiInterface for Wifi {
-    fn is_wifi_enabled() -> Result<bool, WifiError> {
-        let output = Command::new("nmcli").args(&["radio", "wifi"]).output().map_err(|err| WifiError::IoError(err))?;
 
-        Ok(String::from_utf8_lossy(&output.stdout).replace(" ", "").replace("\n", "").contains("enabled"))
+impl WifiInterface for Wifi {
+    fn is_wifi_enabled(&self) -> Result<bool, Error> {
+        Ok(self.runner.exec(&CommandLine::new(NMCLI, &["radio", "wifi"]))? == "enabled")
     }
-    fn turn_on() -> Result<(), WifiError> {
-        Command::new("nmcli").args(&["radio", "wifi", "on"]).output().map_err(|err| WifiError::IoError(err))?;
 
-        Ok(())
+    fn turn_on(&self) -> Result<(), Error> {
+        self.runner.exec(&CommandLine::new(NMCLI, &["radio", "wifi", "on"])).map(|_| ())
     }
-    fn turn_off() -> Result<(), WifiError> {
-        Command::new("nmcli").args(&["radio", "wifi", "off"]).output().map_err(|err| WifiError::IoError(err))?;
 
-        Ok(())
+    fn turn_off(&self) -> Result<(), Error> {
+        self.runner.exec(&CommandLine::new(NMCLI, &["radio", "wifi", "off"])).map(|_| ())
     }
 }
+
 impl Connectivity for Wifi {
-    fn connect(ssid: String, password: String) -> Result<bool, WifiConnectionError> {
-        if !Wifi::is_wifi_enabled().map_err(|err| WifiConnectionError::Other { kind: err })? {
-            return Err(WifiConnectionError::Other { kind: WifiError::WifiDisabled });
+    /// Fails with `Error::Validation` if the wifi interface is turned off.
+    fn connect(&self, ssid: &str, password: &str) -> Result<bool, Error> {
+        if !self.is_wifi_enabled()? {
+            return Err(Error::Validation(String::from("wifi is disabled")));
         }
-        let output = Command::new("nmcli")
-            .args(&["d", "wifi", "connect", &ssid, "password", &password])
-            .output()
-            .map_err(|err| WifiConnectionError::FailedToConnect(format!("{}", err)))?;
-        if !String::from_utf8_lossy(&output.stdout).as_ref().contains("successfully activated") {
-            Ok(false)
-        } else {
-            Ok(true)
+        let stdout = self.runner.exec(&CommandLine::new(NMCLI, &["d", "wifi", "connect", ssid, "password", password]))?;
+        let connected = stdout.contains("successfully activated");
+        if !connected {
+            log::warn!("connecting to {} didn't activate: {}", ssid, stdout);
         }
+        Ok(connected)
     }
-    fn disconnect(ssid: String) -> Result<bool, WifiConnectionError> {
-        let output = Command::new("nmcli").args(&["connection", "down", &ssid]).output().map_err(|err| WifiConnectionError::FailedToDisconnect(format!("{}", err)))?;
-        if !String::from_utf8_lossy(&output.stdout).as_ref().contains("successfully deactivated") {
-            Ok(false)
-        } else {
-            Ok(true)
+
+    fn disconnect(&self, ssid: &str) -> Result<bool, Error> {
+        let stdout = self.runner.exec(&CommandLine::new(NMCLI, &["connection", "down", ssid]))?;
+        let disconnected = stdout.contains("successfully deactivated");
+        if !disconnected {
+            log::warn!("disconnecting from {} didn't deactivate: {}", ssid, stdout);
         }
+        Ok(disconnected)
     }
 }
 
 #[test]
-fn test_wifi() -> Result<(), WifiConnectionError> {
-    println!("turn off wifi: {:?}", Wifi::turn_on());
-    println!("is wifi enable :{:?}", Wifi::is_wifi_enabled());
-    match Wifi::connect("Koompi OS".to_string(), "Hi@Koompi".to_string()) {
-        Ok(is_success) => println!("Connection success: {}", is_success),
-        Err(e) => println!("Error: {:?}", e),
-    }
-    assert_eq!(1, 2);
+fn test_wifi() -> Result<(), Error> {
+    use crate::helpers::{CommandOutput, FakeRunner};
+
+    let runner = FakeRunner::new()
+        .expect("nmcli radio wifi", CommandOutput::ok("enabled\n"))
+        .expect("nmcli d wifi connect Koompi OS password Hi@Koompi", CommandOutput::ok("Device 'wlan0' successfully activated with 'b5d6b5c5'.\n"))
+        .expect("nmcli connection down Koompi OS", CommandOutput::ok("Connection 'Koompi OS' successfully deactivated (D-Bus active path: /org/freedesktop/NetworkManager/ActiveConnection/1)\n"));
+    let wifi = Wifi::with_runner(Runner::new(runner));
+    assert!(wifi.connect("Koompi OS", "Hi@Koompi")?);
+    assert!(wifi.disconnect("Koompi OS")?);
+
+    let runner = FakeRunner::new().expect("nmcli radio wifi", CommandOutput::ok("disabled\n"));
+    let wifi = Wifi::with_runner(Runner::new(runner.clone()));
+    assert!(matches!(wifi.connect("Koompi OS", "Hi@Koompi"), Err(Error::Validation(_))));
+    assert_eq!(runner.calls().len(), 1);
     Ok(())
 }
@@ -0,0 +1,379 @@
+mod client;
+mod service;
+
+pub use client::PrivilegedClient;
+pub use service::run_service;
+
+use std::collections::HashMap;
+use std::path::Path;
+use dbus::Message;
+use crate::error::Error;
+use crate::helpers::{CommandLine, Locked, Lockdown, lockdown::ACTION_CREATE_USER};
+use super::datetime::zoneinfo_path;
+use super::users_groups::{check_account_name, ADM_GROUP};
+
+/// Well-known name of the privileged settings helper on the system bus.
+pub const HELPER_SERVICE: &str = "org.koompi.SettingsHelper";
+pub const HELPER_PATH: &str = "/org/koompi/SettingsHelper";
+pub const HELPER_INTERFACE: &str = "org.koompi.SettingsHelper1";
+
+const USER_ADD: &str = "useradd";
+const USER_MOD: &str = "usermod";
+const USER_DEL: &str = "userdel";
+const CHPASSWD: &str = "chpasswd";
+const GPASSWD: &str = "gpasswd";
+const GROUP_ADD: &str = "groupadd";
+const GROUP_MOD: &str = "groupmod";
+const GROUP_DEL: &str = "groupdel";
+const DEFAULT_GROUPS: [&str; 2] = ["input", "cups"];
+const LOCALTIME_PATH: &str = "/etc/localtime";
+pub(crate) const LOCALE_CONF_PATH: &str = "/etc/locale.conf";
+const LOCALE_CONF_KEYS: [&str; 14] = ["LANG", "LANGUAGE", "LC_CTYPE", "LC_NUMERIC", "LC_TIME", "LC_COLLATE", "LC_MONETARY", "LC_MESSAGES", "LC_PAPER", "LC_NAME", "LC_ADDRESS", "LC_TELEPHONE", "LC_MEASUREMENT", "LC_IDENTIFICATION"];
+/// Keys of `Operation::ModifyUser` changes and the usermod options they map to, in the order passed to usermod.
+const USER_CHANGES: [(&str, &[&str]); 6] = [("uid", &["-u"]), ("gid", &["-g"]), ("fullname", &["-c"]), ("login", &["-l"]), ("shell", &["-s"]), ("home", &["-m", "-d"])];
+
+/// Variants of privileged operation, each guarded by its own polkit action
+#[derive(Clone, PartialEq, Eq)]
+pub enum Operation {
+   CreateUser { fullname: String, username: String, admin: bool, password: String },
+   /// Changes keyed by `uid`, `gid`, `fullname`, `login`, `shell` and `home`.
+   ModifyUser { username: String, changes: HashMap<String, String> },
+   SetAccountType { username: String, admin: bool },
+   /// Add the user to secondary groups.
+   SetUserGroups { username: String, groups: Vec<String> },
+   SetPassword { username: String, password: String },
+   DeleteUser { username: String, remove_home: bool },
+   CreateGroup { name: String },
+   RenameGroup { name: String, new_name: String },
+   SetGroupMembers { name: String, members: Vec<String> },
+   AddGroupMember { name: String, username: String },
+   RemoveGroupMember { name: String, username: String },
+   DeleteGroup { name: String },
+   SetTimezone { timezone: String },
+   WriteLocaleConf { contents: String },
+}
+
+impl Operation {
+   /// Return the helper method running this operation.
+   pub fn method(&self) -> &'static str {
+      use Operation::*;
+      match self {
+         CreateUser { .. } => "CreateUser",
+         ModifyUser { .. } => "ModifyUser",
+         SetAccountType { .. } => "SetAccountType",
+         SetUserGroups { .. } => "SetUserGroups",
+         SetPassword { .. } => "SetPassword",
+         DeleteUser { .. } => "DeleteUser",
+         CreateGroup { .. } => "CreateGroup",
+         RenameGroup { .. } => "RenameGroup",
+         SetGroupMembers { .. } => "SetGroupMembers",
+         AddGroupMember { .. } => "AddGroupMember",
+         RemoveGroupMember { .. } => "RemoveGroupMember",
+         DeleteGroup { .. } => "DeleteGroup",
+         SetTimezone { .. } => "SetTimezone",
+         WriteLocaleConf { .. } => "WriteLocaleConf",
+      }
+   }
+
+   /// Return the polkit action authorizing this operation, as declared in `data/org.koompi.SettingsHelper.policy`.
+   pub fn action_id(&self) -> &'static str {
+      use Operation::*;
+      match self {
+         CreateUser { .. } => "org.koompi.settings.create-user",
+         ModifyUser { .. } | SetAccountType { .. } | SetUserGroups { .. } => "org.koompi.settings.modify-user",
+         SetPassword { .. } => "org.koompi.settings.set-password",
+         DeleteUser { .. } => "org.koompi.settings.delete-user",
+         CreateGroup { .. } | RenameGroup { .. } | SetGroupMembers { .. } | AddGroupMember { .. } | RemoveGroupMember { .. } | DeleteGroup { .. } => "org.koompi.settings.manage-groups",
+         SetTimezone { .. } => "org.koompi.settings.set-timezone",
+         WriteLocaleConf { .. } => "org.koompi.settings.set-locale",
+      }
+   }
+
+   /// Check the arguments. The helper checks them again, since it can't trust its callers.
+   pub fn validate(&self) -> Result<(), Error> {
+      use Operation::*;
+      match self {
+         CreateUser { fullname, username, password, .. } => {
+            check_account_name(username)?;
+            check_field(fullname)?;
+            check_password(password)
+         }
+         ModifyUser { username, changes } => {
+            check_account_name(username)?;
+            changes.iter().try_for_each(|(key, val)| match key.as_str() {
+               "uid" | "gid" => val.parse::<u32>().map(|_| ()).map_err(|_| Error::Validation(format!("{} is not a number: {}", key, val))),
+               "fullname" => check_field(val),
+               "login" => check_account_name(val),
+               "shell" | "home" => check_path(val),
+               _ => Err(Error::Validation(format!("unknown user change: {}", key))),
+            })
+         }
+         SetUserGroups { username, groups } => std::iter::once(username).chain(groups).try_for_each(|name| check_account_name(name)),
+         SetPassword { username, password } => {
+            check_account_name(username)?;
+            check_password(password)
+         }
+         SetAccountType { username, .. } | DeleteUser { username, .. } => check_account_name(username),
+         CreateGroup { name } | DeleteGroup { name } => check_account_name(name),
+         RenameGroup { name, new_name } => check_account_name(name).and_then(|_| check_account_name(new_name)),
+         SetGroupMembers { name, members } => std::iter::once(name).chain(members).try_for_each(|name| check_account_name(name)),
+         AddGroupMember { name, username } | RemoveGroupMember { name, username } => check_account_name(name).and_then(|_| check_account_name(username)),
+         SetTimezone { timezone } => zoneinfo_path(timezone).map(|_| ()),
+         WriteLocaleConf { contents } => check_locale_conf(contents),
+      }
+   }
+
+   /// Check the admin lockdown policy. The helper checks it again, since it can't trust its callers.
+   pub fn check_lockdown(&self, lockdown: &Lockdown) -> Result<(), Error> {
+      match self {
+         Operation::CreateUser { .. } => Ok(lockdown.check_action(ACTION_CREATE_USER)?),
+         _ => Ok(()),
+      }
+   }
+
+   /// Return the commands carrying out this operation, without privilege escalation.
+   pub fn commands(&self) -> Vec<CommandLine> {
+      use Operation::*;
+      match self {
+         CreateUser { fullname, username, admin, password } => {
+            let mut groups = DEFAULT_GROUPS.to_vec();
+            if *admin {
+               groups.push(ADM_GROUP);
+            }
+            vec![
+               CommandLine::new(USER_ADD, &["-c", fullname, "-m", "-N", username]),
+               CommandLine::new(USER_MOD, &["-a", "-G", &groups.join(","), username]),
+               chpasswd(username, password),
+            ]
+         }
+         ModifyUser { username, changes } => {
+            let mut args: Vec<&str> = USER_CHANGES.iter().filter_map(|(key, opts)| changes.get(*key).map(|val| opts.iter().copied().chain(std::iter::once(val.as_str())))).flatten().collect();
+            args.push(username);
+            vec![CommandLine::new(USER_MOD, &args)]
+         }
+         SetAccountType { username, admin } => vec![CommandLine::new(GPASSWD, &[if *admin { "-a" } else { "-d" }, username, ADM_GROUP])],
+         SetUserGroups { username, groups } => vec![CommandLine::new(USER_MOD, &["-a", "-G", &groups.join(","), username])],
+         SetPassword { username, password } => vec![chpasswd(username, password)],
+         DeleteUser { username, remove_home } => vec![CommandLine::new(USER_DEL, &if *remove_home { vec!["-r", username.as_str()] } else { vec![username.as_str()] })],
+         CreateGroup { name } => vec![CommandLine::new(GROUP_ADD, &[name])],
+         RenameGroup { name, new_name } => vec![CommandLine::new(GROUP_MOD, &["-n", new_name, name])],
+         SetGroupMembers { name, members } => vec![CommandLine::new(GPASSWD, &["-M", &members.join(","), name])],
+         AddGroupMember { name, username } => vec![CommandLine::new(GPASSWD, &["-a", username, name])],
+         RemoveGroupMember { name, username } => vec![CommandLine::new(GPASSWD, &["-d", username, name])],
+         DeleteGroup { name } => vec![CommandLine::new(GROUP_DEL, &[name])],
+         SetTimezone { timezone } => {
+            let zoneinfo = zoneinfo_path(timezone).unwrap_or_default();
+            vec![CommandLine::new("ln", &["-sf", zoneinfo.to_str().unwrap_or_default(), LOCALTIME_PATH])]
+         }
+         WriteLocaleConf { contents } => vec![CommandLine::new("tee", &[LOCALE_CONF_PATH]).stdin(contents.as_str())],
+      }
+   }
+
+   /// Build the method call of this operation to the helper.
+   pub fn to_message(&self) -> Message {
+      use Operation::*;
+      let mut msg = Message::new_method_call(HELPER_SERVICE, HELPER_PATH, HELPER_INTERFACE, self.method()).unwrap();
+      match self {
+         CreateUser { fullname, username, admin, password } => msg.append_all((fullname, username, admin, password)),
+         ModifyUser { username, changes } => msg.append_all((username, changes)),
+         SetAccountType { username, admin } => msg.append_all((username, admin)),
+         SetUserGroups { username, groups } => msg.append_all((username, groups)),
+         SetPassword { username, password } => msg.append_all((username, password)),
+         DeleteUser { username, remove_home } => msg.append_all((username, remove_home)),
+         CreateGroup { name } | DeleteGroup { name } => msg.append_all((name,)),
+         RenameGroup { name, new_name } => msg.append_all((name, new_name)),
+         SetGroupMembers { name, members } => msg.append_all((name, members)),
+         AddGroupMember { name, username } | RemoveGroupMember { name, username } => msg.append_all((name, username)),
+         SetTimezone { timezone } => msg.append_all((timezone,)),
+         WriteLocaleConf { contents } => msg.append_all((contents,)),
+      }
+      msg
+   }
+
+   /// Read an operation from a method call to the helper.
+   pub fn from_message(msg: &Message) -> Result<Self, Error> {
+      use Operation::*;
+      let member = msg.member().map(|member| member.to_string()).unwrap_or_default();
+      let invalid = |err: dbus::Error| Error::Validation(format!("{}: {}", member, err.message().unwrap_or_default()));
+      Ok(match member.as_str() {
+         "CreateUser" => {
+            let (fullname, username, admin, password) = msg.read_all().map_err(invalid)?;
+            CreateUser { fullname, username, admin, password }
+         }
+         "ModifyUser" => {
+            let (username, changes) = msg.read_all().map_err(invalid)?;
+            ModifyUser { username, changes }
+         }
+         "SetAccountType" => {
+            let (username, admin) = msg.read_all().map_err(invalid)?;
+            SetAccountType { username, admin }
+         }
+         "SetUserGroups" => {
+            let (username, groups) = msg.read_all().map_err(invalid)?;
+            SetUserGroups { username, groups }
+         }
+         "SetPassword" => {
+            let (username, password) = msg.read_all().map_err(invalid)?;
+            SetPassword { username, password }
+         }
+         "DeleteUser" => {
+            let (username, remove_home) = msg.read_all().map_err(invalid)?;
+            DeleteUser { username, remove_home }
+         }
+         "CreateGroup" => CreateGroup { name: msg.read_all::<(String,)>().map_err(invalid)?.0 },
+         "RenameGroup" => {
+            let (name, new_name) = msg.read_all().map_err(invalid)?;
+            RenameGroup { name, new_name }
+         }
+         "SetGroupMembers" => {
+            let (name, members) = msg.read_all().map_err(invalid)?;
+            SetGroupMembers { name, members }
+         }
+         "AddGroupMember" => {
+            let (name, username) = msg.read_all().map_err(invalid)?;
+            AddGroupMember { name, username }
+         }
+         "RemoveGroupMember" => {
+            let (name, username) = msg.read_all().map_err(invalid)?;
+            RemoveGroupMember { name, username }
+         }
+         "DeleteGroup" => DeleteGroup { name: msg.read_all::<(String,)>().map_err(invalid)?.0 },
+         "SetTimezone" => SetTimezone { timezone: msg.read_all::<(String,)>().map_err(invalid)?.0 },
+         "WriteLocaleConf" => WriteLocaleConf { contents: msg.read_all::<(String,)>().map_err(invalid)?.0 },
+         _ => return Err(Error::Validation(format!("unknown method: {}", member))),
+      })
+   }
+}
+
+/// Return the D-Bus error name and message the helper replies with for an error.
+pub(crate) fn to_dbus_error(err: &Error) -> (String, String) {
+   let (kind, message) = match err {
+      Error::AuthorizationDenied => ("NotAuthorized", err.to_string()),
+      Error::AuthorizationCancelled => ("Cancelled", err.to_string()),
+      Error::Validation(msg) => ("InvalidArgs", msg.clone()),
+      Error::MissingTool(tool) => ("MissingTool", tool.clone()),
+      Error::Locked(Locked(action)) => ("Locked", action.clone()),
+      Error::CommandFailed { cmd, code, stderr } => ("CommandFailed", format!("{}\n{}\n{}", cmd, code.map(|code| code.to_string()).unwrap_or_default(), stderr)),
+      _ => ("Failed", err.to_string()),
+   };
+   (format!("{}.Error.{}", HELPER_INTERFACE, kind), message)
+}
+
+/// Convert an error reply of the helper back into the error the helper hit.
+pub(crate) fn from_dbus_error(err: dbus::Error) -> Error {
+   let message = err.message().unwrap_or_default().to_string();
+   match err.name().and_then(|name| name.strip_prefix(HELPER_INTERFACE)).and_then(|name| name.strip_prefix(".Error.")) {
+      Some("NotAuthorized") => Error::AuthorizationDenied,
+      Some("Cancelled") => Error::AuthorizationCancelled,
+      Some("InvalidArgs") => Error::Validation(message),
+      Some("MissingTool") => Error::MissingTool(message),
+      Some("Locked") => Error::Locked(Locked(message)),
+      Some("CommandFailed") => {
+         let mut parts = message.splitn(3, '\n');
+         Error::CommandFailed {
+            cmd: parts.next().unwrap_or_default().to_string(),
+            code: parts.next().and_then(|code| code.parse().ok()),
+            stderr: parts.next().unwrap_or_default().to_string(),
+         }
+      }
+      _ => Error::DBus(err),
+   }
+}
+
+fn chpasswd(username: &str, password: &str) -> CommandLine {
+   CommandLine::new(CHPASSWD, &[] as &[&str]).stdin(format!("{}:{}\n", username, password))
+}
+
+/// Check a value written to a colon separated account database field.
+fn check_field(val: &str) -> Result<(), Error> {
+   if val.contains([':', '\n']) {
+      Err(Error::Validation(format!("invalid account field: {}", val)))
+   } else {
+      Ok(())
+   }
+}
+
+fn check_password(password: &str) -> Result<(), Error> {
+   if password.is_empty() || password.contains('\n') {
+      Err(Error::Validation(String::from("invalid password")))
+   } else {
+      Ok(())
+   }
+}
+
+fn check_path(val: &str) -> Result<(), Error> {
+   let path = Path::new(val);
+   if path.is_absolute() && !path.components().any(|comp| comp == std::path::Component::ParentDir) {
+      check_field(val)
+   } else {
+      Err(Error::Validation(format!("invalid path: {}", val)))
+   }
+}
+
+/// Check that a locale.conf only assigns locale variables.
+pub(crate) fn check_locale_conf(contents: &str) -> Result<(), Error> {
+   contents.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')).try_for_each(|line| {
+      let valid = line.split_once('=').is_some_and(|(key, val)| LOCALE_CONF_KEYS.contains(&key) && val.chars().all(|c| c.is_ascii_alphanumeric() || "._-@:\"".contains(c)));
+      if valid {
+         Ok(())
+      } else {
+         Err(Error::Validation(format!("invalid locale.conf line: {}", line)))
+      }
+   })
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{check_locale_conf, from_dbus_error, to_dbus_error, Operation};
+   use crate::error::Error;
+   use crate::helpers::{Locked, Lockdown};
+   use std::collections::HashMap;
+
+   #[test]
+   fn test_operation_message() {
+      let changes: HashMap<String, String> = vec![(String::from("login"), String::from("koompi")), (String::from("shell"), String::from("/bin/zsh"))].into_iter().collect();
+      let ops = vec![
+         Operation::CreateUser { fullname: String::from("Koompi"), username: String::from("koompi"), admin: true, password: String::from("1111") },
+         Operation::ModifyUser { username: String::from("test"), changes },
+         Operation::SetGroupMembers { name: String::from("users"), members: vec![String::from("koompi"), String::from("test")] },
+         Operation::WriteLocaleConf { contents: String::from("LANG=km_KH.UTF-8\n") },
+      ];
+      for op in ops {
+         assert!(Operation::from_message(&op.to_message()).ok() == Some(op.clone()));
+      }
+
+      let op = Operation::ModifyUser { username: String::from("test"), changes: vec![(String::from("shell"), String::from("/bin/zsh")), (String::from("uid"), String::from("1005"))].into_iter().collect() };
+      assert_eq!(op.commands()[0].to_string(), "usermod -u 1005 -s /bin/zsh test");
+      let op = Operation::CreateUser { fullname: String::from("Koompi"), username: String::from("koompi"), admin: true, password: String::from("1111") };
+      assert_eq!(op.commands().iter().map(ToString::to_string).collect::<Vec<String>>(), vec!["useradd -c Koompi -m -N koompi", "usermod -a -G input,cups,wheel koompi", "chpasswd"]);
+   }
+
+   #[test]
+   fn test_validate() {
+      assert!(Operation::SetTimezone { timezone: String::from("../../etc/shadow") }.validate().is_err());
+      assert!(Operation::ModifyUser { username: String::from("test"), changes: vec![(String::from("home"), String::from("/home/../etc"))].into_iter().collect() }.validate().is_err());
+      assert!(Operation::CreateUser { fullname: String::from("a:b"), username: String::from("test"), admin: false, password: String::from("1") }.validate().is_err());
+      assert!(check_locale_conf("LANG=en_US.UTF-8\nLANGUAGE=km:en_US\n# comment\n").is_ok());
+      assert!(check_locale_conf("LD_PRELOAD=/tmp/evil.so\n").is_err());
+   }
+
+   #[test]
+   fn test_check_lockdown() {
+      let lockdown: Lockdown = toml::from_str("disabled_actions = [\"users.create-user\"]").unwrap();
+      let op = Operation::CreateUser { fullname: String::from("Koompi"), username: String::from("koompi"), admin: false, password: String::from("1111") };
+      assert!(matches!(op.check_lockdown(&lockdown), Err(Error::Locked(_))));
+      assert!(op.check_lockdown(&Lockdown::default()).is_ok());
+      assert!(Operation::DeleteGroup { name: String::from("koompi") }.check_lockdown(&lockdown).is_ok());
+   }
+
+   #[test]
+   fn test_error_round_trip() {
+      let (name, message) = to_dbus_error(&Error::CommandFailed { cmd: String::from("useradd"), code: Some(9), stderr: String::from("useradd: user 'test' already exists") });
+      let err = from_dbus_error(dbus::Error::new_custom(name.as_str(), &message));
+      assert!(matches!(err, Error::CommandFailed { ref cmd, code: Some(9), .. } if cmd == "useradd"));
+      let (name, message) = to_dbus_error(&Error::AuthorizationCancelled);
+      assert!(matches!(from_dbus_error(dbus::Error::new_custom(name.as_str(), &message)), Error::AuthorizationCancelled));
+      let (name, message) = to_dbus_error(&Error::Locked(Locked(String::from("users.create-user"))));
+      assert!(matches!(from_dbus_error(dbus::Error::new_custom(name.as_str(), &message)), Error::Locked(Locked(ref action)) if action == "users.create-user"));
+   }
+}
@@ -0,0 +1,47 @@
+use std::time::Duration;
+use dbus::blocking::Connection;
+use crate::error::Error;
+use crate::helpers::Runner;
+use super::{from_dbus_error, Operation};
+
+/// The helper waits for the user to authenticate before replying.
+const HELPER_TIMEOUT: Duration = Duration::from_secs(300);
+/// Errors of the bus meaning the helper isn't installed or can't be started.
+const UNAVAILABLE_ERRORS: [&str; 4] = ["org.freedesktop.DBus.Error.ServiceUnknown", "org.freedesktop.DBus.Error.NameHasNoOwner", "org.freedesktop.DBus.Error.Spawn.", "org.freedesktop.DBus.Error.NoServer"];
+
+/// Structure of the privileged helper client. Operations go through the helper service so the user authenticates once,
+/// falling back to running each command through pkexec where the helper isn't installed.
+#[derive(Debug, Clone, Default)]
+pub struct PrivilegedClient {
+   runner: Runner,
+}
+
+impl PrivilegedClient {
+   /// Create a client running the pkexec fallback through the given runner.
+   pub fn new(runner: Runner) -> Self {
+      Self { runner }
+   }
+
+   /// This method is used to run a privileged operation.
+   /// Fails with `Error::AuthorizationDenied` or `Error::AuthorizationCancelled` if the user isn't authorized.
+   pub fn call(&self, op: &Operation) -> Result<(), Error> {
+      op.validate()?;
+      match call_helper(op) {
+         Some(res) => res,
+         None => {
+            log::debug!("privileged helper unavailable, running {} through pkexec", op.method());
+            op.commands().into_iter().try_for_each(|cmd| self.runner.exec(&cmd.pkexec()).map(|_| ()))
+         }
+      }
+   }
+}
+
+/// Call the helper service, or return None if it isn't available.
+fn call_helper(op: &Operation) -> Option<Result<(), Error>> {
+   let conn = Connection::new_system().ok()?;
+   match conn.channel().send_with_reply_and_block(op.to_message(), HELPER_TIMEOUT) {
+      Ok(_) => Some(Ok(())),
+      Err(err) if err.name().is_some_and(|name| UNAVAILABLE_ERRORS.iter().any(|unavailable| name.starts_with(unavailable))) => None,
+      Err(err) => Some(Err(from_dbus_error(err))),
+   }
+}
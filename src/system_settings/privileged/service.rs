@@ -0,0 +1,89 @@
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use dbus::{Message, arg::{RefArg, Variant}, blocking::{Connection, LocalConnection}, channel::{MatchingReceiver, Sender}, message::MatchRule, strings::ErrorName};
+use crate::error::Error;
+use crate::helpers::{write_content_atomic, CommandRunner, Lockdown, SystemRunner};
+use super::{to_dbus_error, Operation, HELPER_INTERFACE, HELPER_SERVICE, LOCALE_CONF_PATH};
+
+const POLKIT_SERVICE: &str = "org.freedesktop.PolicyKit1";
+const POLKIT_PATH: &str = "/org/freedesktop/PolicyKit1/Authority";
+const POLKIT_INTERFACE: &str = "org.freedesktop.PolicyKit1.Authority";
+const POLKIT_CANCELLED: &str = "org.freedesktop.PolicyKit1.Error.Cancelled";
+/// CheckAuthorization flag letting polkit ask the user to authenticate.
+const ALLOW_USER_INTERACTION: u32 = 1;
+/// The user may take a while to type the password.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(300);
+/// The helper is bus activated and exits once unused for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Reply of CheckAuthorization: is authorized, is challenge, details.
+type AuthorizationResult = (bool, bool, HashMap<String, String>);
+
+/// Run the privileged helper on the system bus until it has been idle for a minute.
+/// Each call is authorized through polkit for the calling bus name, so `auth_admin_keep` actions prompt once per session.
+pub fn run_service() -> Result<(), Error> {
+   let conn = LocalConnection::new_system()?;
+   conn.request_name(HELPER_SERVICE, false, true, true)?;
+   let polkit = Connection::new_system()?;
+   let runner = SystemRunner::default();
+   let last_call = Rc::new(Cell::new(Instant::now()));
+   let last_call_cb = last_call.clone();
+
+   conn.start_receive(MatchRule::new_method_call(), Box::new(move |msg, conn| {
+      last_call_cb.set(Instant::now());
+      let reply = match handle_call(&polkit, &runner, &msg) {
+         Ok(()) => msg.method_return(),
+         Err(err) => {
+            log::warn!("{} failed: {}", msg.member().map(|member| member.to_string()).unwrap_or_default(), err);
+            let (name, message) = to_dbus_error(&err);
+            msg.error(&ErrorName::new(name).unwrap(), &CString::new(message.replace('\0', "")).unwrap())
+         }
+      };
+      if !msg.get_no_reply() {
+         let _ = conn.send(reply);
+      }
+      true
+   }));
+
+   while last_call.get().elapsed() < IDLE_TIMEOUT {
+      conn.process(Duration::from_secs(1))?;
+   }
+   Ok(())
+}
+
+fn handle_call(polkit: &Connection, runner: &dyn CommandRunner, msg: &Message) -> Result<(), Error> {
+   if msg.interface().as_deref() != Some(HELPER_INTERFACE) {
+      return Err(Error::Validation(format!("unknown interface: {}", msg.interface().map(|iface| iface.to_string()).unwrap_or_default())));
+   }
+   let op = Operation::from_message(msg)?;
+   op.validate()?;
+   // refuse before polkit prompts for a password the admin disabled anyway
+   op.check_lockdown(&Lockdown::load())?;
+   let sender = msg.sender().ok_or(Error::AuthorizationDenied)?;
+   check_authorization(polkit, &sender, op.action_id())?;
+
+   match &op {
+      Operation::WriteLocaleConf { contents } => Ok(write_content_atomic(LOCALE_CONF_PATH, contents)?),
+      _ => op.commands().iter().try_for_each(|cmd| runner.exec(cmd).map(|_| ())),
+   }
+}
+
+/// Ask polkit whether the caller is allowed to perform an action, letting it prompt for authentication.
+fn check_authorization(polkit: &Connection, sender: &str, action_id: &str) -> Result<(), Error> {
+   let proxy = polkit.with_proxy(POLKIT_SERVICE, POLKIT_PATH, AUTH_TIMEOUT);
+   let mut subject: HashMap<&str, Variant<Box<dyn RefArg>>> = HashMap::new();
+   subject.insert("name", Variant(Box::new(sender.to_string())));
+   let details: HashMap<&str, &str> = HashMap::new();
+
+   let res: Result<(AuthorizationResult,), dbus::Error> = proxy.method_call(POLKIT_INTERFACE, "CheckAuthorization", (("system-bus-name", subject), action_id, details, ALLOW_USER_INTERACTION, ""));
+   match res {
+      Ok(((true, _, _),)) => Ok(()),
+      Ok(((false, _, result),)) if result.get("polkit.dismissed").map(String::as_str) == Some("true") => Err(Error::AuthorizationCancelled),
+      Ok(_) => Err(Error::AuthorizationDenied),
+      Err(err) if err.name() == Some(POLKIT_CANCELLED) => Err(Error::AuthorizationCancelled),
+      Err(err) => Err(err.into()),
+   }
+}
@@ -1,48 +1,39 @@
-use serde::{Deserialize, Serialize};
-use std::fs::File;
-use std::io::{prelude::*, Error, Result};
-use toml::{from_str, to_string_pretty};
-use std::fs;
+mod appearance_portal;
+mod color_scheme;
+mod cursor_theme;
 
+pub use appearance_portal::AppearancePortal;
+pub use color_scheme::*;
+pub(crate) use cursor_theme::set_gtk_settings;
+pub use cursor_theme::{CursorImage, CursorManager, CursorSettings, CursorTheme, CursorThemeResource, STANDARD_CURSORS};
 
-const HOME: &'static str = env!("HOME");
-fn main() {
-    //  generate default
-    let theme = Theme::default();
-    create_dir();
-    // write data to file
-    let my_toml = to_string_pretty(&theme).unwrap();
-    writer("theme.conf", &theme).unwrap();
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use toml::to_string_pretty;
+use crate::desktop::DesktopError;
+use crate::helpers::{constants::LOCAL_CONF, write_content_atomic};
 
-    // read data from file
-    let str_data = reader("theme.conf");
-    let data: Theme = from_str(&str_data.unwrap()).unwrap();
+const THEME_DIR: &str = "koompi/theme";
 
-    println!("{:#?}", data.info.name);
+fn theme_dir() -> PathBuf {
+    LOCAL_CONF.join(THEME_DIR)
 }
 
-pub fn reader(name: &str) -> Result<String> {
-    let path = std::path::Path::new(format!("{}/.config/koompi/theme", HOME).as_str()).join(name);
-
-    std::fs::read_to_string(path)
-    
+/// This function is used to read a theme file from `~/.config/koompi/theme`.
+pub fn reader(name: &str) -> io::Result<String> {
+    fs::read_to_string(theme_dir().join(name))
 }
 
-pub fn writer(name: &str, data: &Theme) -> Result<()> {
-    
-    let path = std::path::Path::new(format!("{}/.config/koompi/theme", HOME).as_str()).join(name);
-   
-    let mut file = File::create(path).unwrap();
-    match file.write_all(to_string_pretty(data).unwrap().as_bytes()) {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e),
-        }
-    
+pub fn writer(name: &str, data: &Theme) -> Result<(), DesktopError> {
+    Ok(write_content_atomic(theme_dir().join(name), &to_string_pretty(data)?)?)
 }
-pub fn create_dir() -> std::io::Result<()>{
-    fs::create_dir_all(format!("{}/.config/koompi/theme",HOME))?;
-    Ok(())
+
+pub fn create_dir() -> io::Result<()> {
+    fs::create_dir_all(theme_dir())
 }
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Theme {
     info: Info,
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use dbus::{arg::Variant, blocking::Connection, channel::{MatchingReceiver, Sender}, message::MatchRule, Error, Message};
+use crate::desktop::PersistentData;
+use super::color_scheme::{AppearanceConf, AppearanceManager, ColorScheme};
+
+const PORTAL_SERVICE: &str = "org.freedesktop.impl.portal.desktop.koompi";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS_INTERFACE: &str = "org.freedesktop.impl.portal.Settings";
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+const NOT_FOUND_ERROR: &str = "org.freedesktop.portal.Error.NotFound";
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+const SETTINGS_VERSION: u32 = 1;
+/// Upper bound between re-evaluations of the automatic schedule while serving.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Backend of the xdg-desktop-portal `Settings` interface, publishing the `org.freedesktop.appearance color-scheme`
+/// setting so portal-aware applications follow the Koompi color scheme.
+///
+/// xdg-desktop-portal finds this backend through `data/koompi.portal`, installed to `/usr/share/xdg-desktop-portal/portals`.
+pub struct AppearancePortal {
+    conn: Connection,
+    color_scheme: Arc<Mutex<ColorScheme>>,
+}
+
+impl AppearancePortal {
+    /// Claim the portal backend name on the session bus and start answering settings requests.
+    pub fn new(color_scheme: ColorScheme) -> Result<Self, Error> {
+        let conn = Connection::new_session()?;
+        conn.request_name(PORTAL_SERVICE, false, true, false)?;
+
+        let color_scheme = Arc::new(Mutex::new(color_scheme));
+        let shared_scheme = color_scheme.clone();
+        let mut rule = MatchRule::new_method_call();
+        rule.path = Some(PORTAL_PATH.into());
+        conn.start_receive(rule, Box::new(move |msg, conn| {
+            let scheme = *shared_scheme.lock().unwrap();
+            if let Some(reply) = handle_call(&msg, scheme) {
+                let _ = conn.send(reply);
+            }
+            true
+        }));
+
+        Ok(Self { conn, color_scheme })
+    }
+
+    /// Return the color scheme currently published.
+    pub fn color_scheme(&self) -> ColorScheme {
+        *self.color_scheme.lock().unwrap()
+    }
+
+    /// Publish a color scheme, emitting `SettingChanged` if it differs from the current one.
+    pub fn set_color_scheme(&self, scheme: ColorScheme) -> Result<(), Error> {
+        let mut current = self.color_scheme.lock().unwrap();
+        if *current != scheme {
+            *current = scheme;
+            let signal = Message::new_signal(PORTAL_PATH, SETTINGS_INTERFACE, "SettingChanged").unwrap().append3(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY, Variant(scheme.portal_value()));
+            self.conn.send(signal).map_err(|_| Error::new_failed("Failed to emit SettingChanged"))?;
+        }
+        Ok(())
+    }
+
+    /// Handle incoming requests for up to `timeout`.
+    pub fn process(&self, timeout: Duration) -> Result<bool, Error> {
+        self.conn.process(timeout)
+    }
+
+    /// Serve requests forever, following the color scheme of the given manager including its automatic schedule.
+    /// Preferences saved by other processes, timezone and daylight saving changes are picked up on the way.
+    pub fn run(&self, manager: &mut AppearanceManager) -> Result<(), Error> {
+        let watcher = AppearanceConf::watch().map_err(|err| Error::new_failed(&err.to_string()))?;
+        loop {
+            if let Some(conf) = watcher.try_recv() {
+                manager.reload(conf);
+            }
+            manager.refresh_clock();
+            self.set_color_scheme(manager.color_scheme())?;
+            let timeout = manager.next_switch().map_or(RECHECK_INTERVAL, |next| next.min(RECHECK_INTERVAL));
+            self.conn.process(timeout)?;
+        }
+    }
+}
+
+/// Build the reply to a method call on the portal object, or None for calls this backend doesn't answer.
+fn handle_call(msg: &Message, scheme: ColorScheme) -> Option<Message> {
+    let interface = msg.interface()?;
+    let member = msg.member()?;
+
+    match (&*interface, &*member) {
+        (SETTINGS_INTERFACE, "ReadAll") => {
+            let namespaces: Vec<String> = msg.read1().unwrap_or_default();
+            let mut settings: HashMap<String, HashMap<String, Variant<u32>>> = HashMap::new();
+            if namespaces.is_empty() || namespaces.iter().any(|ns| namespace_matches(ns)) {
+                settings.entry(APPEARANCE_NAMESPACE.to_string()).or_default().insert(COLOR_SCHEME_KEY.to_string(), Variant(scheme.portal_value()));
+            }
+            Some(msg.method_return().append1(settings))
+        }
+        (SETTINGS_INTERFACE, "Read") => match msg.read2::<&str, &str>() {
+            Ok((APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY)) => Some(msg.method_return().append1(Variant(scheme.portal_value()))),
+            _ => Some(msg.error(&NOT_FOUND_ERROR.into(), &CString::new("Requested setting not found").unwrap())),
+        },
+        (PROPERTIES_INTERFACE, "Get") => Some(msg.method_return().append1(Variant(SETTINGS_VERSION))),
+        (PROPERTIES_INTERFACE, "GetAll") => {
+            let props: HashMap<&str, Variant<u32>> = vec![("version", Variant(SETTINGS_VERSION))].into_iter().collect();
+            Some(msg.method_return().append1(props))
+        }
+        _ => None,
+    }
+}
+
+/// Check a `ReadAll` namespace pattern, which may end with a `*` glob, against the appearance namespace.
+fn namespace_matches(pattern: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => APPEARANCE_NAMESPACE.starts_with(prefix),
+        None => pattern == APPEARANCE_NAMESPACE,
+    }
+}
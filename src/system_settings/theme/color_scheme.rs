@@ -0,0 +1,343 @@
+use std::f64::consts::PI;
+use std::fmt::{self, Display, Formatter};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::desktop::{DesktopError, PersistentData};
+use crate::helpers::exec_cmd;
+use crate::system_settings::datetime::DateTimeManager;
+
+const APPEARANCE_CONF: &str = "appearance.toml";
+const DATE: &str = "date";
+const MINUTES_PER_DAY: f64 = 1440.0;
+/// Zenith of the sun at sunrise/sunset, accounting for refraction and the solar disc.
+const SUN_ZENITH: f64 = 90.833;
+/// Fixed schedule used for sunrise/sunset when the timezone has no known location.
+const FALLBACK_SCHEDULE: ((u8, u8), (u8, u8)) = ((6, 0), (18, 0));
+/// How long to wait before checking again while the sun neither rises nor sets.
+const POLAR_RECHECK: Duration = Duration::from_secs(3600);
+
+/// Color scheme chosen by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorSchemePref {
+    #[default]
+    Light,
+    Dark,
+    Auto,
+}
+
+impl ColorSchemePref {
+    pub const ALL: [ColorSchemePref; 3] = [
+        ColorSchemePref::Light, ColorSchemePref::Dark, ColorSchemePref::Auto
+    ];
+}
+
+impl Display for ColorSchemePref {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use ColorSchemePref::*;
+        write!(f, "{}", match self {
+            Light => "Light",
+            Dark => "Dark",
+            Auto => "Automatic",
+        })
+    }
+}
+
+/// Color scheme in effect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+impl ColorScheme {
+    /// Value of the freedesktop `org.freedesktop.appearance color-scheme` setting (1: prefer dark, 2: prefer light).
+    pub fn portal_value(&self) -> u32 {
+        match self {
+            ColorScheme::Light => 2,
+            ColorScheme::Dark => 1,
+        }
+    }
+}
+
+impl Display for ColorScheme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", match self {
+            ColorScheme::Light => "Light",
+            ColorScheme::Dark => "Dark",
+        })
+    }
+}
+
+/// When automatic mode switches between light and dark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Schedule {
+    /// Switch at fixed local times, given as (hour, minute).
+    Fixed { light_at: (u8, u8), dark_at: (u8, u8) },
+    /// Switch at local sunrise and sunset.
+    #[default]
+    SunriseSunset,
+}
+
+impl Schedule {
+    /// Check that fixed times are valid times of day.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Schedule::Fixed { light_at, dark_at } => [light_at, dark_at].iter().all(|(hour, minute)| *hour < 24 && *minute < 60),
+            Schedule::SunriseSunset => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppearanceConf {
+    pub color_scheme: ColorSchemePref,
+    pub schedule: Schedule,
+}
+
+impl PersistentData for AppearanceConf {
+    fn relative_path() -> PathBuf {
+        PathBuf::from("theme").join(APPEARANCE_CONF)
+    }
+}
+
+/// Structure of Appearance Manager
+#[derive(Debug, Clone)]
+pub struct AppearanceManager {
+    conf: AppearanceConf,
+    coordinates: Option<(f64, f64)>,
+    /// Offset of local time from UTC in seconds, as of the last refresh
+    utc_offset: f64,
+}
+
+/// Position within the daily schedule, as minutes of day on the schedule's clock.
+enum Window {
+    Daily { now: f64, light_at: f64, dark_at: f64 },
+    Polar(ColorScheme),
+}
+
+// Public API
+impl AppearanceManager {
+    /// Load the appearance config, locate the current timezone for sunrise/sunset and read its UTC offset.
+    pub fn new() -> Result<Self, DesktopError> {
+        let conf = AppearanceConf::load()?;
+        let mut manager = Self { conf, coordinates: None, utc_offset: 0.0 };
+        manager.refresh_clock();
+        Ok(manager)
+    }
+
+    pub fn config(&self) -> &AppearanceConf {
+        &self.conf
+    }
+
+    /// Replace the config without saving it, e.g. with one reloaded by `AppearanceConf::watch`.
+    pub fn reload(&mut self, conf: AppearanceConf) {
+        self.conf = conf;
+    }
+
+    /// Read the timezone and UTC offset again, picking up timezone and daylight saving changes.
+    pub fn refresh_clock(&mut self) {
+        self.coordinates = DateTimeManager::new().ok().and_then(|dt_mn| dt_mn.coordinates());
+        self.utc_offset = utc_offset_secs();
+    }
+
+    /// Location used for sunrise/sunset, None if the timezone has no entry in the tz database.
+    pub fn coordinates(&self) -> Option<(f64, f64)> {
+        self.coordinates
+    }
+
+    /// Change and save the color scheme preference, returning the scheme now in effect.
+    pub fn set_preference(&mut self, pref: ColorSchemePref) -> Result<ColorScheme, DesktopError> {
+        self.conf.color_scheme = pref;
+        self.conf.save()?;
+        Ok(self.color_scheme())
+    }
+
+    /// Change and save the automatic switching schedule, returning the scheme now in effect.
+    /// Fails with `DesktopError::InvalidValue` for fixed times past 23:59.
+    pub fn set_schedule(&mut self, schedule: Schedule) -> Result<ColorScheme, DesktopError> {
+        if !schedule.is_valid() {
+            return Err(DesktopError::InvalidValue(format!("{:?}", schedule)));
+        }
+        self.conf.schedule = schedule;
+        self.conf.save()?;
+        Ok(self.color_scheme())
+    }
+
+    /// Return the color scheme in effect right now.
+    pub fn color_scheme(&self) -> ColorScheme {
+        self.color_scheme_at(unix_now())
+    }
+
+    /// Return how long until automatic switching changes the scheme, or None if the preference is not automatic.
+    pub fn next_switch(&self) -> Option<Duration> {
+        self.next_switch_at(unix_now())
+    }
+}
+
+// Private API
+impl AppearanceManager {
+    fn color_scheme_at(&self, unix_secs: f64) -> ColorScheme {
+        match self.conf.color_scheme {
+            ColorSchemePref::Light => ColorScheme::Light,
+            ColorSchemePref::Dark => ColorScheme::Dark,
+            ColorSchemePref::Auto => match self.window(unix_secs) {
+                Window::Daily { now, light_at, dark_at } => if in_window(now, light_at, dark_at) {
+                    ColorScheme::Light
+                } else {
+                    ColorScheme::Dark
+                },
+                Window::Polar(scheme) => scheme,
+            },
+        }
+    }
+
+    fn next_switch_at(&self, unix_secs: f64) -> Option<Duration> {
+        if self.conf.color_scheme != ColorSchemePref::Auto {
+            return None;
+        }
+
+        match self.window(unix_secs) {
+            Window::Daily { now, light_at, dark_at } => {
+                let next = if in_window(now, light_at, dark_at) { dark_at } else { light_at };
+                let minutes = (next - now).rem_euclid(MINUTES_PER_DAY);
+                Some(Duration::from_secs_f64(minutes * 60.0).max(Duration::from_secs(1)))
+            }
+            Window::Polar(_) => Some(POLAR_RECHECK),
+        }
+    }
+
+    fn window(&self, unix_secs: f64) -> Window {
+        let fixed = |(light_at, dark_at): ((u8, u8), (u8, u8))| Window::Daily {
+            now: minute_of_day(unix_secs + self.utc_offset),
+            light_at: f64::from(light_at.0) * 60.0 + f64::from(light_at.1),
+            dark_at: f64::from(dark_at.0) * 60.0 + f64::from(dark_at.1),
+        };
+
+        match (self.conf.schedule, self.coordinates) {
+            // a hand edited config may hold invalid times
+            (schedule, _) if !schedule.is_valid() => fixed(FALLBACK_SCHEDULE),
+            (Schedule::Fixed { light_at, dark_at }, _) => fixed((light_at, dark_at)),
+            (Schedule::SunriseSunset, Some((lat, lon))) => sun_window(unix_secs, lat, lon),
+            (Schedule::SunriseSunset, None) => fixed(FALLBACK_SCHEDULE),
+        }
+    }
+}
+
+/// Sunrise and sunset in minutes after UTC midnight, using the NOAA approximation of the solar position.
+fn sun_window(unix_secs: f64, lat: f64, lon: f64) -> Window {
+    // Fraction of the tropical year since the epoch; accurate to within a day, plenty for sunrise/sunset.
+    let gamma = 2.0 * PI * (unix_secs / 86400.0 / 365.2425).fract();
+    let eqtime = 229.18 * (0.000075 + 0.001868 * gamma.cos() - 0.032077 * gamma.sin() - 0.014615 * (2.0 * gamma).cos() - 0.040849 * (2.0 * gamma).sin());
+    let decl = 0.006918 - 0.399912 * gamma.cos() + 0.070257 * gamma.sin() - 0.006758 * (2.0 * gamma).cos() + 0.000907 * (2.0 * gamma).sin() - 0.002697 * (3.0 * gamma).cos() + 0.00148 * (3.0 * gamma).sin();
+    let lat_rad = lat.to_radians();
+    let cos_ha = SUN_ZENITH.to_radians().cos() / (lat_rad.cos() * decl.cos()) - lat_rad.tan() * decl.tan();
+
+    if cos_ha > 1.0 {
+        Window::Polar(ColorScheme::Dark)
+    } else if cos_ha < -1.0 {
+        Window::Polar(ColorScheme::Light)
+    } else {
+        let ha = cos_ha.acos().to_degrees();
+        Window::Daily {
+            now: minute_of_day(unix_secs),
+            light_at: (720.0 - 4.0 * (lon + ha) - eqtime).rem_euclid(MINUTES_PER_DAY),
+            dark_at: (720.0 - 4.0 * (lon - ha) - eqtime).rem_euclid(MINUTES_PER_DAY),
+        }
+    }
+}
+
+/// Check whether `now` falls between `start` and `end`, wrapping around midnight.
+fn in_window(now: f64, start: f64, end: f64) -> bool {
+    if start <= end {
+        start <= now && now < end
+    } else {
+        now >= start || now < end
+    }
+}
+
+fn minute_of_day(secs: f64) -> f64 {
+    (secs / 60.0).rem_euclid(MINUTES_PER_DAY)
+}
+
+fn unix_now() -> f64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|dur| dur.as_secs_f64()).unwrap_or_default()
+}
+
+/// Offset of local time from UTC in seconds, parsed from `date +%z` (e.g. `+0700`).
+fn utc_offset_secs() -> f64 {
+    match exec_cmd(DATE, vec!["+%z"]) {
+        Ok(offset) if offset.len() == 5 => {
+            let sign = if offset.starts_with('-') { -1.0 } else { 1.0 };
+            let hours: f64 = offset[1..3].parse().unwrap_or_default();
+            let minutes: f64 = offset[3..5].parse().unwrap_or_default();
+            sign * (hours * 3600.0 + minutes * 60.0)
+        }
+        _ => 0.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{in_window, sun_window, AppearanceConf, AppearanceManager, ColorScheme, ColorSchemePref, Schedule, Window};
+    use std::time::Duration;
+
+    /// 2021-06-21 00:00 UTC
+    const MIDNIGHT_UTC: f64 = 1_624_233_600.0;
+
+    fn manager(color_scheme: ColorSchemePref, schedule: Schedule) -> AppearanceManager {
+        // Phnom Penh is 7 hours ahead of UTC
+        AppearanceManager { conf: AppearanceConf { color_scheme, schedule }, coordinates: None, utc_offset: 7.0 * 3600.0 }
+    }
+
+    #[test]
+    fn test_sun_window() {
+        // 2021-06-21 00:00 UTC in Phnom Penh: sunrise ~22:30 UTC, sunset ~11:30 UTC.
+        match sun_window(1_624_233_600.0, 11.55, 104.92) {
+            Window::Daily { light_at, dark_at, .. } => {
+                assert!((light_at - 1350.0).abs() < 20.0, "sunrise at {}", light_at);
+                assert!((dark_at - 690.0).abs() < 20.0, "sunset at {}", dark_at);
+                assert!(in_window(0.0, light_at, dark_at));
+                assert!(!in_window(800.0, light_at, dark_at));
+            }
+            Window::Polar(_) => panic!("the sun rises in Phnom Penh"),
+        }
+
+        // Midsummer above the arctic circle.
+        assert!(matches!(sun_window(1_624_233_600.0, 78.22, 15.65), Window::Polar(super::ColorScheme::Light)));
+    }
+
+    #[test]
+    fn test_fixed_schedule() {
+        let auto = manager(ColorSchemePref::Auto, Schedule::Fixed { light_at: (7, 0), dark_at: (19, 0) });
+        // 07:00 and 19:30 local time
+        assert_eq!(auto.color_scheme_at(MIDNIGHT_UTC), ColorScheme::Light);
+        assert_eq!(auto.next_switch_at(MIDNIGHT_UTC), Some(Duration::from_secs(12 * 3600)));
+        assert_eq!(auto.color_scheme_at(MIDNIGHT_UTC + 12.5 * 3600.0), ColorScheme::Dark);
+        assert_eq!(auto.next_switch_at(MIDNIGHT_UTC + 12.5 * 3600.0), Some(Duration::from_secs(11 * 3600 + 1800)));
+
+        // a dark window across midnight
+        let night = manager(ColorSchemePref::Auto, Schedule::Fixed { light_at: (1, 0), dark_at: (22, 0) });
+        assert_eq!(night.color_scheme_at(MIDNIGHT_UTC - 8.0 * 3600.0), ColorScheme::Dark);
+        assert_eq!(night.next_switch_at(MIDNIGHT_UTC - 8.0 * 3600.0), Some(Duration::from_secs(2 * 3600)));
+
+        // invalid times fall back to 06:00 - 18:00
+        let invalid = manager(ColorSchemePref::Auto, Schedule::Fixed { light_at: (24, 0), dark_at: (18, 60) });
+        assert!(!invalid.conf.schedule.is_valid());
+        assert_eq!(invalid.color_scheme_at(MIDNIGHT_UTC - 1.5 * 3600.0), ColorScheme::Dark);
+        assert_eq!(invalid.next_switch_at(MIDNIGHT_UTC - 1.5 * 3600.0), Some(Duration::from_secs(1800)));
+    }
+
+    #[test]
+    fn test_color_scheme_pref() {
+        let dark = manager(ColorSchemePref::Dark, Schedule::Fixed { light_at: (7, 0), dark_at: (19, 0) });
+        assert_eq!(dark.color_scheme_at(MIDNIGHT_UTC), ColorScheme::Dark);
+        assert_eq!(dark.next_switch_at(MIDNIGHT_UTC), None);
+        assert_eq!(manager(ColorSchemePref::Light, Schedule::SunriseSunset).color_scheme_at(MIDNIGHT_UTC - 8.0 * 3600.0), ColorScheme::Light);
+
+        // without a known location, sunrise and sunset fall back to 06:00 - 18:00
+        let auto = manager(ColorSchemePref::Auto, Schedule::SunriseSunset);
+        assert_eq!(auto.color_scheme_at(MIDNIGHT_UTC), ColorScheme::Light);
+        assert_eq!(auto.next_switch_at(MIDNIGHT_UTC), Some(Duration::from_secs(11 * 3600)));
+    }
+}
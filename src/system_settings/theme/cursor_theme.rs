@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::error::Error;
+use crate::helpers::{read_content, write_content_overwrite, CommandLine, Resources, Runner};
+
+const ICONS_DIR: &str = "icons";
+const CURSORS_DIR: &str = "cursors";
+const INDEX_THEME: &str = "index.theme";
+const ICON_THEME_SECTION: &str = "Icon Theme";
+const GTK_SETTINGS_SECTION: &str = "Settings";
+const GTK_SETTINGS_DIRS: [&str; 2] = ["gtk-3.0", "gtk-4.0"];
+const GTK_CURSOR_THEME: &str = "gtk-cursor-theme-name";
+const GTK_CURSOR_SIZE: &str = "gtk-cursor-theme-size";
+const XRESOURCES: &str = ".Xresources";
+const XCURSOR_THEME: &str = "Xcursor.theme";
+const XCURSOR_SIZE: &str = "Xcursor.size";
+const XRDB: &str = "xrdb";
+const XCURSOR_MAGIC: u32 = 0x7275_6358; // "Xcur"
+const XCURSOR_IMAGE_TYPE: u32 = 0xfffd_0002;
+const MAX_INHERIT_DEPTH: usize = 8;
+const DEF_CURSOR_THEME: &str = "Adwaita";
+const DEF_CURSOR_SIZE: u32 = 24;
+
+/// Standard cursor names, each with the legacy X11 names themes may ship instead.
+pub const STANDARD_CURSORS: [(&str, &[&str]); 12] = [
+    ("default", &["left_ptr", "arrow"]),
+    ("pointer", &["hand2", "hand1", "pointing_hand"]),
+    ("text", &["xterm", "ibeam"]),
+    ("wait", &["watch"]),
+    ("progress", &["left_ptr_watch", "half-busy"]),
+    ("help", &["question_arrow", "whats_this"]),
+    ("crosshair", &["cross", "tcross"]),
+    ("move", &["fleur", "size_all"]),
+    ("not-allowed", &["crossed_circle", "forbidden"]),
+    ("grab", &["openhand"]),
+    ("ew-resize", &["sb_h_double_arrow", "h_double_arrow"]),
+    ("ns-resize", &["sb_v_double_arrow", "v_double_arrow"]),
+];
+
+/// Structure of an installed XCursor theme
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CursorTheme {
+    /// Directory name, used to select the theme.
+    pub id: String,
+    pub name: String,
+    pub comment: Option<String>,
+    pub path: PathBuf,
+    pub inherits: Vec<String>,
+}
+
+/// Structure of cursor theme and size in effect
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorSettings {
+    pub theme: String,
+    pub size: u32,
+}
+
+impl Default for CursorSettings {
+    fn default() -> Self {
+        Self {
+            theme: DEF_CURSOR_THEME.to_string(),
+            size: DEF_CURSOR_SIZE,
+        }
+    }
+}
+
+/// Structure of one decoded cursor image, with premultiplied ARGB pixels in row-major order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CursorImage {
+    pub nominal_size: u32,
+    pub width: u32,
+    pub height: u32,
+    pub xhot: u32,
+    pub yhot: u32,
+    pub pixels: Vec<u32>,
+}
+
+pub struct CursorThemeResource;
+impl Resources for CursorThemeResource {
+    fn relative_path() -> PathBuf {
+        PathBuf::from(ICONS_DIR)
+    }
+
+    fn additional_paths() -> Option<Vec<PathBuf>> {
+        dirs_next::home_dir().map(|home| vec![home.join(".icons")])
+    }
+}
+
+/// Structure of Cursor Theme Manager
+#[derive(Debug, Clone, Default)]
+pub struct CursorManager {
+    themes: Vec<CursorTheme>,
+    current: CursorSettings,
+    runner: Runner,
+}
+
+// Public API
+impl CursorManager {
+    /// Scan installed cursor themes and read the current theme and size.
+    pub fn new() -> Self {
+        Self::with_runner(Runner::default())
+    }
+
+    /// Scan installed cursor themes, applying changes to the running X session through the given runner.
+    pub fn with_runner(runner: Runner) -> Self {
+        Self {
+            themes: list_cursor_themes(),
+            current: read_current_settings(),
+            runner,
+        }
+    }
+
+    /// Return installed cursor themes sorted by name.
+    pub fn themes(&self) -> &[CursorTheme] {
+        self.themes.as_slice()
+    }
+
+    /// Return an installed cursor theme by its id.
+    pub fn theme<T: AsRef<str>>(&self, id: T) -> Option<&CursorTheme> {
+        self.themes.iter().find(|theme| theme.id == id.as_ref())
+    }
+
+    /// Return the cursor theme and size in effect.
+    pub fn current(&self) -> &CursorSettings {
+        &self.current
+    }
+
+    /// Change the cursor theme and size in `~/.icons/default/index.theme`, `~/.Xresources` and the GTK settings.
+    pub fn set_cursor<T: AsRef<str>>(&mut self, id: T, size: u32) -> Result<(), Error> {
+        let id = id.as_ref();
+        if self.theme(id).is_none() {
+            return Err(Error::Validation(format!("cursor theme not installed: {}", id)));
+        }
+        let home = dirs_next::home_dir().ok_or_else(|| Error::Validation(String::from("no home directory")))?;
+
+        let default_dir = home.join(".icons").join("default");
+        fs::create_dir_all(&default_dir)?;
+        let default_theme = default_dir.join(INDEX_THEME);
+        let content = read_content(&default_theme).unwrap_or_else(|_| format!("[{}]\nName=Default\nComment=Default Cursor Theme\n", ICON_THEME_SECTION));
+        write_content_overwrite(&default_theme, &set_ini_values(&content, ICON_THEME_SECTION, &[("Inherits", id.to_string())]))?;
+
+        let xresources = home.join(XRESOURCES);
+        let content = set_xresources(&read_content(&xresources).unwrap_or_default(), id, size);
+        write_content_overwrite(&xresources, &content)?;
+        // apply to the running X session, if any
+        if let Err(err) = self.runner.exec(&CommandLine::new(XRDB, &["-merge"]).stdin(content)) {
+            log::warn!("cursor not applied to the X session: {}", err);
+        }
+
+        set_gtk_settings(&[(GTK_CURSOR_THEME, id.to_string()), (GTK_CURSOR_SIZE, size.to_string())])?;
+
+        self.current = CursorSettings { theme: id.to_string(), size };
+        Ok(())
+    }
+
+    /// Render the standard cursors of a theme at the nearest available size, following inherited themes.
+    /// Cursors the theme doesn't provide are left out.
+    pub fn preview<T: AsRef<str>>(&self, id: T, size: u32) -> Vec<(&'static str, CursorImage)> {
+        STANDARD_CURSORS.iter().filter_map(|(name, aliases)| {
+            let mut names = vec![*name];
+            names.extend(aliases.iter());
+            self.find_cursor_file(id.as_ref(), &names, 0).and_then(|file| read_xcursor(file, size).ok()).map(|image| (*name, image))
+        }).collect()
+    }
+}
+
+// Private API
+impl CursorManager {
+    fn find_cursor_file(&self, id: &str, names: &[&str], depth: usize) -> Option<PathBuf> {
+        let theme = self.theme(id)?;
+        names.iter().map(|name| theme.path.join(CURSORS_DIR).join(name)).find(|path| path.exists()).or_else(|| {
+            if depth < MAX_INHERIT_DEPTH {
+                theme.inherits.iter().find_map(|parent| self.find_cursor_file(parent, names, depth + 1))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// List theme directories containing a `cursors` directory. Earlier base paths take precedence for duplicate ids.
+fn list_cursor_themes() -> Vec<CursorTheme> {
+    let mut seen = HashSet::new();
+    let mut themes: Vec<CursorTheme> = CursorThemeResource.paths().into_iter().filter_map(|dir| dir.read_dir().ok()).flat_map(|entries| entries.filter_map(|e| e.ok())).filter_map(|entry| {
+        let path = entry.path();
+        let id = entry.file_name().to_str()?.to_string();
+        if path.join(CURSORS_DIR).is_dir() && seen.insert(id.clone()) {
+            Some(read_theme_index(id, path))
+        } else {
+            None
+        }
+    }).collect();
+    themes.sort_by_key(|theme| theme.name.to_lowercase());
+    themes
+}
+
+fn read_theme_index(id: String, path: PathBuf) -> CursorTheme {
+    let mut theme = CursorTheme { name: id.clone(), id, path, ..CursorTheme::default() };
+    if let Ok(entry) = freedesktop_entry_parser::parse_entry(theme.path.join(INDEX_THEME)) {
+        let section = entry.section(ICON_THEME_SECTION);
+        if let Some(name) = section.attr("Name") {
+            theme.name = name.to_string();
+        }
+        theme.comment = section.attr("Comment").map(ToString::to_string);
+        theme.inherits = section.attr("Inherits").map(|inherits| inherits.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect()).unwrap_or_default();
+    }
+    theme
+}
+
+/// Read the cursor theme and size, preferring GTK settings, then `~/.Xresources`, then `~/.icons/default/index.theme`.
+fn read_current_settings() -> CursorSettings {
+    let mut theme = None;
+    let mut size = None;
+
+    if let Some(config_dir) = dirs_next::config_dir() {
+        let mut ini = configparser::ini::Ini::new_cs();
+        if ini.load(config_dir.join(GTK_SETTINGS_DIRS[0]).join("settings.ini")).is_ok() {
+            theme = ini.get(GTK_SETTINGS_SECTION, GTK_CURSOR_THEME);
+            size = ini.get(GTK_SETTINGS_SECTION, GTK_CURSOR_SIZE).and_then(|size| size.parse().ok());
+        }
+    }
+
+    if let Some(home) = dirs_next::home_dir() {
+        if let Ok(content) = read_content(home.join(XRESOURCES)) {
+            content.lines().filter_map(|line| {
+                let mut kv = line.splitn(2, ':');
+                Some((kv.next()?.trim(), kv.next()?.trim()))
+            }).for_each(|(key, val)| {
+                if key == XCURSOR_THEME && theme.is_none() {
+                    theme = Some(val.to_string());
+                } else if key == XCURSOR_SIZE && size.is_none() {
+                    size = val.parse().ok();
+                }
+            });
+        }
+
+        if theme.is_none() {
+            if let Ok(entry) = freedesktop_entry_parser::parse_entry(home.join(".icons").join("default").join(INDEX_THEME)) {
+                theme = entry.section(ICON_THEME_SECTION).attr("Inherits").map(ToString::to_string);
+            }
+        }
+    }
+
+    let default = CursorSettings::default();
+    CursorSettings {
+        theme: theme.unwrap_or(default.theme),
+        size: size.unwrap_or(default.size),
+    }
+}
+
+/// Replace the Xcursor entries of an Xresources file, keeping every other line.
+fn set_xresources(content: &str, theme: &str, size: u32) -> String {
+    let mut lines: Vec<String> = content.lines().filter(|line| {
+        let key = line.split(':').next().unwrap_or_default().trim();
+        key != XCURSOR_THEME && key != XCURSOR_SIZE
+    }).map(ToString::to_string).collect();
+    lines.push(format!("{}: {}", XCURSOR_THEME, theme));
+    lines.push(format!("{}: {}", XCURSOR_SIZE, size));
+    lines.join("\n") + "\n"
+}
+
+/// Set keys in the GTK 3 and GTK 4 user settings, keeping the other keys.
+pub(crate) fn set_gtk_settings(values: &[(&str, String)]) -> std::io::Result<()> {
+    let config_dir = dirs_next::config_dir().ok_or_else(|| std::io::Error::new(std::io::ErrorKind::NotFound, "no config directory"))?;
+    for gtk_dir in GTK_SETTINGS_DIRS.iter() {
+        fs::create_dir_all(config_dir.join(gtk_dir))?;
+        let settings = config_dir.join(gtk_dir).join("settings.ini");
+        let content = read_content(&settings).unwrap_or_default();
+        write_content_overwrite(&settings, &set_ini_values(&content, GTK_SETTINGS_SECTION, values))?;
+    }
+    Ok(())
+}
+
+/// Set keys of one section of an INI file in place, appending the keys or the section if missing.
+/// Comments, ordering and other sections are kept.
+fn set_ini_values(content: &str, section: &str, values: &[(&str, String)]) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut written = HashSet::new();
+    let mut in_section = false;
+    let mut found_section = false;
+    let append_missing = |lines: &mut Vec<String>, written: &HashSet<&str>| {
+        values.iter().filter(|(key, _)| !written.contains(key)).for_each(|(key, val)| lines.push(format!("{}={}", key, val)));
+    };
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            if in_section {
+                append_missing(&mut lines, &written);
+            }
+            in_section = &trimmed[1..trimmed.len() - 1] == section;
+            found_section |= in_section;
+            lines.push(line.to_string());
+        } else if let Some((key, val)) = values.iter().find(|(key, _)| in_section && trimmed.split('=').next().map(str::trim) == Some(key)) {
+            written.insert(*key);
+            lines.push(format!("{}={}", key, val));
+        } else {
+            lines.push(line.to_string());
+        }
+    }
+
+    if in_section {
+        append_missing(&mut lines, &written);
+    } else if !found_section {
+        lines.push(format!("[{}]", section));
+        append_missing(&mut lines, &written);
+    }
+    lines.join("\n") + "\n"
+}
+
+/// Decode the first frame of the image closest to `size` from an Xcursor file.
+fn read_xcursor<P: AsRef<Path>>(path: P, size: u32) -> Result<CursorImage, Error> {
+    parse_xcursor(&fs::read(path)?, size).ok_or_else(|| Error::Parse(String::from("Xcursor file")))
+}
+
+fn parse_xcursor(data: &[u8], size: u32) -> Option<CursorImage> {
+    let le32 = |pos: usize| data.get(pos..pos + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    if le32(0)? != XCURSOR_MAGIC {
+        return None;
+    }
+    let header_len = le32(4)? as usize;
+    let ntoc = le32(12)? as usize;
+
+    // (nominal size, position) of image chunks; the first chunk of a size is its first frame
+    let mut best: Option<(u32, usize)> = None;
+    for idx in 0..ntoc {
+        let toc = header_len + 12 * idx;
+        if le32(toc)? != XCURSOR_IMAGE_TYPE {
+            continue;
+        }
+        let (nominal, pos) = (le32(toc + 4)?, le32(toc + 8)? as usize);
+        match best {
+            Some((best_size, _)) if best_size.abs_diff(size) <= nominal.abs_diff(size) => {}
+            _ => best = Some((nominal, pos)),
+        }
+    }
+
+    let (nominal_size, pos) = best?;
+    let (width, height) = (le32(pos + 16)?, le32(pos + 20)?);
+    let pixels_start = pos + le32(pos)? as usize;
+    let pixels = (0..(width as usize * height as usize)).map(|idx| le32(pixels_start + 4 * idx)).collect::<Option<Vec<u32>>>()?;
+    Some(CursorImage {
+        nominal_size,
+        width,
+        height,
+        xhot: le32(pos + 24)?,
+        yhot: le32(pos + 28)?,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_xcursor, set_ini_values, XCURSOR_IMAGE_TYPE, XCURSOR_MAGIC};
+
+    #[test]
+    fn test_set_ini_values() {
+        let content = "# gtk\n[Settings]\ngtk-theme-name=Koompi\ngtk-cursor-theme-name=Adwaita\n\n[Other]\nkey=val\n";
+        let values = [("gtk-cursor-theme-name", String::from("Breeze")), ("gtk-cursor-theme-size", String::from("32"))];
+        assert_eq!(set_ini_values(content, "Settings", &values), "# gtk\n[Settings]\ngtk-theme-name=Koompi\ngtk-cursor-theme-name=Breeze\n\ngtk-cursor-theme-size=32\n[Other]\nkey=val\n");
+        assert_eq!(set_ini_values("", "Settings", &values), "[Settings]\ngtk-cursor-theme-name=Breeze\ngtk-cursor-theme-size=32\n");
+    }
+
+    #[test]
+    fn test_parse_xcursor() {
+        // header, two TOC entries (sizes 24 and 48), then two 1x1 images
+        let mut data: Vec<u32> = vec![XCURSOR_MAGIC, 16, 0x1_0000, 2, XCURSOR_IMAGE_TYPE, 24, 40, XCURSOR_IMAGE_TYPE, 48, 80];
+        data.extend(vec![36, XCURSOR_IMAGE_TYPE, 24, 1, 1, 1, 0, 0, 0, 0xff00_00ff]);
+        data.extend(vec![36, XCURSOR_IMAGE_TYPE, 48, 1, 1, 1, 0, 0, 0, 0xffff_0000]);
+        let bytes: Vec<u8> = data.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect();
+
+        let image = parse_xcursor(&bytes, 40).unwrap();
+        assert_eq!((image.nominal_size, image.width, image.height), (48, 1, 1));
+        assert_eq!(image.pixels, vec![0xffff_0000]);
+        assert_eq!(parse_xcursor(&bytes, 16).unwrap().pixels, vec![0xff00_00ff]);
+        assert_eq!(parse_xcursor(&bytes[4..], 24), None);
+    }
+}
@@ -1,21 +1,24 @@
 mod users;
 mod groups;
 mod account_type;
+mod async_users_groups;
 
 pub use users::User;
 pub use groups::Group;
 pub use account_type::AccountType;
-use std::io::{Error, ErrorKind};
+pub use async_users_groups::AsyncUsersGroupsManager;
 use std::path::Path;
 use std::collections::HashSet;
-use crate::helpers::{get_list_by_sep, exec_cmd, read_lines, to_account_name};
+pub(crate) use users::check_account_name;
+use crate::error::Error;
+use crate::helpers::{get_list_by_sep, CommandLine, Runner, to_account_name, lockdown::{check_action, ACTION_CREATE_USER}};
 
 const PASSWD: &str = "passwd";
 const GREP: &str = "grep";
 const CHSH: &str = "chsh";
 const ID: &str = "id";
-const ADM_GROUP: &str = "wheel";
-const USERS_DB_PATH: &str = "/etc/passwd";
+const GETENT: &str = "getent";
+pub(crate) const ADM_GROUP: &str = "wheel";
 const GROUP_DB_PATH: &str = "/etc/group";
 /// Minimum UID/GID for user and group
 pub const MIN_UID: u16 = 1000;
@@ -31,13 +34,19 @@ pub struct UsersGroupsManager {
    ls_all_groups: Vec<Group>,
    ls_groups: Vec<Group>,
    login_shells: Vec<String>,
+   runner: Runner,
 }
 
 // Public API
 impl UsersGroupsManager {
    /// This method is used to initialize Users & Groups manager.
    pub fn new() -> Result<Self, Error> {
-      let mut ug_mn = Self::default();
+      Self::with_runner(Runner::default())
+   }
+
+   /// This method is used to initialize Users & Groups manager running account tools through the given runner.
+   pub fn with_runner(runner: Runner) -> Result<Self, Error> {
+      let mut ug_mn = Self { runner, ..Self::default() };
       ug_mn.load_curr_user()?;
       ug_mn.load_users()?;
       ug_mn.load_groups()?;
@@ -46,10 +55,13 @@ impl UsersGroupsManager {
    }
 
    /// This method is used to create a new user after check for username exists and then refresh users database.
+   /// Fails with `Error::Locked` if the administrator disabled creating users, and with `Error::Validation` for an invalid username or password.
    pub fn create_user<T: AsRef<str> + Clone>(&mut self, fullname: T, usrname: T, account_type: AccountType, pwd: T, verify_pwd: T) -> Result<Option<&User>, Error> {
+      check_action(ACTION_CREATE_USER)?;
       let usrname = to_account_name(usrname);
+      check_account_name(&usrname)?;
       if !self.ls_all_users.iter().any(|user| user.username().eq(&usrname)) {
-         User::new(fullname.as_ref(), usrname.as_str(), account_type, pwd.as_ref(), verify_pwd.as_ref())?;
+         User::new(&self.runner, fullname.as_ref(), usrname.as_str(), account_type, pwd.as_ref(), verify_pwd.as_ref())?;
          self.load_users()?;
          Ok(self.user_from_name(usrname))
       } else {
@@ -132,8 +144,9 @@ impl UsersGroupsManager {
    /// This method is used to reset user password by specified username and password.
    pub fn reset_user_password<T: AsRef<str>>(&mut self, usrname: T, pwd: T, verify_pwd: T) -> Result<Option<User>, Error> {
       let usrname = to_account_name(usrname);
+      let runner = self.runner.clone();
       if let Some(usr) = self.get_mut_user(&usrname) {
-         User::reset_password(usrname.as_str(), pwd.as_ref(), verify_pwd.as_ref())?;
+         User::reset_password(&runner, usrname.as_str(), pwd.as_ref(), verify_pwd.as_ref())?;
          Ok(Some(usr.clone()))
       } else {
          Ok(None)
@@ -165,8 +178,9 @@ impl UsersGroupsManager {
    /// This method is used to create a new group after check for group name exists and then refresh groups database.
    pub fn create_group<T: AsRef<str> + Clone>(&mut self, gname: T) -> Result<Option<&Group>, Error> {
       let gname = to_account_name(gname);
+      check_account_name(&gname)?;
       if !self.ls_all_groups.iter().any(|group| group.name().eq(&gname)) {
-         Group::new(&gname)?;
+         Group::new(&self.runner, &gname)?;
          self.load_groups()?;
          Ok(self.group_from_name(gname))
       } else {
@@ -178,6 +192,7 @@ impl UsersGroupsManager {
    pub fn change_group_name<T: AsRef<str>>(&mut self, gname: T, new_name: T) -> Result<Option<Group>, Error> {
       let gname = to_account_name(gname);
       let new_name = to_account_name(new_name);
+      check_account_name(&new_name)?;
       let ls_all_groups = self.ls_all_groups.clone();
       if let Some(group) = self.get_mut_group(&gname){
          if !ls_all_groups.iter().any(|grp| grp.name().eq(&new_name)) {
@@ -277,15 +292,11 @@ impl UsersGroupsManager {
 impl UsersGroupsManager {
    /// Refresh users database after any update.
    fn load_users(&mut self) -> Result<(), Error> {
-      let allusers = read_lines(USERS_DB_PATH)?;
-      let admin_members_stdout = exec_cmd(GREP, vec![&format!("{}:", ADM_GROUP), GROUP_DB_PATH])?;
+      let allusers = self.runner.exec(&CommandLine::new(GETENT, &["passwd"]))?;
+      let admin_members_stdout = self.runner.exec(&CommandLine::new(GREP, &[format!("{}:", ADM_GROUP).as_str(), GROUP_DB_PATH]))?;
       let admin_members = &get_list_by_sep(&admin_members_stdout, ":")[3];
       let ls_admin_usrnames = get_list_by_sep(&admin_members, ",");
-      self.ls_all_users = allusers.map(|line| if let Ok(line) = line {
-         Some(User::from_vec(get_list_by_sep(&line, ":").as_ref(), ls_admin_usrnames.iter().map(AsRef::as_ref).collect()))
-      } else {
-         None
-      }).filter_map(|usr| usr).collect();
+      self.ls_all_users = allusers.lines().map(|line| User::from_vec(get_list_by_sep(line, ":").as_ref(), ls_admin_usrnames.iter().map(AsRef::as_ref).collect(), self.runner.clone())).collect();
 
       let mut ls_users: Vec<User> = self.ls_all_users.iter().filter(|usr| MIN_UID < usr.uid() && usr.uid() < MAX_UID).map(ToOwned::to_owned).collect();
       if let Some(idx) = ls_users.iter().position(|usr| usr.uid().eq(&self.curr_uid)) {
@@ -299,12 +310,8 @@ impl UsersGroupsManager {
 
    /// Refresh groups database after any update.
    fn load_groups(&mut self) -> Result<(), Error> {
-      let allgroups = read_lines(GROUP_DB_PATH)?;
-      self.ls_all_groups = allgroups.map(|line| if let Ok(line) = line {
-         Some(Group::from_vec(get_list_by_sep(&line, ":").as_ref()))
-      } else {
-         None
-      }).filter_map(|grp| grp).collect();
+      let allgroups = self.runner.exec(&CommandLine::new(GETENT, &["group"]))?;
+      self.ls_all_groups = allgroups.lines().map(|line| Group::from_vec(get_list_by_sep(line, ":").as_ref(), self.runner.clone())).collect();
 
       let ls_users_gid: HashSet<u16> = self.list_users().iter().map(|usr| usr.gid()).collect();
       self.ls_groups = self.ls_all_groups.iter().filter(|grp| MIN_UID < grp.gid() && grp.gid() < MAX_UID).filter(|grp| !ls_users_gid.contains(&grp.gid())).map(ToOwned::to_owned).collect();
@@ -313,19 +320,19 @@ impl UsersGroupsManager {
 
    /// Load current running user account.
    fn load_curr_user(&mut self) -> Result<(), Error> {
-      let uid = exec_cmd(ID, vec!["-u"])?;
+      let uid = self.runner.exec(&CommandLine::new(ID, &["-u"]))?;
       match uid.parse::<u16>() {
          Ok(uid) => {
             self.curr_uid = uid;
             Ok(())
          },
-         Err(err) => Err(Error::new(ErrorKind::Other, err))
+         Err(err) => Err(Error::Parse(format!("uid {:?}: {}", uid, err)))
       }
    }
 
    /// Load all available login shells.
    fn load_login_shells(&mut self) -> Result<(), Error> {
-      let stdout = exec_cmd(CHSH, vec!["-l"])?;
+      let stdout = self.runner.exec(&CommandLine::new(CHSH, &["-l"]))?;
       self.login_shells = stdout.lines().map(ToString::to_string).collect();
       Ok(())
    }
@@ -349,76 +356,113 @@ impl UsersGroupsManager {
 
 #[cfg(test)]
 mod test {
-   use super::UsersGroupsManager;
-   use std::io::Error;
-   use crate::helpers::to_formatted_name;
+   use super::{AccountType, UsersGroupsManager};
+   use crate::error::Error;
+   use crate::helpers::{to_formatted_name, CommandOutput, FakeRunner, Runner};
+   use crate::test_support::TestBus;
+   use std::path::Path;
+
+   const PASSWD: &str = "root:x:0:0:root:/root:/bin/bash\nkoompi:x:1001:1001:Koompi:/home/koompi:/bin/bash\n";
+   const GROUP: &str = "root:x:0:\nusers:x:100:\nwheel:x:998:koompi\nkoompi:x:1001:\nfriends:x:1002:koompi\n";
+
+   /// Runner of a system with the user `koompi` logged in, answering `getent` with each database content in turn.
+   /// Operations fall back to pkexec, since the privileged helper isn't on the private bus.
+   fn fake_system(passwd: &[&str], group: &[&str]) -> FakeRunner {
+      let runner = FakeRunner::new()
+         .expect("id -u", CommandOutput::ok("1001\n"))
+         .expect("grep wheel: /etc/group", CommandOutput::ok("wheel:x:998:koompi\n"))
+         .expect("chsh -l", CommandOutput::ok("/bin/sh\n/bin/bash\n"))
+         .expect("groups koompi", CommandOutput::ok("koompi wheel friends\n"));
+      let runner = passwd.iter().fold(runner, |runner, content| runner.expect("getent passwd", CommandOutput::ok(*content)));
+      group.iter().fold(runner, |runner, content| runner.expect("getent group", CommandOutput::ok(*content)))
+   }
+
+   fn privileged_calls(runner: &FakeRunner) -> Vec<String> {
+      runner.calls().iter().filter(|call| call.program() == "pkexec").map(ToString::to_string).collect()
+   }
+
+   #[test]
+   fn test_fake_runner_manager() -> Result<(), Error> {
+      let runner = fake_system(&[PASSWD], &[GROUP]);
+      let mut usr_mn = UsersGroupsManager::with_runner(Runner::new(runner.clone()))?;
+      assert_eq!(usr_mn.current_uid(), 1001);
+      assert_eq!(usr_mn.login_shells(), ["/bin/sh", "/bin/bash"]);
+      assert!(matches!(usr_mn.create_user("Test", "libkoompi_test", AccountType::User, "1111", "2222"), Err(Error::Validation(_))));
+      assert!(matches!(usr_mn.create_group("9lives"), Err(Error::Validation(_))));
+      assert!(!runner.calls().iter().any(|call| call.program() == "pkexec"));
+      Ok(())
+   }
+
+   #[test]
+   fn test_async_manager() -> Result<(), Error> {
+      use super::AsyncUsersGroupsManager;
+      use crate::helpers::block_on;
+
+      let usr_mn = block_on(AsyncUsersGroupsManager::with_runner(Runner::new(fake_system(&[PASSWD], &[GROUP]))))?;
+      assert_eq!(block_on(usr_mn.run(|ug_mn| ug_mn.current_uid())), 1001);
+      let res = block_on(usr_mn.create_user("Test".into(), "libkoompi_test".into(), AccountType::User, "1111".into(), "2222".into()));
+      assert!(matches!(res, Err(Error::Validation(_))));
+      Ok(())
+   }
 
    #[test]
    fn test_users_manager() -> Result<(), Error> {
-      let usr_mn = UsersGroupsManager::new()?; 
-      println!("{:#?}", usr_mn.list_users());
-      if let Ok(uid) = std::env::var("UID") {
-         assert_eq!(usr_mn.current_uid(), uid.parse().unwrap());
-      }
-      assert_eq!(1, 0);
+      let usr_mn = UsersGroupsManager::with_runner(Runner::new(fake_system(&[PASSWD], &[GROUP])))?;
+      assert_eq!(usr_mn.all_users().len(), 2);
+      let usr = usr_mn.current_user().expect("no current user");
+      assert_eq!((usr.username().as_str(), usr.uid(), usr.account_type()), ("koompi", 1001, AccountType::Admin));
+      assert_eq!(usr.home_dir(), Path::new("/home/koompi"));
+      assert_eq!(usr.groups(), ["koompi", "wheel", "friends"]);
+      assert_eq!(usr_mn.list_groups().iter().map(|grp| grp.name().as_str()).collect::<Vec<&str>>(), ["friends"]);
+      assert_eq!(usr_mn.group_members("friends").map(|members| members.len()), Some(1));
       Ok(())
    }
 
    #[test]
    fn test_crud_group() -> Result<(), Error> {
-      const GRP_NAME: &'static str = "test";
-      const NEW_GRP_NAME: &'static str = "test_group";
-
-      let mut usr_mn = UsersGroupsManager::new()?;
-      if let Some(_) = usr_mn.create_group(GRP_NAME)? {
-         println!("--> created group {}", GRP_NAME);
-         if let Some(grp) = usr_mn.change_group_name(GRP_NAME, NEW_GRP_NAME)? {
-            println!("--> changed group name from {} to {}", GRP_NAME, NEW_GRP_NAME);
-            assert_eq!(grp.formatted_name(), to_formatted_name(NEW_GRP_NAME));
-            if usr_mn.delete_group(NEW_GRP_NAME)? {
-               println!("--> deleted group {}", NEW_GRP_NAME);
-               println!("--> listing groups: \n{:#?}", usr_mn.list_groups());
-               match usr_mn.group_from_name(NEW_GRP_NAME) {
-                  Some(_) => assert_eq!(true, false),
-                  None => assert_eq!(true, true),
-               }
-            }
-         } 
+      if TestBus::shared().is_none() {
+         return Ok(());
       }
+      let runner = fake_system(&[PASSWD], &[GROUP, &format!("{}test:x:1003:\n", GROUP), GROUP])
+         .expect("pkexec groupadd test", CommandOutput::ok(""))
+         .expect("pkexec groupmod -n test_group test", CommandOutput::ok(""))
+         .expect("pkexec groupdel test_group", CommandOutput::ok(""));
+      let mut usr_mn = UsersGroupsManager::with_runner(Runner::new(runner.clone()))?;
+
+      assert_eq!(usr_mn.create_group("test")?.map(|grp| grp.gid()), Some(1003));
+      let grp = usr_mn.change_group_name("test", "test_group")?.expect("group not renamed");
+      assert_eq!(grp.formatted_name(), to_formatted_name("test_group"));
+      assert!(usr_mn.delete_group("test_group")?);
+      assert!(usr_mn.group_from_name("test_group").is_none());
+      assert_eq!(privileged_calls(&runner), ["pkexec groupadd test", "pkexec groupmod -n test_group test", "pkexec groupdel test_group"]);
       Ok(())
    }
 
    #[test]
    fn test_crud_user() -> Result<(), Error> {
-      use super::AccountType;
-
-      const USR_NAME: &'static str = "test";
-      const NEW_USR_NAME: &'static str = "user_test";
-
-      let mut usr_mn = UsersGroupsManager::new()?;
-      if let Some(usr) = usr_mn.create_user("Test", USR_NAME, AccountType::default(), "1111", "1111")? {
-         println!("--> created user {}", USR_NAME);
-         assert_eq!(usr.home_dir(), &std::path::Path::new("/home").join(USR_NAME));
-         if let Some(usr) = usr_mn.change_user_type(USR_NAME, AccountType::Admin)? {
-            println!("--> changed type user {} to {}", USR_NAME, AccountType::Admin);
-            assert_eq!(usr.account_type(), AccountType::Admin);
-            if let Some(usr) = usr_mn.change_user_info(USR_NAME, "1005", "users", "User Test", NEW_USR_NAME, "/bin/zsh", "/home/user")? {
-               println!("--> changed user name from {} to {}", USR_NAME, NEW_USR_NAME);
-               assert_eq!(usr.username(), NEW_USR_NAME);
-               assert_eq!(usr.uid(), 1005);
-               assert_eq!(usr.fullname(), "User Test");
-               assert_eq!(usr.login_shell().to_str().unwrap(), "/bin/zsh");
-               assert_eq!(usr.home_dir().to_str().unwrap(), "/home/user");
-               if usr_mn.delete_user(USR_NAME, true)? {
-                  println!("--> deleted user {}", USR_NAME);
-                  match usr_mn.group_from_name(NEW_USR_NAME) {
-                     Some(_) => assert_eq!(true, false),
-                     None => assert_eq!(true, true),
-                  }
-               }
-            }
-         }
+      if TestBus::shared().is_none() {
+         return Ok(());
       }
+      let runner = fake_system(&[PASSWD, &format!("{}test:x:1002:100:Test:/home/test:/bin/sh\n", PASSWD), PASSWD], &[GROUP])
+         .expect("pkexec useradd -c Test -m -N test", CommandOutput::ok(""))
+         .expect("pkexec usermod -a -G input,cups test", CommandOutput::ok(""))
+         .expect("pkexec chpasswd", CommandOutput::ok(""))
+         .expect("pkexec gpasswd -a test wheel", CommandOutput::ok(""))
+         .expect("pkexec usermod -u 1005 -g 100 -c User Test -l user_test -s /bin/bash -m -d /home/user_test test", CommandOutput::ok(""))
+         // the home directory doesn't exist, so there is none to remove
+         .expect("pkexec userdel user_test", CommandOutput::ok(""));
+      let mut usr_mn = UsersGroupsManager::with_runner(Runner::new(runner.clone()))?;
+
+      let usr = usr_mn.create_user("Test", "test", AccountType::User, "1111", "1111")?.expect("user not created");
+      assert_eq!(usr.home_dir(), Path::new("/home/test"));
+      let usr = usr_mn.change_user_type("test", AccountType::Admin)?.expect("user not found");
+      assert_eq!(usr.account_type(), AccountType::Admin);
+      let usr = usr_mn.change_user_info("test", "1005", "users", "User Test", "user_test", "/bin/bash", "/home/user_test")?.expect("user not changed");
+      assert_eq!((usr.username().as_str(), usr.uid(), usr.gid(), usr.fullname().as_str()), ("user_test", 1005, 100, "User Test"));
+      assert_eq!((usr.login_shell().as_path(), usr.home_dir().as_path()), (Path::new("/bin/bash"), Path::new("/home/user_test")));
+      assert!(usr_mn.delete_user("user_test", true)?);
+      assert!(usr_mn.user_from_name("user_test").is_none());
+      assert_eq!(privileged_calls(&runner).len(), 6);
       Ok(())
    }
-}
\ No newline at end of file
+}
@@ -0,0 +1,80 @@
+use std::sync::{Arc, Mutex, PoisonError};
+use crate::error::Error;
+use crate::helpers::{unblock, Runner};
+use super::{AccountType, Group, User, UsersGroupsManager};
+
+/// Structure of the async Users & Groups Manager. Account tools and database reads run off the async executor,
+/// one call at a time, against a shared `UsersGroupsManager`.
+#[derive(Debug, Clone)]
+pub struct AsyncUsersGroupsManager {
+   inner: Arc<Mutex<UsersGroupsManager>>,
+}
+
+// Public API
+impl AsyncUsersGroupsManager {
+   /// This method is used to initialize Users & Groups manager.
+   pub async fn new() -> Result<Self, Error> {
+      Self::with_runner(Runner::default()).await
+   }
+
+   /// This method is used to initialize Users & Groups manager running account tools through the given runner.
+   pub async fn with_runner(runner: Runner) -> Result<Self, Error> {
+      let ug_mn = unblock(move || UsersGroupsManager::with_runner(runner)).await?;
+      Ok(Self { inner: Arc::new(Mutex::new(ug_mn)) })
+   }
+
+   /// This method is used to run any method of the blocking manager off the async executor.
+   pub async fn run<F, R>(&self, f: F) -> R
+   where
+      F: FnOnce(&mut UsersGroupsManager) -> R + Send + 'static,
+      R: Send + 'static,
+   {
+      let inner = self.inner.clone();
+      unblock(move || f(&mut inner.lock().unwrap_or_else(PoisonError::into_inner))).await
+   }
+
+   /// This method is used to create a new user and return it.
+   pub async fn create_user(&self, fullname: String, usrname: String, account_type: AccountType, pwd: String, verify_pwd: String) -> Result<Option<User>, Error> {
+      self.run(move |ug_mn| ug_mn.create_user(fullname, usrname, account_type, pwd, verify_pwd).map(|user| user.cloned())).await
+   }
+
+   /// This method is used to change the account type of a user.
+   pub async fn change_user_type(&self, usrname: String, account_type: AccountType) -> Result<Option<User>, Error> {
+      self.run(move |ug_mn| ug_mn.change_user_type(usrname, account_type)).await
+   }
+
+   /// This method is used to reset the password of a user.
+   pub async fn reset_user_password(&self, usrname: String, pwd: String, verify_pwd: String) -> Result<Option<User>, Error> {
+      self.run(move |ug_mn| ug_mn.reset_user_password(usrname, pwd, verify_pwd)).await
+   }
+
+   /// This method is used to delete a user.
+   pub async fn delete_user(&self, usrname: String, delete_home_dir: bool) -> Result<bool, Error> {
+      self.run(move |ug_mn| ug_mn.delete_user(usrname, delete_home_dir)).await
+   }
+
+   /// This method is used to create a new group and return it.
+   pub async fn create_group(&self, gname: String) -> Result<Option<Group>, Error> {
+      self.run(move |ug_mn| ug_mn.create_group(gname).map(|group| group.cloned())).await
+   }
+
+   /// This method is used to delete a group.
+   pub async fn delete_group(&self, gname: String) -> Result<bool, Error> {
+      self.run(move |ug_mn| ug_mn.delete_group(gname)).await
+   }
+
+   /// This method is used to return the current user.
+   pub async fn current_user(&self) -> Option<User> {
+      self.run(|ug_mn| ug_mn.current_user().cloned()).await
+   }
+
+   /// This method is used to return the normal users.
+   pub async fn list_users(&self) -> Vec<User> {
+      self.run(|ug_mn| ug_mn.list_users().to_vec()).await
+   }
+
+   /// This method is used to return the normal groups.
+   pub async fn list_groups(&self) -> Vec<Group> {
+      self.run(|ug_mn| ug_mn.list_groups().to_vec()).await
+   }
+}
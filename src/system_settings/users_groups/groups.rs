@@ -1,30 +1,26 @@
 use std::fmt::Display;
-use std::io::Error;
-use crate::helpers::{exec_cmd, get_list_by_sep, constants::PKEXEC, to_account_name, to_formatted_name};
-
-pub(super) const GPASSWD: &str = "gpasswd";
-const GROUP_ADD: &str = "groupadd";
-const GROUP_MOD: &str = "groupmod";
-const GROUP_DEL: &str = "groupdel";
+use crate::error::Error;
+use crate::helpers::{get_list_by_sep, Runner, to_account_name, to_formatted_name};
+use super::super::privileged::{Operation, PrivilegedClient};
 
 /// Structure of Group Account
 #[derive(Debug, Clone, Default)]
 pub struct Group {
    gid: u16,
    gname: String,
-   members: Vec<String>
+   members: Vec<String>,
+   runner: Runner,
 }
 
 // Public API
 impl Group {
    /// This method is used to create a new group with group name.
-   pub(super) fn new<T: AsRef<str>>(gname: T) -> Result<(), Error> {
-      exec_cmd(PKEXEC, vec![GROUP_ADD, gname.as_ref()])?;
-      Ok(())
+   pub(super) fn new<T: AsRef<str>>(runner: &Runner, gname: T) -> Result<(), Error> {
+      PrivilegedClient::new(runner.clone()).call(&Operation::CreateGroup { name: gname.as_ref().to_string() })
    }
 
    /// This method is used to create a group entry object from a list of fields.
-   pub(super) fn from_vec<T: AsRef<str> + Display>(fields: &[T]) -> Self {
+   pub(super) fn from_vec<T: AsRef<str> + Display>(fields: &[T], runner: Runner) -> Self {
       let mut iter = fields.iter();
       let gname = iter.next().unwrap();
       iter.next().unwrap();
@@ -34,15 +30,15 @@ impl Group {
       Self {
          gid, 
          gname: gname.to_string(), 
-         members
+         members,
+         runner,
       }
    }
 
    /// This method is used to set/change list of members of the group instance.
    pub(super) fn change_membership(&mut self, ls_members: Vec<&str>) -> Result<(), Error> {
       if ls_members != self.members.iter().map(AsRef::as_ref).collect::<Vec<&str>>() {
-         let formatted_members = ls_members.join(",");
-         exec_cmd(PKEXEC, vec![GPASSWD, "-M", &formatted_members, &self.gname])?;
+         self.privileged().call(&Operation::SetGroupMembers { name: self.gname.clone(), members: ls_members.iter().map(ToString::to_string).collect() })?;
          self.members = ls_members.iter().map(ToString::to_string).collect();
       }
       Ok(())
@@ -53,7 +49,7 @@ impl Group {
       let mut res = false;
       let usrname = to_account_name(usrname);
       if !self.members.contains(&usrname) {
-         exec_cmd(PKEXEC, vec![GPASSWD, "-a", usrname.as_ref(), &self.gname])?;
+         self.privileged().call(&Operation::AddGroupMember { name: self.gname.clone(), username: usrname.clone() })?;
          self.members.push(usrname);
          res = true;
       }
@@ -65,7 +61,7 @@ impl Group {
       let mut res = false;
       let usrname = to_account_name(usrname);
       if self.members.contains(&usrname) {
-         exec_cmd(PKEXEC, vec![GPASSWD, "-d", usrname.as_ref(), &self.gname])?;
+         self.privileged().call(&Operation::RemoveGroupMember { name: self.gname.clone(), username: usrname.clone() })?;
          let idx = self.members.iter().position(|m| m.eq(&usrname) );
          if let Some(idx) = idx {
             self.members.remove(idx);
@@ -80,8 +76,7 @@ impl Group {
       let mut all_members: Vec<&str> = self.members.iter().map(AsRef::as_ref).chain(ls_members).collect();
       all_members.sort();
       all_members.dedup();
-      let formatted_members = all_members.join(",");
-      exec_cmd(PKEXEC, vec![GPASSWD, "-M", &formatted_members, &self.gname])?;
+      self.privileged().call(&Operation::SetGroupMembers { name: self.gname.clone(), members: all_members.iter().map(ToString::to_string).collect() })?;
       self.members = all_members.iter().map(ToString::to_string).collect();
       Ok(())
    }
@@ -91,7 +86,7 @@ impl Group {
       let mut res = false;
       let name = new_gname.as_ref();
       if name != self.gname {
-         exec_cmd(PKEXEC, vec![GROUP_MOD, "-n", name, self.gname.as_str()])?;
+         self.privileged().call(&Operation::RenameGroup { name: self.gname.clone(), new_name: name.to_string() })?;
          self.gname = name.to_string();
          res = true;
       } 
@@ -100,8 +95,7 @@ impl Group {
 
    /// This method is used to delete the group from database.
    pub(super) fn delete(&mut self) -> Result<(), Error> {
-      exec_cmd(PKEXEC, vec![GROUP_DEL, &self.gname])?;
-      Ok(())
+      self.privileged().call(&Operation::DeleteGroup { name: self.gname.clone() })
    }
 
    /// This method is return group GID.
@@ -122,4 +116,8 @@ impl Group {
    pub fn members(&self) -> &[String] {
       self.members.as_slice()
    }
+
+   fn privileged(&self) -> PrivilegedClient {
+      PrivilegedClient::new(self.runner.clone())
+   }
 }
\ No newline at end of file
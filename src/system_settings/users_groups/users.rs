@@ -1,14 +1,12 @@
+use std::collections::HashMap;
 use std::fmt::Display;
-use std::io::Error;
 use std::path::{Path, PathBuf};
-use super::groups::GPASSWD;
 use super::account_type::AccountType;
-use super::super::users_groups::{ADM_GROUP, PASSWD};
-use crate::helpers::{exec_cmd, exec_spawn_cmd, get_list_by_sep, constants::PKEXEC, to_formatted_name};
+use super::super::users_groups::PASSWD;
+use super::super::privileged::{Operation, PrivilegedClient};
+use crate::error::Error;
+use crate::helpers::{get_list_by_sep, CommandLine, Runner, to_formatted_name};
 
-const USER_ADD: &str = "useradd";
-const USER_MOD: &str = "usermod";
-const USER_DEL: &str = "userdel";
 const GROUPS: &str = "groups";
 const PROFILE_ROOT_PATH: &str = "/var/lib/AccountsService/icons";
 
@@ -23,27 +21,24 @@ pub struct User {
    login_shell: PathBuf,
    home_dir: PathBuf,
    groups: Vec<String>,
+   runner: Runner,
 }
 
 // Public API
 impl User {
    /// This method is used to create a new user without creating personal group and add to ADMIN group if account type is admin.
-   pub(super) fn new<T: AsRef<str>>(fullname: T, usrname: T, account_type: AccountType, pwd: T, verify_pwd: T) -> Result<(), Error> {
-      let args = vec![USER_ADD, "-c", fullname.as_ref(), "-m", "-N", usrname.as_ref()];
-      exec_cmd(PKEXEC, args)?;
-      let mut gpasswd_args = vec![USER_MOD, "-a", "-G"];
-      let mut groups = vec!["input", "cups"];
-      if account_type == AccountType::Admin {
-         groups.push(ADM_GROUP);
-      }
-      let concat_groups = groups.join(",");
-      gpasswd_args.extend(vec![concat_groups.as_str(), usrname.as_ref()]);
-      exec_cmd(PKEXEC, gpasswd_args)?;
-      User::reset_password(usrname, pwd, verify_pwd)
+   pub(super) fn new<T: AsRef<str>>(runner: &Runner, fullname: T, usrname: T, account_type: AccountType, pwd: T, verify_pwd: T) -> Result<(), Error> {
+      check_password(pwd.as_ref(), verify_pwd.as_ref())?;
+      PrivilegedClient::new(runner.clone()).call(&Operation::CreateUser {
+         fullname: fullname.as_ref().to_string(),
+         username: usrname.as_ref().to_string(),
+         admin: account_type == AccountType::Admin,
+         password: pwd.as_ref().to_string(),
+      })
    }
 
    /// This method is used to create a user entry object from a list of fields and list of admin usernames.
-   pub(super) fn from_vec<T: AsRef<str> + Display>(fields: &[T], ls_admin: Vec<&str>) -> Self {
+   pub(super) fn from_vec<T: AsRef<str> + Display>(fields: &[T], ls_admin: Vec<&str>, runner: Runner) -> Self {
       let mut iter = fields.iter();
       let usrname = iter.next().unwrap().to_string();
       iter.next().unwrap();
@@ -52,7 +47,7 @@ impl User {
       let fullname = iter.next().unwrap();
       let home_dir = iter.next().unwrap();
       let login_shell = iter.next().unwrap();
-      let groups = match exec_cmd(GROUPS, vec![usrname.as_ref()]) {
+      let groups = match runner.exec(&CommandLine::new(GROUPS, &[usrname.as_str()])) {
          Ok(output) => get_list_by_sep(&output, " "),
          Err(_) => Vec::new()
       };
@@ -64,70 +59,65 @@ impl User {
          login_shell: PathBuf::from(&login_shell.to_string()), 
          home_dir: PathBuf::from(&home_dir.to_string()), 
          acc_type: if ls_admin.contains(&usrname.as_str()) {AccountType::Admin} else {AccountType::User},
+         runner,
       }
    }
 
    /// This method is used to toggle account type of the user and return a message. 
    pub(super) fn change_account_type(&mut self, account_type: AccountType) -> Result<(), Error> {
-      let opt = match account_type {
-         AccountType::User => "-d",
-         AccountType::Admin => "-a",
-      };
-      exec_cmd(PKEXEC, vec![GPASSWD, opt, self.username(), ADM_GROUP])?;
+      self.privileged().call(&Operation::SetAccountType { username: self.usrname.clone(), admin: account_type == AccountType::Admin })?;
       self.acc_type = account_type;
       Ok(())
    }
 
    /// This method is used to change user account information except account typpe and password.
    pub(super) fn change_info<T: AsRef<str>, P: AsRef<Path>>(&mut self, uid: Option<T>, gid: Option<T>, fullname: T, login_name: Option<T>, login_shell: Option<P>, home_dir: Option<P>) -> Result<bool, Error> {
-      let mut args = Vec::new();
+      let mut changes = HashMap::new();
       let usrname = self.usrname.clone();
       if let Some(uid) = &uid {
          if let Ok(uid_u16) = uid.as_ref().to_string().parse() { 
-            args.extend(vec!["-u", uid.as_ref()]);
+            changes.insert(String::from("uid"), uid.as_ref().to_string());
             self.uid = uid_u16;
          } 
       } if let Some(gid) = &gid {
-         args.extend(vec!["-g", gid.as_ref()]);
+         changes.insert(String::from("gid"), gid.as_ref().to_string());
          if let Ok(gid) = gid.as_ref().to_string().parse() {
             self.gid = gid;
          }
       } if fullname.as_ref().ne(self.fullname().as_str()) {
-         args.extend(vec!["-c", fullname.as_ref()]);
+         changes.insert(String::from("fullname"), fullname.as_ref().to_string());
          self.fullname = fullname.as_ref().to_string();
       } if let Some(login_name) = &login_name {
-         args.extend(vec!["-l", login_name.as_ref()]);
+         changes.insert(String::from("login"), login_name.as_ref().to_string());
          self.usrname = login_name.as_ref().to_string();
       } if let Some(login_shell) = &login_shell {
          if login_shell.as_ref().ne(&self.login_shell) {
-            args.extend(vec!["-s", login_shell.as_ref().to_str().unwrap()]);
+            changes.insert(String::from("shell"), login_shell.as_ref().to_string_lossy().into_owned());
             self.login_shell = login_shell.as_ref().into();
          } 
       } if let Some(home_dir) = &home_dir {
-         args.extend(vec!["-m", "-d", home_dir.as_ref().to_str().unwrap()]);
+         changes.insert(String::from("home"), home_dir.as_ref().to_string_lossy().into_owned());
          self.home_dir = home_dir.as_ref().into();
       }
 
-      if args.is_empty() {
+      if changes.is_empty() {
          Ok(false)
       } else {
-         args.insert(0, USER_MOD);
-         args.push(usrname.as_str());
-         exec_cmd(PKEXEC, args)?;
+         self.privileged().call(&Operation::ModifyUser { username: usrname, changes })?;
          Ok(true)
       }
    }
 
    /// This method is used to change password for the user account.
    pub(super) fn change_password<T: AsRef<str>>(&mut self, curr_pwd: T, pwd: T, verify_pwd: T) -> Result<(), Error> {
-      exec_spawn_cmd(PASSWD, Vec::new(), Some(&vec![curr_pwd.as_ref(), pwd.as_ref(), verify_pwd.as_ref()].join("\n")))?;
+      check_password(pwd.as_ref(), verify_pwd.as_ref())?;
+      self.runner.exec(&CommandLine::new(PASSWD, &[] as &[&str]).stdin([curr_pwd.as_ref(), pwd.as_ref(), verify_pwd.as_ref()].join("\n")))?;
       Ok(())
    }
 
    /// This method is used to change secondary groups.
    pub(super) fn change_groups(&mut self, ls_grps: Vec<&str>) -> Result<(), Error> {
-      let grps_str = ls_grps.join(",");
-      exec_cmd(PKEXEC, vec![USER_MOD, "-a", "-G", &grps_str, &self.usrname])?;
+      self.privileged().call(&Operation::SetUserGroups { username: self.usrname.clone(), groups: ls_grps.iter().map(ToString::to_string).collect() })?;
       self.groups = ls_grps.into_iter().map(ToOwned::to_owned).collect();
       Ok(())
    }
@@ -138,22 +128,15 @@ impl User {
    }
 
    /// This method is used to reset other users account's password.
-   pub(super) fn reset_password<T: AsRef<str>>(usrname: T, pwd: T, verify_pwd: T) -> Result<(), Error> {
-      exec_spawn_cmd(PKEXEC, vec![PASSWD, usrname.as_ref()], Some(&vec![pwd.as_ref(), verify_pwd.as_ref()].join("\n")))?;
-      Ok(())
+   pub(super) fn reset_password<T: AsRef<str>>(runner: &Runner, usrname: T, pwd: T, verify_pwd: T) -> Result<(), Error> {
+      check_password(pwd.as_ref(), verify_pwd.as_ref())?;
+      PrivilegedClient::new(runner.clone()).call(&Operation::SetPassword { username: usrname.as_ref().to_string(), password: pwd.as_ref().to_string() })
    }
 
    /// This method is used to delete this user account from database.
    pub(super) fn delete(&mut self, delete_home_dir: bool) -> Result<(), Error> {
-      let mut args = vec![USER_DEL];
-      if delete_home_dir {
-         if std::path::PathBuf::from(&self.home_dir).exists() {
-            args.push("-r");
-         }
-      }
-      args.push(&self.usrname);
-      exec_cmd(PKEXEC, args)?;
-      Ok(())
+      let remove_home = delete_home_dir && self.home_dir.exists();
+      self.privileged().call(&Operation::DeleteUser { username: self.usrname.clone(), remove_home })
    }
 
    /// This method is return UID.
@@ -205,4 +188,30 @@ impl User {
    pub fn profile_path(&self) -> PathBuf {
       PathBuf::from(PROFILE_ROOT_PATH).join(&self.usrname)
    }
-}
\ No newline at end of file
+
+   fn privileged(&self) -> PrivilegedClient {
+      PrivilegedClient::new(self.runner.clone())
+   }
+}
+
+/// Check that a new password isn't empty and was typed the same twice.
+fn check_password(pwd: &str, verify_pwd: &str) -> Result<(), Error> {
+   if pwd.is_empty() {
+      Err(Error::Validation(String::from("password is empty")))
+   } else if pwd != verify_pwd {
+      Err(Error::Validation(String::from("passwords do not match")))
+   } else {
+      Ok(())
+   }
+}
+
+/// Check that an account name is accepted by useradd and groupadd.
+pub(crate) fn check_account_name(name: &str) -> Result<(), Error> {
+   let mut chars = name.chars();
+   let valid_first = chars.next().is_some_and(|c| c.is_ascii_lowercase() || c == '_');
+   if valid_first && name.len() <= 32 && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-') {
+      Ok(())
+   } else {
+      Err(Error::Validation(format!("invalid account name: {}", name)))
+   }
+}
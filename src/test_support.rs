@@ -0,0 +1,13 @@
+//! Support for testing the managers against a private bus instead of the real system services.
+//!
+//! `TestBus::shared()` starts a `dbus-daemon` for the test process and makes it the system bus,
+//! then `MockService` stands in for the services the managers talk to.
+//! `SysfsFixture` does the same for the device layer with a fake sysfs tree.
+mod mock_service;
+mod mocks;
+mod sysfs_fixture;
+mod test_bus;
+
+pub use mock_service::{MockError, MockService, MockValue};
+pub use sysfs_fixture::SysfsFixture;
+pub use test_bus::TestBus;
@@ -0,0 +1,394 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ffi::CString;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use dbus::arg::{ArgType, OwnedFd, PropMap, RefArg, Variant};
+use dbus::blocking::stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged;
+use dbus::blocking::SyncConnection;
+use dbus::channel::{MatchingReceiver, Sender};
+use dbus::message::{MatchRule, SignalArgs};
+use dbus::strings::{ErrorName, Path};
+use dbus::Message;
+use crate::error::Error;
+use super::TestBus;
+
+const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+const OBJECT_MANAGER_INTERFACE: &str = "org.freedesktop.DBus.ObjectManager";
+const INTROSPECTABLE_INTERFACE: &str = "org.freedesktop.DBus.Introspectable";
+const UNKNOWN_METHOD: &str = "org.freedesktop.DBus.Error.UnknownMethod";
+const UNKNOWN_PROPERTY: &str = "org.freedesktop.DBus.Error.UnknownProperty";
+const INVALID_ARGS: &str = "org.freedesktop.DBus.Error.InvalidArgs";
+const PROCESS_INTERVAL: Duration = Duration::from_millis(20);
+
+lazy_static::lazy_static! {
+   /// Names owned by a live mock. Tests mocking the same service run one after another.
+   static ref NAMES_IN_USE: (Mutex<HashSet<String>>, Condvar) = (Mutex::new(HashSet::new()), Condvar::new());
+}
+
+/// Value of a mocked property or argument
+#[derive(Debug, Clone, PartialEq)]
+pub enum MockValue {
+   Bool(bool),
+   Byte(u8),
+   I16(i16),
+   U16(u16),
+   I32(i32),
+   U32(u32),
+   I64(i64),
+   U64(u64),
+   F64(f64),
+   Str(String),
+   Path(String),
+   Bytes(Vec<u8>),
+   Strs(Vec<String>),
+   Paths(Vec<String>),
+   /// A file descriptor, duplicated into each message it's sent in
+   Fd(OwnedFd),
+}
+
+impl MockValue {
+   /// An object path value.
+   pub fn path<T: Into<String>>(path: T) -> Self {
+      MockValue::Path(path.into())
+   }
+
+   /// An array of object paths.
+   pub fn paths<T: Into<String>>(paths: Vec<T>) -> Self {
+      MockValue::Paths(paths.into_iter().map(Into::into).collect())
+   }
+
+   fn to_refarg(&self) -> Box<dyn RefArg> {
+      match self {
+         MockValue::Bool(val) => Box::new(*val),
+         MockValue::Byte(val) => Box::new(*val),
+         MockValue::I16(val) => Box::new(*val),
+         MockValue::U16(val) => Box::new(*val),
+         MockValue::I32(val) => Box::new(*val),
+         MockValue::U32(val) => Box::new(*val),
+         MockValue::I64(val) => Box::new(*val),
+         MockValue::U64(val) => Box::new(*val),
+         MockValue::F64(val) => Box::new(*val),
+         MockValue::Str(val) => Box::new(val.clone()),
+         MockValue::Path(val) => Box::new(Path::from(val.clone())),
+         MockValue::Bytes(val) => Box::new(val.clone()),
+         MockValue::Strs(val) => Box::new(val.clone()),
+         MockValue::Paths(val) => Box::new(val.iter().map(|path| Path::from(path.clone())).collect::<Vec<Path<'static>>>()),
+         MockValue::Fd(val) => Box::new(val.clone()),
+      }
+   }
+
+   fn from_refarg(arg: &dyn RefArg) -> Option<Self> {
+      Some(match arg.arg_type() {
+         ArgType::Boolean => MockValue::Bool(arg.as_u64()? != 0),
+         ArgType::Byte => MockValue::Byte(arg.as_u64()? as u8),
+         ArgType::Int16 => MockValue::I16(arg.as_i64()? as i16),
+         ArgType::UInt16 => MockValue::U16(arg.as_u64()? as u16),
+         ArgType::Int32 => MockValue::I32(arg.as_i64()? as i32),
+         ArgType::UInt32 => MockValue::U32(arg.as_u64()? as u32),
+         ArgType::Int64 => MockValue::I64(arg.as_i64()?),
+         ArgType::UInt64 => MockValue::U64(arg.as_u64()?),
+         ArgType::Double => MockValue::F64(arg.as_f64()?),
+         ArgType::String => MockValue::Str(arg.as_str()?.to_string()),
+         ArgType::ObjectPath => MockValue::Path(arg.as_str()?.to_string()),
+         ArgType::Variant => return MockValue::from_refarg(arg.as_iter()?.next()?),
+         ArgType::Array => match &*arg.signature() {
+            "ay" => MockValue::Bytes(arg.as_iter()?.map(|item| item.as_u64().map(|byte| byte as u8)).collect::<Option<_>>()?),
+            "as" => MockValue::Strs(arg.as_iter()?.map(|item| item.as_str().map(ToString::to_string)).collect::<Option<_>>()?),
+            "ao" => MockValue::Paths(arg.as_iter()?.map(|item| item.as_str().map(ToString::to_string)).collect::<Option<_>>()?),
+            _ => return None,
+         },
+         _ => return None,
+      })
+   }
+}
+
+macro_rules! mock_value_from {
+   ($($ty:ty => $variant:ident),*) => {
+      $(impl From<$ty> for MockValue {
+         fn from(val: $ty) -> Self {
+            MockValue::$variant(val.into())
+         }
+      })*
+   };
+}
+
+mock_value_from!(bool => Bool, u8 => Byte, i16 => I16, u16 => U16, i32 => I32, u32 => U32, i64 => I64, u64 => U64, f64 => F64, &str => Str, String => Str, Vec<u8> => Bytes, Vec<String> => Strs);
+
+/// Error a mocked method replies with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockError {
+   pub name: String,
+   pub message: String,
+}
+
+impl MockError {
+   pub fn new<N: Into<String>, M: Into<String>>(name: N, message: M) -> Self {
+      Self { name: name.into(), message: message.into() }
+   }
+}
+
+type Handler = Box<dyn Fn(&Message) -> Result<Vec<MockValue>, MockError> + Send>;
+
+#[derive(Default)]
+struct MockState {
+   /// Properties by object path, interface and name
+   objects: BTreeMap<String, BTreeMap<String, BTreeMap<String, MockValue>>>,
+   handlers: HashMap<(String, String), Handler>,
+   calls: Vec<Message>,
+}
+
+/// Scriptable service owning a name on the private test bus. Properties, `GetManagedObjects` and `Introspect`
+/// are served from the mocked objects; other methods reply as scripted and are recorded.
+pub struct MockService {
+   name: String,
+   conn: Arc<SyncConnection>,
+   state: Arc<Mutex<MockState>>,
+   stop: Arc<AtomicBool>,
+   thread: Option<JoinHandle<()>>,
+}
+
+impl std::fmt::Debug for MockService {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      f.debug_struct("MockService").field("name", &self.name).finish()
+   }
+}
+
+// Public API
+impl MockService {
+   /// Own `name` on the private bus, waiting for another test's mock of the same service to be dropped.
+   /// Fails with `Error::MissingTool` if the private bus can't be started.
+   pub fn new(name: &str) -> Result<Self, Error> {
+      let bus = TestBus::shared().ok_or_else(|| Error::MissingTool(String::from("dbus-daemon")))?;
+      let conn = Arc::new(bus.connect()?);
+      acquire_name(name);
+      if let Err(err) = conn.request_name(name, false, true, true) {
+         release_name(name);
+         return Err(err.into());
+      }
+
+      let state = Arc::new(Mutex::new(MockState::default()));
+      let cb_state = state.clone();
+      conn.start_receive(MatchRule::new_method_call(), Box::new(move |msg, conn| {
+         let reply = handle_call(&cb_state, &msg, conn);
+         if !msg.get_no_reply() {
+            let _ = conn.send(reply);
+         }
+         true
+      }));
+
+      let stop = Arc::new(AtomicBool::new(false));
+      let (thread_conn, thread_stop) = (conn.clone(), stop.clone());
+      let thread = thread::spawn(move || {
+         while !thread_stop.load(Ordering::SeqCst) {
+            if thread_conn.process(PROCESS_INTERVAL).is_err() {
+               break;
+            }
+         }
+      });
+      Ok(Self { name: name.to_string(), conn, state, stop, thread: Some(thread) })
+   }
+
+   pub fn name(&self) -> &str {
+      &self.name
+   }
+
+   /// Add an object implementing `interface` with the given properties.
+   pub fn add_object(&self, path: &str, interface: &str, props: Vec<(&str, MockValue)>) -> &Self {
+      let mut state = self.state.lock().unwrap();
+      let iface_props = state.objects.entry(path.to_string()).or_default().entry(interface.to_string()).or_default();
+      iface_props.extend(props.into_iter().map(|(name, val)| (name.to_string(), val)));
+      self
+   }
+
+   /// Change a property, emitting `PropertiesChanged` like the real service.
+   pub fn set_property<V: Into<MockValue>>(&self, path: &str, interface: &str, name: &str, value: V) {
+      let value = value.into();
+      self.add_object(path, interface, vec![(name, value.clone())]);
+      let _ = self.conn.send(properties_changed(path, interface, name, &value));
+   }
+
+   /// Return the current value of a property, e.g. after the code under test set it.
+   pub fn property(&self, path: &str, interface: &str, name: &str) -> Option<MockValue> {
+      self.state.lock().unwrap().objects.get(path)?.get(interface)?.get(name).cloned()
+   }
+
+   /// Handle calls of `method` with a function of the call message.
+   pub fn on_call<F>(&self, interface: &str, method: &str, handler: F) -> &Self
+   where
+      F: Fn(&Message) -> Result<Vec<MockValue>, MockError> + Send + 'static,
+   {
+      self.state.lock().unwrap().handlers.insert((interface.to_string(), method.to_string()), Box::new(handler));
+      self
+   }
+
+   /// Reply to every call of `method` with `values`.
+   pub fn reply(&self, interface: &str, method: &str, values: Vec<MockValue>) -> &Self {
+      self.on_call(interface, method, move |_| Ok(values.clone()))
+   }
+
+   /// Fail every call of `method` with the error `name`.
+   pub fn fail(&self, interface: &str, method: &str, name: &str, message: &str) -> &Self {
+      let err = MockError::new(name, message);
+      self.on_call(interface, method, move |_| Err(err.clone()))
+   }
+
+   /// Emit a signal from `path`.
+   pub fn emit(&self, path: &str, interface: &str, member: &str, args: Vec<MockValue>) {
+      let mut signal = Message::new_signal(path, interface, member).unwrap();
+      {
+         let mut iter = dbus::arg::IterAppend::new(&mut signal);
+         args.iter().for_each(|arg| arg.to_refarg().append(&mut iter));
+      }
+      let _ = self.conn.send(signal);
+   }
+
+   /// Return the scripted method calls received so far, in order.
+   pub fn calls(&self) -> Vec<Message> {
+      self.state.lock().unwrap().calls.iter().filter_map(|msg| msg.duplicate().ok()).collect()
+   }
+
+   /// Return the calls received so far of one method.
+   pub fn calls_to(&self, method: &str) -> Vec<Message> {
+      self.calls().into_iter().filter(|msg| msg.member().as_deref() == Some(method)).collect()
+   }
+}
+
+impl Drop for MockService {
+   fn drop(&mut self) {
+      self.stop.store(true, Ordering::SeqCst);
+      if let Some(thread) = self.thread.take() {
+         let _ = thread.join();
+      }
+      let _ = self.conn.release_name(self.name.as_str());
+      release_name(&self.name);
+   }
+}
+
+fn acquire_name(name: &str) {
+   let (lock, cvar) = &*NAMES_IN_USE;
+   let mut names = lock.lock().unwrap();
+   while names.contains(name) {
+      names = cvar.wait(names).unwrap();
+   }
+   names.insert(name.to_string());
+}
+
+fn release_name(name: &str) {
+   let (lock, cvar) = &*NAMES_IN_USE;
+   lock.lock().unwrap().remove(name);
+   cvar.notify_all();
+}
+
+fn properties_changed(path: &str, interface: &str, name: &str, value: &MockValue) -> Message {
+   let mut changed = PropMap::new();
+   changed.insert(name.to_string(), Variant(value.to_refarg()));
+   let signal = PropertiesPropertiesChanged { interface_name: interface.to_string(), changed_properties: changed, invalidated_properties: Vec::new() };
+   signal.to_emit_message(&Path::from(path.to_string()))
+}
+
+fn error_reply(msg: &Message, name: &str, message: &str) -> Message {
+   msg.error(&ErrorName::from(name.to_string()), &CString::new(message.replace('\0', "")).unwrap())
+}
+
+fn to_prop_map(props: &BTreeMap<String, MockValue>) -> PropMap {
+   props.iter().map(|(name, val)| (name.clone(), Variant(val.to_refarg()))).collect()
+}
+
+fn handle_call(state: &Mutex<MockState>, msg: &Message, conn: &SyncConnection) -> Message {
+   let mut state = state.lock().unwrap();
+   let path = msg.path().map(|path| path.to_string()).unwrap_or_default();
+   let interface = msg.interface().map(|iface| iface.to_string()).unwrap_or_default();
+   let member = msg.member().map(|member| member.to_string()).unwrap_or_default();
+
+   match (interface.as_str(), member.as_str()) {
+      (PROPERTIES_INTERFACE, "Get") => match msg.read2::<&str, &str>() {
+         Ok((iface, name)) => match state.objects.get(&path).and_then(|obj| obj.get(iface)).and_then(|props| props.get(name)) {
+            Some(val) => msg.method_return().append1(Variant(val.to_refarg())),
+            None => error_reply(msg, UNKNOWN_PROPERTY, name),
+         },
+         Err(err) => error_reply(msg, INVALID_ARGS, &err.to_string()),
+      },
+      (PROPERTIES_INTERFACE, "GetAll") => match msg.read1::<&str>() {
+         Ok(iface) => {
+            let props = state.objects.get(&path).and_then(|obj| obj.get(iface)).map(to_prop_map).unwrap_or_default();
+            msg.method_return().append1(props)
+         }
+         Err(err) => error_reply(msg, INVALID_ARGS, &err.to_string()),
+      },
+      (PROPERTIES_INTERFACE, "Set") => match msg.read3::<&str, &str, Variant<Box<dyn RefArg>>>() {
+         Ok((iface, name, value)) => match MockValue::from_refarg(&*value.0) {
+            Some(value) => {
+               state.objects.entry(path.clone()).or_default().entry(iface.to_string()).or_default().insert(name.to_string(), value.clone());
+               let _ = conn.send(properties_changed(&path, iface, name, &value));
+               msg.method_return()
+            }
+            None => error_reply(msg, INVALID_ARGS, name),
+         },
+         Err(err) => error_reply(msg, INVALID_ARGS, &err.to_string()),
+      },
+      (OBJECT_MANAGER_INTERFACE, "GetManagedObjects") => {
+         let prefix = if path == "/" { String::from("/") } else { format!("{}/", path) };
+         let objects: HashMap<Path<'static>, HashMap<String, PropMap>> = state.objects.iter()
+            .filter(|(obj_path, _)| obj_path.starts_with(&prefix))
+            .map(|(obj_path, ifaces)| (Path::from(obj_path.clone()), ifaces.iter().map(|(iface, props)| (iface.clone(), to_prop_map(props))).collect()))
+            .collect();
+         msg.method_return().append1(objects)
+      }
+      (INTROSPECTABLE_INTERFACE, "Introspect") => {
+         let prefix = if path == "/" { String::from("/") } else { format!("{}/", path) };
+         let children: BTreeSet<&str> = state.objects.keys().filter_map(|obj_path| obj_path.strip_prefix(&prefix)?.split('/').next()).filter(|child| !child.is_empty()).collect();
+         let nodes: String = children.iter().map(|child| format!("<node name=\"{}\"/>", child)).collect();
+         msg.method_return().append1(format!("<node>{}</node>", nodes))
+      }
+      _ => {
+         if let Ok(call) = msg.duplicate() {
+            state.calls.push(call);
+         }
+         match state.handlers.get(&(interface.clone(), member.clone())) {
+            Some(handler) => match handler(msg) {
+               Ok(values) => {
+                  let mut reply = msg.method_return();
+                  {
+                     let mut iter = dbus::arg::IterAppend::new(&mut reply);
+                     values.iter().for_each(|val| val.to_refarg().append(&mut iter));
+                  }
+                  reply
+               }
+               Err(err) => error_reply(msg, &err.name, &err.message),
+            },
+            None => error_reply(msg, UNKNOWN_METHOD, &format!("{}.{} is not mocked", interface, member)),
+         }
+      }
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::{MockService, MockValue};
+   use crate::error::Error;
+   use dbus::blocking::stdintf::org_freedesktop_dbus::Properties;
+   use std::time::Duration;
+
+   #[test]
+   fn test_mock_service() -> Result<(), Error> {
+      let mock = match MockService::new("org.koompi.Test") {
+         Ok(mock) => mock,
+         Err(Error::MissingTool(_)) => return Ok(()),
+         Err(err) => return Err(err),
+      };
+      mock.add_object("/org/koompi/Test", "org.koompi.Test", vec![("Level", 3u32.into())]).reply("org.koompi.Test", "Ping", vec!["pong".into()]);
+
+      let conn = super::TestBus::shared().unwrap().connect()?;
+      let proxy = conn.with_proxy("org.koompi.Test", "/org/koompi/Test", Duration::from_secs(5));
+      let (pong,): (String,) = proxy.method_call("org.koompi.Test", "Ping", (1u8,))?;
+      assert_eq!(pong, "pong");
+      assert_eq!(proxy.get::<u32>("org.koompi.Test", "Level")?, 3);
+      proxy.set("org.koompi.Test", "Level", 4u32)?;
+      assert_eq!(mock.property("/org/koompi/Test", "org.koompi.Test", "Level"), Some(MockValue::U32(4)));
+      assert!(proxy.method_call::<(), _, _, _>("org.koompi.Test", "Unknown", ()).is_err());
+      assert_eq!(mock.calls_to("Ping").len(), 1);
+      Ok(())
+   }
+}
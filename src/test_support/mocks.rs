@@ -0,0 +1,88 @@
+use std::fs::File;
+use std::os::unix::io::IntoRawFd;
+use dbus::arg::OwnedFd;
+use crate::error::Error;
+use super::{MockError, MockService, MockValue};
+
+const BLUEZ_ADAPTER_INTERFACE: &str = "org.bluez.Adapter1";
+const NM_INTERFACE: &str = "org.freedesktop.NetworkManager";
+const LOGIN1_MANAGER_INTERFACE: &str = "org.freedesktop.login1.Manager";
+const LOGIN1_SESSION_INTERFACE: &str = "org.freedesktop.login1.Session";
+const UPOWER_INTERFACE: &str = "org.freedesktop.UPower";
+const UPOWER_DEVICE_INTERFACE: &str = "org.freedesktop.UPower.Device";
+const UPOWER_KBD_BACKLIGHT_INTERFACE: &str = "org.freedesktop.UPower.KbdBacklight";
+
+/// Presets of the system services the managers talk to, with one healthy device or session each.
+/// Tests script the deviations they need on top.
+impl MockService {
+   /// BlueZ with one powered adapter at `/org/bluez/hci0`.
+   pub fn bluez() -> Result<Self, Error> {
+      let mock = Self::new("org.bluez")?;
+      mock.add_object("/org/bluez/hci0", BLUEZ_ADAPTER_INTERFACE, vec![
+         ("Address", "00:11:22:33:44:55".into()),
+         ("AddressType", "public".into()),
+         ("Name", "koompi".into()),
+         ("Alias", "koompi".into()),
+         ("Powered", true.into()),
+         ("Discovering", false.into()),
+      ]);
+      ["StartDiscovery", "StopDiscovery", "SetDiscoveryFilter", "RemoveDevice"].iter().for_each(|method| {
+         mock.reply(BLUEZ_ADAPTER_INTERFACE, method, Vec::new());
+      });
+      Ok(mock)
+   }
+
+   /// NetworkManager, connected with full connectivity and no devices.
+   pub fn network_manager() -> Result<Self, Error> {
+      let mock = Self::new(NM_INTERFACE)?;
+      mock.add_object("/org/freedesktop/NetworkManager", NM_INTERFACE, vec![
+         ("NetworkingEnabled", true.into()),
+         ("WirelessEnabled", true.into()),
+         ("State", 70u32.into()),
+         ("Connectivity", 4u32.into()),
+      ]);
+      mock.reply(NM_INTERFACE, "GetDevices", vec![MockValue::paths(Vec::<String>::new())]).reply(NM_INTERFACE, "CheckConnectivity", vec![4u32.into()]);
+      Ok(mock)
+   }
+
+   /// logind allowing every power action and inhibitor, undocked, with the caller's session `1` at `/org/freedesktop/login1/session/auto`.
+   pub fn login1() -> Result<Self, Error> {
+      let mock = Self::new("org.freedesktop.login1")?;
+      mock.add_object("/org/freedesktop/login1", LOGIN1_MANAGER_INTERFACE, vec![("PreparingForSleep", false.into()), ("PreparingForShutdown", false.into()), ("Docked", false.into())]);
+      mock.add_object("/org/freedesktop/login1/session/auto", LOGIN1_SESSION_INTERFACE, vec![("Id", "1".into()), ("Active", true.into())]);
+      ["CanPowerOff", "CanReboot", "CanSuspend", "CanHibernate"].iter().for_each(|method| {
+         mock.reply(LOGIN1_MANAGER_INTERFACE, method, vec!["yes".into()]);
+      });
+      ["PowerOff", "Reboot", "Suspend", "Hibernate", "TerminateSession"].iter().for_each(|method| {
+         mock.reply(LOGIN1_MANAGER_INTERFACE, method, Vec::new());
+      });
+      mock.reply(LOGIN1_SESSION_INTERFACE, "SetBrightness", Vec::new());
+      // the inhibitor is released when every copy of the fd is closed, which the mock doesn't track
+      mock.on_call(LOGIN1_MANAGER_INTERFACE, "Inhibit", |_| {
+         let file = File::open("/dev/null").map_err(|err| MockError::new("org.freedesktop.DBus.Error.Failed", err.to_string()))?;
+         Ok(vec![MockValue::Fd(unsafe { OwnedFd::new(file.into_raw_fd()) })])
+      });
+      Ok(mock)
+   }
+
+   /// UPower on AC power with a fully charged battery as the display device, and a keyboard backlight at level 1 of 2.
+   pub fn upower() -> Result<Self, Error> {
+      const DISPLAY_DEVICE: &str = "/org/freedesktop/UPower/devices/DisplayDevice";
+      let mock = Self::new(UPOWER_INTERFACE)?;
+      mock.add_object("/org/freedesktop/UPower", UPOWER_INTERFACE, vec![("OnBattery", false.into()), ("LidIsPresent", false.into()), ("LidIsClosed", false.into())]);
+      mock.add_object(DISPLAY_DEVICE, UPOWER_DEVICE_INTERFACE, vec![
+         ("Type", 2u32.into()),
+         ("IsPresent", true.into()),
+         ("Percentage", 100.0.into()),
+         ("State", 4u32.into()),
+         ("TimeToEmpty", 0i64.into()),
+         ("TimeToFull", 0i64.into()),
+      ]);
+      mock.add_object("/org/freedesktop/UPower/KbdBacklight", UPOWER_KBD_BACKLIGHT_INTERFACE, Vec::new());
+      mock.reply(UPOWER_KBD_BACKLIGHT_INTERFACE, "GetBrightness", vec![1i32.into()])
+         .reply(UPOWER_KBD_BACKLIGHT_INTERFACE, "GetMaxBrightness", vec![2i32.into()])
+         .reply(UPOWER_KBD_BACKLIGHT_INTERFACE, "SetBrightness", Vec::new());
+      mock.reply(UPOWER_INTERFACE, "EnumerateDevices", vec![MockValue::paths(Vec::<String>::new())]).reply(UPOWER_INTERFACE, "GetDisplayDevice", vec![MockValue::path(DISPLAY_DEVICE)]);
+      Ok(mock)
+   }
+}
@@ -0,0 +1,104 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::helpers::Sysfs;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Structure of a fake sysfs tree in a temp dir, removed once the fixture is dropped.
+/// Devices are plain directories of attribute files, so writes by the code under test can be read back.
+#[derive(Debug)]
+pub struct SysfsFixture {
+   root: PathBuf,
+}
+
+impl SysfsFixture {
+   /// Create an empty tree, unique to this fixture.
+   pub fn new() -> std::io::Result<Self> {
+      let root = std::env::temp_dir().join(format!("libkoompi-sysfs-{}-{}", std::process::id(), NEXT_ID.fetch_add(1, Ordering::SeqCst)));
+      if root.exists() {
+         std::fs::remove_dir_all(&root)?;
+      }
+      std::fs::create_dir_all(root.join("class"))?;
+      Ok(Self { root })
+   }
+
+   pub fn root(&self) -> &Path {
+      &self.root
+   }
+
+   /// The tree as the device layer sees it.
+   pub fn sysfs(&self) -> Sysfs {
+      Sysfs::new(&self.root)
+   }
+
+   /// Add a backlight, where `kind` is its `type`: `firmware`, `platform` or `raw`.
+   pub fn backlight(&self, id: &str, kind: &str, max: u32, current: u32) -> &Self {
+      self.device("backlight", id, &[("type", kind), ("max_brightness", &max.to_string()), ("brightness", &current.to_string()), ("actual_brightness", &current.to_string())])
+   }
+
+   /// Add a power supply with arbitrary attributes, e.g. `("type", "USB")`.
+   pub fn power_supply(&self, id: &str, attrs: &[(&str, &str)]) -> &Self {
+      self.device("power_supply", id, attrs)
+   }
+
+   /// Add a battery with its charge in percent and its `status`, e.g. `Discharging`.
+   pub fn battery(&self, id: &str, capacity: u32, status: &str) -> &Self {
+      self.power_supply(id, &[("type", "Battery"), ("present", "1"), ("capacity", &capacity.to_string()), ("status", status)])
+   }
+
+   /// Add a mains adapter.
+   pub fn ac(&self, id: &str, online: bool) -> &Self {
+      self.power_supply(id, &[("type", "Mains"), ("online", if online { "1" } else { "0" })])
+   }
+
+   /// Add a LED, e.g. `input3::capslock` or `tpacpi::kbd_backlight`.
+   pub fn led(&self, id: &str, max: u32, current: u32) -> &Self {
+      self.device("leds", id, &[("max_brightness", &max.to_string()), ("brightness", &current.to_string()), ("trigger", "[none]")])
+   }
+
+   /// Add a device of any class, or overwrite attributes of an existing one.
+   pub fn device(&self, class: &str, id: &str, attrs: &[(&str, &str)]) -> &Self {
+      let dir = self.sysfs().device_dir(class, id);
+      std::fs::create_dir_all(&dir).unwrap();
+      for (attr, value) in attrs {
+         std::fs::write(dir.join(attr), format!("{}\n", value)).unwrap();
+      }
+      self
+   }
+
+   /// Read an attribute back, trimmed of the trailing newline.
+   pub fn read(&self, class: &str, id: &str, attr: &str) -> String {
+      self.sysfs().read_attribute(class, id, attr).unwrap()
+   }
+}
+
+impl Drop for SysfsFixture {
+   fn drop(&mut self) {
+      let _ = std::fs::remove_dir_all(&self.root);
+   }
+}
+
+#[cfg(test)]
+mod tests {
+   use super::SysfsFixture;
+
+   #[test]
+   fn test_sysfs_fixture() -> std::io::Result<()> {
+      let fixture = SysfsFixture::new()?;
+      fixture.backlight("intel_backlight", "raw", 937, 400).battery("BAT0", 76, "Discharging").ac("AC", false).led("input3::capslock", 1, 0);
+      let sysfs = fixture.sysfs();
+      assert_eq!(sysfs.list_devices("backlight"), vec!["intel_backlight"]);
+      assert_eq!(sysfs.list_devices("power_supply"), vec!["AC", "BAT0"]);
+      assert_eq!(sysfs.read_u32("backlight", "intel_backlight", "max_brightness").unwrap(), 937);
+      assert!(sysfs.read_u32("power_supply", "BAT0", "status").is_err());
+      assert!(sysfs.list_devices("drm").is_empty());
+
+      sysfs.write_attribute("leds", "input3::capslock", "brightness", 1).unwrap();
+      assert_eq!(fixture.read("leds", "input3::capslock", "brightness"), "1");
+
+      let root = fixture.root().to_path_buf();
+      drop(fixture);
+      assert!(!root.exists());
+      Ok(())
+   }
+}
@@ -0,0 +1,105 @@
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::Mutex;
+use dbus::blocking::SyncConnection;
+use dbus::channel::Channel;
+use crate::error::Error;
+use crate::helpers::reset_shared;
+
+/// Program used to start the private bus, unless overridden by `DBUS_DAEMON`.
+const DBUS_DAEMON: &str = "dbus-daemon";
+/// The wrapper shell kills the daemon once its stdin closes, i.e. when the test process exits,
+/// then removes the socket directory, as statics are never dropped.
+const DAEMON_WRAPPER: &str = r#""$0" --nofork --nopidfile --print-address --config-file="$1" & pid=$!; read _; kill $pid; wait $pid; rm -rf "$2""#;
+
+lazy_static::lazy_static! {
+   static ref TEST_BUS: Option<TestBus> = match TestBus::start() {
+      Ok(bus) => Some(bus),
+      Err(err) => {
+         log::warn!("private bus unavailable, skipping bus tests: {}", err);
+         None
+      }
+   };
+}
+
+/// Structure of a private `dbus-daemon` standing in for the system bus of the test process
+pub struct TestBus {
+   address: String,
+   _daemon: Mutex<(Child, ChildStdin)>,
+}
+
+impl std::fmt::Debug for TestBus {
+   fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+      f.debug_struct("TestBus").field("address", &self.address).finish()
+   }
+}
+
+impl TestBus {
+   /// Start the private bus on first use and make it the system bus of this process.
+   /// Returns `None` if `dbus-daemon` isn't installed, so tests can skip.
+   pub fn shared() -> Option<&'static TestBus> {
+      TEST_BUS.as_ref()
+   }
+
+   pub fn address(&self) -> &str {
+      &self.address
+   }
+
+   /// Open a new connection to the private bus.
+   pub fn connect(&self) -> Result<SyncConnection, Error> {
+      let mut channel = Channel::open_private(&self.address)?;
+      channel.register()?;
+      Ok(SyncConnection::from(channel))
+   }
+
+   fn start() -> Result<Self, Error> {
+      let dir = std::env::temp_dir().join(format!("libkoompi-test-bus-{}", std::process::id()));
+      std::fs::create_dir_all(&dir)?;
+      let config = dir.join("bus.conf");
+      std::fs::write(&config, bus_config(&dir))?;
+
+      let daemon = std::env::var("DBUS_DAEMON").unwrap_or_else(|_| DBUS_DAEMON.to_string());
+      let mut child = Command::new("sh")
+         .arg("-c")
+         .arg(DAEMON_WRAPPER)
+         .arg(&daemon)
+         .arg(&config)
+         .arg(&dir)
+         .stdin(Stdio::piped())
+         .stdout(Stdio::piped())
+         .stderr(Stdio::null())
+         .spawn()?;
+      let stdin = child.stdin.take().unwrap();
+      let mut address = String::new();
+      BufReader::new(child.stdout.take().unwrap()).read_line(&mut address)?;
+      let address = address.trim().to_string();
+      if address.is_empty() {
+         drop(stdin);
+         let _ = child.wait();
+         return Err(Error::MissingTool(daemon));
+      }
+
+      std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &address);
+      reset_shared();
+      Ok(Self { address, _daemon: Mutex::new((child, stdin)) })
+   }
+}
+
+fn bus_config(dir: &std::path::Path) -> String {
+   format!(
+      r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN" "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>system</type>
+  <listen>unix:dir={}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow user="*"/>
+    <allow own="*"/>
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+  </policy>
+</busconfig>
+"#,
+      dir.display()
+   )
+}